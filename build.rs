@@ -0,0 +1,31 @@
+//! Embeds build information (git commit, target, enabled features) for
+//! `lnurl-client version --verbose`.
+
+use std::process::Command;
+
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=LNURL_BUILD_COMMIT={}", commit);
+
+    let target = std::env::var("TARGET").unwrap_or_default();
+    println!("cargo:rustc-env=LNURL_BUILD_TARGET={}", target);
+    let profile = std::env::var("PROFILE").unwrap_or_default();
+    println!("cargo:rustc-env=LNURL_BUILD_PROFILE={}", profile);
+
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(k, _)| k.strip_prefix("CARGO_FEATURE_").map(|f| f.to_lowercase().replace('_', "-")))
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=LNURL_BUILD_FEATURES={}", features.join(","));
+
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-changed=build.rs");
+}
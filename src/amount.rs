@@ -0,0 +1,254 @@
+//! Amounts: parsed with their unit, shown in the `--unit`, given relative to
+//! a service's limits, and how computed amounts are rounded to msat.
+//!
+//! msat stays the unit on the wire and in the history; units only matter at
+//! the edges. `25000sat`, `0.0005btc` and `25_000_000msat` are the same
+//! amount, and a bare number is msat, as the LNURL specs count.
+//!
+//! Percentages (and other conversions) rarely land on a whole satoshi, and
+//! some services reject amounts that aren't sat-aligned. The rounding policy
+//! decides what is sent; any rounding that happened is logged.
+
+use anyhow::{anyhow, bail, Result};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Unit amounts are shown in (`--unit`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Unit {
+    Sat,
+    Msat,
+    Btc,
+}
+
+static UNIT: AtomicU8 = AtomicU8::new(Unit::Sat as u8);
+
+/// Sets the unit `show` uses.
+pub fn set_unit(unit: Unit) {
+    UNIT.store(unit as u8, Ordering::Relaxed);
+}
+
+/// `msat` in the `--unit`, e.g. `25000 sat`, `25000500 msat` or
+/// `0.00025 BTC`; sats keep their msat fraction.
+pub fn show(msat: u64) -> String {
+    match UNIT.load(Ordering::Relaxed) {
+        u if u == Unit::Msat as u8 => format!("{} msat", msat),
+        u if u == Unit::Btc as u8 => format!("{} BTC", decimal(msat, 11)),
+        _ => format!("{} sat", decimal(msat, 3)),
+    }
+}
+
+/// `msat` divided by 10^`scale`, without trailing zeros.
+fn decimal(msat: u64, scale: u32) -> String {
+    let unit = 10u64.pow(scale);
+    let fraction = format!("{:0width$}", msat % unit, width = scale as usize);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        (msat / unit).to_string()
+    } else {
+        format!("{}.{}", msat / unit, fraction)
+    }
+}
+
+/// Parses an amount into msat: `25000sat` (or `sats`), `0.0005btc`,
+/// `25000000msat`, or a bare number of msat. Underscores may group digits.
+pub fn parse_msat(s: &str) -> Result<u64> {
+    let text: String = s.trim().chars().filter(|c| *c != '_').collect::<String>().to_ascii_lowercase();
+    let (number, scale) = [("msat", 0), ("sats", 3), ("sat", 3), ("btc", 11)]
+        .iter()
+        .find_map(|(unit, scale)| text.strip_suffix(unit).map(|number| (number.trim(), *scale)))
+        .unwrap_or((text.as_str(), 0));
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    let valid = (digits(whole) || whole.is_empty() && digits(fraction)) && (fraction.is_empty() || digits(fraction));
+    if !valid {
+        bail!("Invalid amount '{}', expected e.g. 25000sat, 0.0005btc or 25000000msat (a bare number is msat)", s.trim());
+    }
+    if fraction.len() > scale as usize {
+        bail!("Amount '{}' is finer than 1 msat", s.trim());
+    }
+    let whole: u64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| anyhow!("Amount '{}' is too large", s.trim()))? };
+    let fraction: u64 = format!("{:0<width$}", fraction, width = scale as usize).parse().unwrap_or(0);
+    whole
+        .checked_mul(10u64.pow(scale))
+        .and_then(|msat| msat.checked_add(fraction))
+        .ok_or_else(|| anyhow!("Amount '{}' is too large", s.trim()))
+}
+
+/// How a computed amount is turned into msat (`--rounding`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Rounding {
+    /// Round down to a whole satoshi.
+    Floor,
+    /// Round to the nearest whole satoshi.
+    Nearest,
+    /// Keep msat precision.
+    Exact,
+}
+
+impl Rounding {
+    /// Rounds `msat` per the policy, never above `max` (the result is stepped
+    /// down a satoshi if rounding up would exceed it). Refused if no whole
+    /// satoshi fits, rather than sending 0, and if the amount overflows.
+    pub fn apply(self, msat: f64, max: u64) -> Result<u64> {
+        let sats = |sat: f64| (sat as u64).checked_mul(1000).ok_or_else(|| anyhow!("{:.0} msat is too large", msat));
+        let rounded = match self {
+            Rounding::Floor => sats((msat / 1000.0).floor())?,
+            Rounding::Nearest => sats((msat / 1000.0).round())?,
+            Rounding::Exact => msat.round() as u64,
+        };
+        let rounded = if rounded > max && self != Rounding::Exact {
+            (max / 1000) * 1000
+        } else {
+            rounded.min(max)
+        };
+        if rounded == 0 && self != Rounding::Exact {
+            bail!("{:.3} msat (at most {} msat) rounds to 0 ({}); use --rounding exact", msat, max, self.describe());
+        }
+        Ok(rounded)
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Rounding::Floor => "floor to sat",
+            Rounding::Nearest => "nearest sat",
+            Rounding::Exact => "exact msat",
+        }
+    }
+}
+
+/// Amount argument: an amount (see `parse_msat`), a percentage of the
+/// maximum the service allows, or an amount of fiat money.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmountArg {
+    Msat(u64),
+    Percent(f64),
+    /// E.g. `5usd`: converted at the current rate of `currency` (a
+    /// lowercase ISO code) when the flow runs.
+    Fiat { amount: f64, currency: String },
+}
+
+impl FromStr for AmountArg {
+    type Err = anyhow::Error;
+
+    /// Parses `25000sat`, `25000000` (msat), `50%` or `5usd`.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some(percent) = s.strip_suffix('%') {
+            let percent: f64 = percent
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("Invalid percentage '{}'", s))?;
+            if !(percent > 0.0 && percent <= 100.0) {
+                return Err(anyhow!("Percentage must be above 0 and at most 100, got {}", s));
+            }
+            return Ok(AmountArg::Percent(percent));
+        }
+        if let Some(fiat) = parse_fiat(s)? {
+            return Ok(fiat);
+        }
+        parse_msat(s).map(AmountArg::Msat)
+    }
+}
+
+/// A number followed by a three-letter currency code other than `sat` and
+/// `btc`, e.g. `5usd` or `12.50 EUR`.
+fn parse_fiat(s: &str) -> Result<Option<AmountArg>> {
+    let text = s.to_ascii_lowercase();
+    let number = text.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let currency = &text[number.len()..];
+    if currency.len() != 3 || currency == "sat" || currency == "btc" {
+        return Ok(None);
+    }
+    let amount: f64 = number
+        .trim()
+        .replace('_', "")
+        .parse()
+        .map_err(|_| anyhow!("Invalid amount '{}', expected e.g. 5usd", s))?;
+    if !(amount.is_finite() && amount > 0.0) {
+        bail!("Fiat amount must be above 0, got {}", s);
+    }
+    Ok(Some(AmountArg::Fiat { amount, currency: currency.to_string() }))
+}
+
+impl AmountArg {
+    /// The amount in msat, with percentages taken of `max` and rounded per
+    /// `rounding`. A fiat amount needs its rate: `price_per_btc`, refused if
+    /// `None`.
+    pub fn resolve(self, max: u64, rounding: Rounding, price_per_btc: Option<f64>) -> Result<u64> {
+        Ok(match self {
+            AmountArg::Msat(msat) => msat,
+            AmountArg::Fiat { amount, currency } => {
+                let price = price_per_btc.ok_or_else(|| anyhow!("No {} rate to convert {} {} with", currency.to_uppercase(), amount, currency.to_uppercase()))?;
+                let exact = amount / price * 100_000_000_000.0;
+                let msat = rounding.apply(exact, u64::MAX)?;
+                log_rounding(&format!("{} {} at {:.2} {}/BTC", amount, currency.to_uppercase(), price, currency.to_uppercase()), exact, msat, rounding);
+                msat
+            }
+            AmountArg::Percent(percent) => {
+                let exact = max as f64 * percent / 100.0;
+                let msat = rounding.apply(exact, max)?;
+                log_rounding(&format!("{}% of {} msat", percent, max), exact, msat, rounding);
+                msat
+            }
+        })
+    }
+}
+
+/// Logs the computed and the sent amount when they differ.
+fn log_rounding(what: &str, exact: f64, msat: u64, rounding: Rounding) {
+    if (exact - msat as f64).abs() >= 0.001 {
+        log::info!("{} is {:.3} msat, rounded to {} msat ({})", what, exact, msat, rounding.describe());
+    } else {
+        log::info!("{} is {} msat", what, msat);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_units() {
+        assert_eq!(parse_msat("25000sat").unwrap(), 25_000_000);
+        assert_eq!(parse_msat("25_000 SATS").unwrap(), 25_000_000);
+        assert_eq!(parse_msat("0.0005btc").unwrap(), 50_000_000);
+        assert_eq!(parse_msat(".5btc").unwrap(), 50_000_000_000);
+        assert_eq!(parse_msat("1.001sat").unwrap(), 1001);
+        assert_eq!(parse_msat("42").unwrap(), 42);
+    }
+
+    #[test]
+    fn refuses_bad_amounts() {
+        assert!(parse_msat("1.0001sat").unwrap_err().to_string().contains("finer than 1 msat"));
+        assert!(parse_msat("1.5msat").is_err());
+        assert!(parse_msat("sat").is_err());
+        assert!(parse_msat("-1sat").is_err());
+        assert!(parse_msat("18446744073709552sat").unwrap_err().to_string().contains("too large"));
+        assert!(parse_msat("99999999999999999999").unwrap_err().to_string().contains("too large"));
+    }
+
+    #[test]
+    fn rounds_within_max() {
+        assert_eq!(Rounding::Floor.apply(12_345.6, 100_000).unwrap(), 12_000);
+        assert_eq!(Rounding::Nearest.apply(12_545.6, 100_000).unwrap(), 13_000);
+        assert_eq!(Rounding::Exact.apply(12_345.6, 100_000).unwrap(), 12_346);
+        // Rounding up past the maximum steps down to the last whole satoshi.
+        assert_eq!(Rounding::Nearest.apply(99_900.0, 99_900).unwrap(), 99_000);
+        assert_eq!(Rounding::Exact.apply(99_900.4, 99_900).unwrap(), 99_900);
+    }
+
+    #[test]
+    fn refuses_rounding_to_nothing() {
+        assert!(Rounding::Floor.apply(500.0, 800).is_err());
+        assert!(Rounding::Nearest.apply(800.0, 800).is_err());
+        assert_eq!(Rounding::Exact.apply(800.0, 800).unwrap(), 800);
+    }
+
+    #[test]
+    fn refuses_overflowing_amounts() {
+        assert!(Rounding::Floor.apply(1e30, u64::MAX).is_err());
+        assert!(Rounding::Nearest.apply(f64::INFINITY, u64::MAX).is_err());
+    }
+}
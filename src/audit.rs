@@ -0,0 +1,205 @@
+//! Append-only audit log of funds-moving operations.
+//!
+//! Each entry stores the hash of the previous one, so editing or removing an
+//! entry breaks the chain for every entry after it. Entries can optionally be
+//! signed by the node (CLN `signmessage` over the entry hash). Writers hold an
+//! exclusive lock on the log from reading its last entry to appending the
+//! next one, so concurrent runs can't fork the chain.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+/// `prev_hash` of the first entry in a log.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// What happened, as reported by the flow that moved funds.
+#[derive(Debug)]
+pub struct AuditRecord {
+    pub operation: String,
+    pub url: String,
+    pub amount_msat: Option<u64>,
+    pub detail: String,
+    pub status: String,
+}
+
+/// One line of the audit log (JSON).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub timestamp: String,
+    pub operation: String,
+    pub url: String,
+    pub amount_msat: Option<u64>,
+    pub detail: String,
+    pub status: String,
+    pub prev_hash: String,
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pubkey: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// The hashed fields of an entry, in a fixed order.
+#[derive(Serialize)]
+struct Hashed<'a> {
+    seq: u64,
+    timestamp: &'a str,
+    operation: &'a str,
+    url: &'a str,
+    amount_msat: Option<u64>,
+    detail: &'a str,
+    status: &'a str,
+    prev_hash: &'a str,
+}
+
+impl AuditEntry {
+    /// Hash over every field except `hash`, the pubkey and the signature,
+    /// encoded as JSON so free-text fields can't run into each other.
+    fn compute_hash(&self) -> String {
+        let hashed = Hashed {
+            seq: self.seq,
+            timestamp: &self.timestamp,
+            operation: &self.operation,
+            url: &self.url,
+            amount_msat: self.amount_msat,
+            detail: &self.detail,
+            status: &self.status,
+            prev_hash: &self.prev_hash,
+        };
+        hex::encode(Sha256::digest(serde_json::to_vec(&hashed).expect("Hashed serializes")))
+    }
+}
+
+/// Returns the path of the audit log inside the client data directory.
+pub fn audit_log_path() -> PathBuf {
+    crate::get_data_dir().join("audit.log")
+}
+
+/// Reads all entries from the audit log (empty if the log does not exist yet).
+fn read_entries() -> Result<Vec<AuditEntry>> {
+    let path = audit_log_path();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let file = std::fs::File::open(&path)
+        .with_context(|| format!("Failed to open audit log {}", path.display()))?;
+    let mut entries = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str::<AuditEntry>(&line)
+            .with_context(|| format!("Malformed audit log entry on line {}", i + 1))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// The audit log, locked for writing until dropped.
+pub struct AuditLog {
+    file: File,
+}
+
+/// Opens the audit log for appending, waiting for other writers to finish.
+pub fn open() -> Result<AuditLog> {
+    let path = audit_log_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open audit log {}", path.display()))?;
+    file.lock().with_context(|| format!("Failed to lock audit log {}", path.display()))?;
+    Ok(AuditLog { file })
+}
+
+impl AuditLog {
+    /// Builds the next entry for `record`, chained to the last entry in the
+    /// log. The entry is not written until `append` is called, so it can be
+    /// signed first.
+    pub fn prepare(&self, record: AuditRecord) -> Result<AuditEntry> {
+        let entries = read_entries()?;
+        let (seq, prev_hash) = match entries.last() {
+            Some(last) => (last.seq + 1, last.hash.clone()),
+            None => (0, GENESIS_HASH.to_string()),
+        };
+        let mut entry = AuditEntry {
+            seq,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            operation: record.operation,
+            url: record.url,
+            amount_msat: record.amount_msat,
+            detail: record.detail,
+            status: record.status,
+            prev_hash,
+            hash: String::new(),
+            pubkey: None,
+            signature: None,
+        };
+        entry.hash = entry.compute_hash();
+        Ok(entry)
+    }
+
+    /// Appends a prepared entry to the log.
+    pub fn append(&mut self, entry: &AuditEntry) -> Result<()> {
+        writeln!(self.file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+}
+
+/// Checks that every entry's hash matches its content and links to the
+/// previous entry. Returns the entries on success.
+pub fn verify_chain() -> Result<Vec<AuditEntry>> {
+    let entries = read_entries()?;
+    let mut prev_hash = GENESIS_HASH.to_string();
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.seq != i as u64 {
+            return Err(anyhow!("Entry {} has sequence number {} (expected {})", i, entry.seq, i));
+        }
+        if entry.prev_hash != prev_hash {
+            return Err(anyhow!("Entry {} does not link to the previous entry", entry.seq));
+        }
+        if entry.compute_hash() != entry.hash {
+            return Err(anyhow!("Entry {} has been modified (hash mismatch)", entry.seq));
+        }
+        prev_hash = entry.hash.clone();
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(operation: &str, detail: &str) -> AuditEntry {
+        AuditEntry {
+            seq: 0,
+            timestamp: "2026-01-01T00:00:00+00:00".into(),
+            operation: operation.into(),
+            url: "https://service.example".into(),
+            amount_msat: Some(1000),
+            detail: detail.into(),
+            status: "OK".into(),
+            prev_hash: GENESIS_HASH.into(),
+            hash: String::new(),
+            pubkey: None,
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn hash_keeps_fields_apart() {
+        assert_ne!(entry("pay|x", "y").compute_hash(), entry("pay", "x|y").compute_hash());
+        assert_ne!(entry("pay", "a").compute_hash(), entry("pay", "b").compute_hash());
+        assert_eq!(entry("pay", "a").compute_hash(), entry("pay", "a").compute_hash());
+    }
+}
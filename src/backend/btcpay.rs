@@ -0,0 +1,103 @@
+//! A BTCPay Server store's Lightning node over the Greenfield API, so
+//! withdrawals land in the wallet BTCPay manages for the store.
+//!
+//! Calls go to `/api/v1/stores/<store>/lightning/BTC/...` with an API key
+//! (`Authorization: token <key>`) created for the store with
+//! `btcpay.store.cancreatelightninginvoice`, plus
+//! `btcpay.store.canuselightningnode` for node info and connecting to peers.
+//! Amounts are msat, as strings.
+
+use super::{InvoiceRequest, NodeBackend};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+pub struct BtcpayBackend {
+    url: String,
+    client: reqwest::Client,
+    /// `<url>/api/v1/stores/<store>/lightning/BTC`.
+    base: String,
+    api_key: String,
+}
+
+impl BtcpayBackend {
+    /// Prepares calls to the store `store` of the BTCPay instance at `url`
+    /// (e.g. `https://btcpay.example`).
+    pub fn new(url: &str, store: &str, api_key: &str) -> Result<Self> {
+        let client = reqwest::Client::builder().timeout(std::time::Duration::from_secs(60)).build()?;
+        let url = url.trim_end_matches('/').to_string();
+        Ok(Self {
+            base: format!("{}/api/v1/stores/{}/lightning/BTC", url, urlencoding::encode(store)),
+            url,
+            client,
+            api_key: api_key.to_string(),
+        })
+    }
+
+    /// Sends a request to `path` under the store's Lightning API (e.g.
+    /// `/info`), with a JSON `body` when given, and returns the JSON answer or
+    /// BTCPay's error message.
+    async fn call(&self, method: reqwest::Method, path: &str, body: Option<Value>) -> Result<Value> {
+        let mut request = self
+            .client
+            .request(method, format!("{}{}", self.base, path))
+            .header(reqwest::header::AUTHORIZATION, format!("token {}", self.api_key));
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response = request.send().await.with_context(|| format!("BTCPay {} failed", path))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(anyhow!("BTCPay rejected the API key"));
+        }
+        let text = response.text().await.with_context(|| format!("BTCPay {} failed", path))?;
+        let value: Value = if text.is_empty() { Value::Null } else { serde_json::from_str(&text).unwrap_or(Value::Null) };
+        if !status.is_success() {
+            // Either `{code, message}` or, for invalid fields, `[{path, message}]`.
+            let message = value["message"]
+                .as_str()
+                .or(value[0]["message"].as_str())
+                .unwrap_or(text.trim());
+            return Err(anyhow!("BTCPay {} failed (HTTP {}): {}", path, status.as_u16(), message));
+        }
+        Ok(value)
+    }
+}
+
+#[async_trait]
+impl NodeBackend for BtcpayBackend {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    async fn get_local_node_id(&mut self) -> Result<String> {
+        let info = self.call(reqwest::Method::GET, "/info", None).await?;
+        // The node id is only given as part of its URIs (`pubkey@host:port`).
+        info["nodeURIs"][0]
+            .as_str()
+            .and_then(|uri| uri.split('@').next())
+            .map(|id| id.to_string())
+            .ok_or_else(|| anyhow!("BTCPay node info has no nodeURIs"))
+    }
+
+    async fn connect_to_node(&mut self, node_uri: &str) -> Result<()> {
+        let (pubkey, host, port) = super::parse_node_uri(node_uri)?;
+        log::info!("Connecting to node {}@{}:{}...", pubkey, host, port);
+        let uri = format!("{}@{}", pubkey, super::host_port(&host, port));
+        self.call(reqwest::Method::POST, "/connect", Some(json!({ "nodeURI": uri }))).await?;
+        Ok(())
+    }
+
+    async fn create_invoice(&mut self, request: &InvoiceRequest) -> Result<String> {
+        let body = json!({
+            "amount": request.amount_msat.to_string(),
+            "description": request.description,
+            "expiry": request.expiry,
+        });
+        let invoice = self.call(reqwest::Method::POST, "/invoices", Some(body)).await?;
+        invoice["BOLT11"]
+            .as_str()
+            .map(|bolt11| bolt11.to_string())
+            .ok_or_else(|| anyhow!("BTCPay invoice response has no BOLT11"))
+    }
+}
@@ -0,0 +1,138 @@
+//! Core Lightning over its RPC socket (`lightning-rpc`).
+
+use super::{DecodedInvoice, InvoiceRequest, NodeBackend, Payment};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use cln_rpc::model::{requests, Response};
+use cln_rpc::{ClnRpc, Request};
+use log::info;
+use serde_json::Value;
+
+/// Reads the result of the `decode` command.
+pub(super) fn decoded_invoice(decoded: &Value) -> Result<DecodedInvoice> {
+    if decoded["valid"] == false {
+        return Err(anyhow!("Service returned an invalid invoice"));
+    }
+    Ok(DecodedInvoice {
+        amount_msat: decoded["amount_msat"].as_u64(),
+        description_hash: decoded["description_hash"].as_str().map(|h| h.to_string()),
+        payment_hash: decoded["payment_hash"]
+            .as_str()
+            .map(|h| h.to_string())
+            .ok_or_else(|| anyhow!("Decoded invoice has no payment_hash"))?,
+    })
+}
+
+/// Reads the result of the `pay` command.
+pub(super) fn payment(result: &Value) -> Result<Payment> {
+    if result["status"] != "complete" {
+        return Err(anyhow!("payment {}", result["status"].as_str().unwrap_or("failed")));
+    }
+    Ok(Payment {
+        preimage: result["payment_preimage"].as_str().map(|p| p.to_string()),
+        amount_sent_msat: result["amount_sent_msat"].as_u64(),
+    })
+}
+
+pub struct ClnBackend {
+    socket: String,
+    client: ClnRpc,
+}
+
+impl ClnBackend {
+    /// Opens the RPC socket at `socket`.
+    pub async fn connect(socket: &str) -> Result<Self> {
+        Ok(Self {
+            socket: socket.to_string(),
+            client: ClnRpc::new(socket)
+                .await
+                .with_context(|| format!("Failed to open the Core Lightning RPC socket {}", socket))?,
+        })
+    }
+}
+
+#[async_trait]
+impl NodeBackend for ClnBackend {
+    fn name(&self) -> &str {
+        &self.socket
+    }
+
+    async fn get_local_node_id(&mut self) -> Result<String> {
+        match self.client.call(Request::Getinfo(requests::GetinfoRequest {})).await {
+            Ok(Response::Getinfo(response)) => Ok(response.id.to_string()),
+            Ok(_) => Err(anyhow!("Unexpected response type from getinfo")),
+            Err(e) => Err(anyhow!("Failed to get node info: {}", e)),
+        }
+    }
+
+    async fn connect_to_node(&mut self, node_uri: &str) -> Result<()> {
+        let (pubkey, host, port) = super::parse_node_uri(node_uri)?;
+        info!("Connecting to node {}@{}:{}...", pubkey, host, port);
+        let request = requests::ConnectRequest {
+            id: pubkey,
+            host: Some(host),
+            port: Some(port),
+        };
+        self.client.call(Request::Connect(request)).await?;
+        Ok(())
+    }
+
+    async fn create_invoice(&mut self, request: &InvoiceRequest) -> Result<String> {
+        let request = requests::InvoiceRequest {
+            amount_msat: cln_rpc::primitives::AmountOrAny::Amount(cln_rpc::primitives::Amount::from_msat(
+                request.amount_msat,
+            )),
+            description: request.description.clone(),
+            label: request.label.clone(),
+            expiry: Some(request.expiry),
+            fallbacks: None,
+            preimage: None,
+            cltv: None,
+            deschashonly: None,
+            exposeprivatechannels: None,
+        };
+        match self.client.call(Request::Invoice(request)).await? {
+            Response::Invoice(r) => Ok(r.bolt11.to_string()),
+            _ => Err(anyhow!("Unexpected response type from invoice request")),
+        }
+    }
+
+    async fn decode_invoice(&mut self, bolt11: &str) -> Result<DecodedInvoice> {
+        let decoded = self
+            .client
+            .call_raw("decode", &serde_json::json!({ "string": bolt11 }))
+            .await
+            .map_err(|e| anyhow!("decode failed: {}", e))?;
+        decoded_invoice(&decoded)
+    }
+
+    async fn pay_invoice(&mut self, bolt11: &str) -> Result<Payment> {
+        let result = self
+            .client
+            .call_raw("pay", &serde_json::json!({ "bolt11": bolt11 }))
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        payment(&result)
+    }
+
+    async fn lookup_invoice(&mut self, label: &str) -> Result<Option<Value>> {
+        let response: Value = self
+            .client
+            .call_raw("listinvoices", &serde_json::json!({ "label": label }))
+            .await
+            .map_err(|e| anyhow!("listinvoices failed: {}", e))?;
+        Ok(response["invoices"].get(0).cloned())
+    }
+
+    async fn wait_invoice(&mut self, label: &str) -> Result<bool> {
+        let request = requests::WaitinvoiceRequest { label: label.to_string() };
+        match self.client.call(Request::WaitInvoice(request)).await? {
+            Response::WaitInvoice(r) => Ok(r.status == cln_rpc::model::responses::WaitinvoiceStatus::PAID),
+            _ => Err(anyhow!("Unexpected response type from waitinvoice")),
+        }
+    }
+
+    fn as_cln(&mut self) -> Option<&mut ClnRpc> {
+        Some(&mut self.client)
+    }
+}
@@ -0,0 +1,368 @@
+//! Core Lightning reached over the Lightning network itself with the
+//! `commando` plugin, so no RPC socket or port beyond the node's peer port
+//! needs to be exposed.
+//!
+//! The client connects to the node as a peer (BOLT 8 transport, BOLT 1
+//! `init`) and sends each command as a commando request (message type
+//! 0x4c4f) carrying a rune. The node answers with 0x594b messages carrying
+//! parts of the JSON reply and a final 0x594d one, and only runs commands
+//! the rune allows; create one with `lightning-cli createrune`, e.g.
+//! restricted to `method=getinfo`, `method=invoice` and `method=connect`.
+//!
+//! The client's node key is kept in the data directory (`commando_key`), so
+//! a rune can also be restricted to it with `id=<key>`; it is logged when
+//! connecting.
+
+use super::{DecodedInvoice, InvoiceRequest, NodeBackend, Payment};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
+use hmac::{Hmac, Mac};
+use secp256k1::ecdh::SharedSecret;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Message types (BOLT 1 and commando).
+const INIT: u16 = 16;
+const PING: u16 = 18;
+const PONG: u16 = 19;
+const COMMANDO_REQUEST: u16 = 0x4c4f;
+const COMMANDO_REPLY_CONTINUES: u16 = 0x594b;
+const COMMANDO_REPLY_TERM: u16 = 0x594d;
+
+/// Features sent in `init`, as optional bits: data_loss_protect (1),
+/// var_onion_optin (9), static_remotekey (13) and payment_secret (15).
+const INIT_FEATURES: [u8; 2] = [0xa2, 0x02];
+
+/// How long to wait for the connection and for each reply.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Reads the client's node key, creating a random one if there is none yet.
+fn load_or_create_key() -> Result<SecretKey> {
+    let path = crate::get_data_dir().join("commando_key");
+    if let Ok(text) = std::fs::read_to_string(&path) {
+        return SecretKey::from_str(text.trim()).with_context(|| format!("{} is not a hex secret key", path.display()));
+    }
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).map_err(|e| anyhow!("No randomness for a new key: {}", e))?;
+    let key = SecretKey::from_slice(&bytes)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    crate::keys::write_private(&path, &hex::encode(key.secret_bytes()))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(key)
+}
+
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// HKDF-SHA256 with `salt`, giving the two 32-byte outputs BOLT 8 uses.
+fn hkdf(salt: &[u8; 32], ikm: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let hmac = |key: &[u8], parts: &[&[u8]]| -> [u8; 32] {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+        for part in parts {
+            mac.update(part);
+        }
+        mac.finalize().into_bytes().into()
+    };
+    let prk = hmac(salt, &[ikm]);
+    let first = hmac(&prk, &[&[1]]);
+    let second = hmac(&prk, &[&first, &[2]]);
+    (first, second)
+}
+
+/// The 96-bit ChaCha20-Poly1305 nonce for BOLT 8 nonce `n`.
+fn nonce(n: u64) -> chacha20poly1305::Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&n.to_le_bytes());
+    nonce.into()
+}
+
+fn encrypt(key: &[u8; 32], n: u64, ad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    ChaCha20Poly1305::new(key.into())
+        .encrypt(&nonce(n), Payload { msg: plaintext, aad: ad })
+        .expect("ChaCha20-Poly1305 encryption can't fail")
+}
+
+fn decrypt(key: &[u8; 32], n: u64, ad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+    ChaCha20Poly1305::new(key.into())
+        .decrypt(&nonce(n), Payload { msg: ciphertext, aad: ad })
+        .map_err(|_| anyhow!("Failed to decrypt a message from the node"))
+}
+
+/// One direction of the transport: its key, nonce and chaining key.
+struct CipherState {
+    key: [u8; 32],
+    chaining_key: [u8; 32],
+    nonce: u64,
+}
+
+impl CipherState {
+    /// Moves to the next nonce, rotating the key after 1000 uses.
+    fn advance(&mut self) {
+        self.nonce += 1;
+        if self.nonce == 1000 {
+            (self.chaining_key, self.key) = hkdf(&self.chaining_key, &self.key);
+            self.nonce = 0;
+        }
+    }
+
+    fn encrypt(&mut self, plaintext: &[u8]) -> Vec<u8> {
+        let ciphertext = encrypt(&self.key, self.nonce, &[], plaintext);
+        self.advance();
+        ciphertext
+    }
+
+    fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let plaintext = decrypt(&self.key, self.nonce, &[], ciphertext)?;
+        self.advance();
+        Ok(plaintext)
+    }
+}
+
+/// An encrypted connection to a peer (BOLT 8, as initiator).
+struct Transport {
+    stream: TcpStream,
+    send: CipherState,
+    receive: CipherState,
+}
+
+impl Transport {
+    /// Runs the Noise_XK handshake with the node `remote` over `stream`.
+    async fn handshake(mut stream: TcpStream, local: &SecretKey, remote: &PublicKey) -> Result<Self> {
+        let secp = Secp256k1::new();
+        let mut ephemeral = [0u8; 32];
+        getrandom::getrandom(&mut ephemeral).map_err(|e| anyhow!("No randomness for the handshake: {}", e))?;
+        let ephemeral = SecretKey::from_slice(&ephemeral)?;
+        let ephemeral_pub = ephemeral.public_key(&secp).serialize();
+
+        let mut chaining_key = sha256(&[b"Noise_XK_secp256k1_ChaChaPoly_SHA256"]);
+        let mut h = sha256(&[&chaining_key, b"lightning"]);
+        h = sha256(&[&h, &remote.serialize()]);
+
+        // Act one.
+        h = sha256(&[&h, &ephemeral_pub]);
+        let temp_key;
+        (chaining_key, temp_key) = hkdf(&chaining_key, &SharedSecret::new(remote, &ephemeral).secret_bytes());
+        let c = encrypt(&temp_key, 0, &h, &[]);
+        h = sha256(&[&h, &c]);
+        stream.write_all(&[&[0u8][..], &ephemeral_pub, &c].concat()).await?;
+
+        // Act two.
+        let mut act_two = [0u8; 50];
+        stream.read_exact(&mut act_two).await.context("The node closed the connection during the handshake")?;
+        if act_two[0] != 0 {
+            return Err(anyhow!("Unsupported transport version {} from the node", act_two[0]));
+        }
+        let remote_ephemeral = PublicKey::from_slice(&act_two[1..34]).context("Invalid handshake from the node")?;
+        h = sha256(&[&h, &act_two[1..34]]);
+        let temp_key;
+        (chaining_key, temp_key) = hkdf(&chaining_key, &SharedSecret::new(&remote_ephemeral, &ephemeral).secret_bytes());
+        decrypt(&temp_key, 0, &h, &act_two[34..]).context("The node isn't the one given (handshake failed)")?;
+        h = sha256(&[&h, &act_two[34..]]);
+
+        // Act three.
+        let c = encrypt(&temp_key, 1, &h, &local.public_key(&secp).serialize());
+        h = sha256(&[&h, &c]);
+        let temp_key;
+        (chaining_key, temp_key) = hkdf(&chaining_key, &SharedSecret::new(&remote_ephemeral, local).secret_bytes());
+        let t = encrypt(&temp_key, 0, &h, &[]);
+        stream.write_all(&[&[0u8][..], &c, &t].concat()).await?;
+
+        let (send_key, receive_key) = hkdf(&chaining_key, &[]);
+        Ok(Self {
+            stream,
+            send: CipherState { key: send_key, chaining_key, nonce: 0 },
+            receive: CipherState { key: receive_key, chaining_key, nonce: 0 },
+        })
+    }
+
+    async fn write(&mut self, message: &[u8]) -> Result<()> {
+        let length = u16::try_from(message.len()).map_err(|_| anyhow!("Message too long"))?;
+        let header = self.send.encrypt(&length.to_be_bytes());
+        let body = self.send.encrypt(message);
+        self.stream.write_all(&[header, body].concat()).await?;
+        Ok(())
+    }
+
+    async fn read(&mut self) -> Result<Vec<u8>> {
+        let mut header = [0u8; 18];
+        self.stream.read_exact(&mut header).await.context("The node closed the connection")?;
+        let length = self.receive.decrypt(&header)?;
+        let length = u16::from_be_bytes([length[0], length[1]]) as usize;
+        let mut body = vec![0u8; length + 16];
+        self.stream.read_exact(&mut body).await.context("The node closed the connection")?;
+        self.receive.decrypt(&body)
+    }
+
+    /// Reads the next message other than a ping, which is answered.
+    async fn read_message(&mut self) -> Result<(u16, Vec<u8>)> {
+        loop {
+            let message = self.read().await?;
+            if message.len() < 2 {
+                continue;
+            }
+            let kind = u16::from_be_bytes([message[0], message[1]]);
+            if kind == PING && message.len() >= 4 {
+                let pong_bytes = u16::from_be_bytes([message[2], message[3]]);
+                if pong_bytes < 65532 {
+                    let mut pong = [PONG.to_be_bytes(), pong_bytes.to_be_bytes()].concat();
+                    pong.resize(4 + pong_bytes as usize, 0);
+                    self.write(&pong).await?;
+                }
+                continue;
+            }
+            return Ok((kind, message[2..].to_vec()));
+        }
+    }
+}
+
+pub struct CommandoBackend {
+    name: String,
+    transport: Transport,
+    rune: String,
+    next_id: u64,
+}
+
+impl CommandoBackend {
+    /// Connects to the node `node_id` at `address` (`host:port`) as a peer;
+    /// commands will carry `rune`. `name` is what `NodeBackend::name` reports.
+    pub async fn connect(name: &str, node_id: &str, address: &str, rune: &str) -> Result<Self> {
+        let remote = PublicKey::from_str(node_id).map_err(|e| anyhow!("Invalid commando node id {}: {}", node_id, e))?;
+        let local = load_or_create_key()?;
+        log::info!(
+            "Connecting to {}@{} for commando as {}...",
+            node_id,
+            address,
+            local.public_key(&Secp256k1::new())
+        );
+        let stream = tokio::time::timeout(TIMEOUT, TcpStream::connect(address))
+            .await
+            .map_err(|_| anyhow!("Timed out connecting to {}", address))?
+            .with_context(|| format!("Failed to connect to {}", address))?;
+        let mut transport = tokio::time::timeout(TIMEOUT, Transport::handshake(stream, &local, &remote))
+            .await
+            .map_err(|_| anyhow!("Timed out in the handshake with {}", address))??;
+
+        let mut init = INIT.to_be_bytes().to_vec();
+        init.extend_from_slice(&0u16.to_be_bytes());
+        init.extend_from_slice(&(INIT_FEATURES.len() as u16).to_be_bytes());
+        init.extend_from_slice(&INIT_FEATURES);
+        transport.write(&init).await?;
+        loop {
+            let (kind, _) = tokio::time::timeout(TIMEOUT, transport.read_message())
+                .await
+                .map_err(|_| anyhow!("No init message from {}", address))??;
+            if kind == INIT {
+                break;
+            }
+        }
+
+        let mut id = [0u8; 8];
+        getrandom::getrandom(&mut id).map_err(|e| anyhow!("No randomness for request ids: {}", e))?;
+        Ok(Self {
+            name: name.to_string(),
+            transport,
+            rune: rune.to_string(),
+            next_id: u64::from_be_bytes(id),
+        })
+    }
+
+    /// Runs `method` with `params` on the node and returns its result, or the
+    /// node's error (e.g. a rune that doesn't allow it).
+    async fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let request = json!({
+            "method": method,
+            "params": params,
+            "rune": self.rune,
+            "id": format!("lnurl-client:{}", id),
+        });
+        let mut message = COMMANDO_REQUEST.to_be_bytes().to_vec();
+        message.extend_from_slice(&id.to_be_bytes());
+        message.extend_from_slice(request.to_string().as_bytes());
+        self.transport.write(&message).await?;
+
+        let mut reply = Vec::new();
+        loop {
+            let (kind, body) = tokio::time::timeout(TIMEOUT, self.transport.read_message())
+                .await
+                .map_err(|_| anyhow!("commando {}: no reply within {}s", method, TIMEOUT.as_secs()))??;
+            if (kind != COMMANDO_REPLY_CONTINUES && kind != COMMANDO_REPLY_TERM) || body.len() < 8 || body[..8] != id.to_be_bytes() {
+                continue;
+            }
+            reply.extend_from_slice(&body[8..]);
+            if kind == COMMANDO_REPLY_TERM {
+                break;
+            }
+        }
+        let reply: Value = serde_json::from_slice(&reply).with_context(|| format!("commando {} returned no JSON", method))?;
+        if let Some(error) = reply.get("error") {
+            return Err(anyhow!(
+                "{} failed: {}",
+                method,
+                error["message"].as_str().unwrap_or("unknown error")
+            ));
+        }
+        Ok(reply["result"].clone())
+    }
+}
+
+#[async_trait]
+impl NodeBackend for CommandoBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn get_local_node_id(&mut self) -> Result<String> {
+        let info = self.call("getinfo", json!({})).await?;
+        info["id"]
+            .as_str()
+            .map(|id| id.to_string())
+            .ok_or_else(|| anyhow!("getinfo has no id"))
+    }
+
+    async fn connect_to_node(&mut self, node_uri: &str) -> Result<()> {
+        let (pubkey, host, port) = super::parse_node_uri(node_uri)?;
+        log::info!("Connecting to node {}@{}:{}...", pubkey, host, port);
+        self.call("connect", json!({ "id": pubkey, "host": host, "port": port })).await?;
+        Ok(())
+    }
+
+    async fn create_invoice(&mut self, request: &InvoiceRequest) -> Result<String> {
+        let params = json!({
+            "amount_msat": request.amount_msat,
+            "label": request.label,
+            "description": request.description,
+            "expiry": request.expiry,
+        });
+        let invoice = self.call("invoice", params).await?;
+        invoice["bolt11"]
+            .as_str()
+            .map(|bolt11| bolt11.to_string())
+            .ok_or_else(|| anyhow!("invoice result has no bolt11"))
+    }
+
+    async fn decode_invoice(&mut self, bolt11: &str) -> Result<DecodedInvoice> {
+        let decoded = self.call("decode", json!({ "string": bolt11 })).await?;
+        super::cln::decoded_invoice(&decoded)
+    }
+
+    async fn pay_invoice(&mut self, bolt11: &str) -> Result<Payment> {
+        let result = self.call("pay", json!({ "bolt11": bolt11 })).await?;
+        super::cln::payment(&result)
+    }
+}
@@ -0,0 +1,88 @@
+//! Eclair over its HTTP API (port 8080 by default).
+//!
+//! Every call is a POST with form-encoded parameters, authenticated with HTTP
+//! basic auth: an empty user and the `eclair.api.password`. Field names
+//! differ from the other nodes (`nodeId`, `amountMsat`, `expireIn`,
+//! `serialized` for the BOLT11 string).
+
+use super::{InvoiceRequest, NodeBackend};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde_json::Value;
+
+pub struct EclairBackend {
+    url: String,
+    client: reqwest::Client,
+    password: String,
+}
+
+impl EclairBackend {
+    /// Prepares calls to Eclair at `url` (e.g. `http://localhost:8080`).
+    pub fn new(url: &str, password: &str) -> Result<Self> {
+        let client = reqwest::Client::builder().timeout(std::time::Duration::from_secs(60)).build()?;
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            client,
+            password: password.to_string(),
+        })
+    }
+
+    /// POSTs `params` to the API method `method` (e.g. `getinfo`) and returns
+    /// the JSON answer or Eclair's error message.
+    async fn call(&self, method: &str, params: &[(&str, String)]) -> Result<Value> {
+        let response = self
+            .client
+            .post(format!("{}/{}", self.url, method))
+            .basic_auth("", Some(&self.password))
+            .form(params)
+            .send()
+            .await
+            .with_context(|| format!("Eclair {} failed", method))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(anyhow!("Eclair rejected the API password"));
+        }
+        let value: Value = response.json().await.with_context(|| format!("Eclair {} returned no JSON", method))?;
+        if !status.is_success() {
+            let message = value["error"].as_str().unwrap_or("unknown error");
+            return Err(anyhow!("Eclair {} failed (HTTP {}): {}", method, status.as_u16(), message));
+        }
+        Ok(value)
+    }
+}
+
+#[async_trait]
+impl NodeBackend for EclairBackend {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    async fn get_local_node_id(&mut self) -> Result<String> {
+        let info = self.call("getinfo", &[]).await?;
+        info["nodeId"]
+            .as_str()
+            .map(|id| id.to_string())
+            .ok_or_else(|| anyhow!("Eclair getinfo has no nodeId"))
+    }
+
+    async fn connect_to_node(&mut self, node_uri: &str) -> Result<()> {
+        let (pubkey, host, port) = super::parse_node_uri(node_uri)?;
+        log::info!("Connecting to node {}@{}:{}...", pubkey, host, port);
+        let params = [("nodeId", pubkey), ("host", host), ("port", port.to_string())];
+        self.call("connect", &params).await?;
+        Ok(())
+    }
+
+    async fn create_invoice(&mut self, request: &InvoiceRequest) -> Result<String> {
+        let params = [
+            ("description", request.description.clone()),
+            ("amountMsat", request.amount_msat.to_string()),
+            ("expireIn", request.expiry.to_string()),
+        ];
+        let invoice = self.call("createinvoice", &params).await?;
+        invoice["serialized"]
+            .as_str()
+            .map(|bolt11| bolt11.to_string())
+            .ok_or_else(|| anyhow!("Eclair createinvoice response has no serialized invoice"))
+    }
+}
@@ -0,0 +1,168 @@
+//! An embedded Lightning node (LDK Node), for users without a node of their
+//! own (`--backend ldk`, built with the `ldk` feature).
+//!
+//! The node keeps its keys, channels and payments in its own directory
+//! (`ldk/` in the data directory by default; back up `keys_seed`) and follows
+//! the chain through an Esplora server. It only runs while the client does:
+//! each flow starts it, and `settle` keeps it up until the channel the service
+//! opens, or the payment it sends, reached it.
+//!
+//! A new node has no channels, so it can't receive a withdrawal before a
+//! service opened one to it (`request-channel`). Inbound channels are accepted
+//! without an on-chain reserve for fee bumping, as such a node usually has no
+//! on-chain funds.
+
+use super::{InvoiceRequest, NodeBackend};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use ldk_node::bitcoin::secp256k1::PublicKey;
+use ldk_node::bitcoin::Network;
+use ldk_node::config::{AnchorChannelsConfig, Config};
+use ldk_node::lightning::ln::msgs::SocketAddress;
+use ldk_node::lightning_invoice::{Bolt11InvoiceDescription, Description};
+use ldk_node::{Event, Node};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// What a flow asked the service for, to wait for in `settle`.
+enum Pending {
+    /// Payment of the invoice with this hash (hex).
+    Payment(String),
+    /// A channel from this peer.
+    Channel(PublicKey),
+}
+
+pub struct LdkBackend {
+    node: Arc<Node>,
+    name: String,
+    pending: Vec<Pending>,
+    /// How long `settle` waits.
+    wait: Duration,
+}
+
+/// Runs `f` on a thread of its own: LDK Node drives its own runtime and
+/// can't be called from within the flow's.
+fn off_runtime<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|s| s.spawn(f).join().expect("LDK node thread panicked"))
+}
+
+impl LdkBackend {
+    /// Starts the node stored in `dir` on `network` (e.g. `testnet4`);
+    /// `name` is what `NodeBackend::name` reports.
+    pub fn start(dir: &Path, name: &str, network: &str, esplora: &str, wait: Duration) -> Result<Self> {
+        let network = Network::from_str(network).map_err(|_| anyhow!("Unknown network '{}'", network))?;
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let config = Config {
+            storage_dir_path: dir.display().to_string(),
+            network,
+            anchor_channels_config: Some(AnchorChannelsConfig {
+                trusted_peers_no_reserve: Vec::new(),
+                per_channel_reserve_sats: 0,
+            }),
+            ..Config::default()
+        };
+        let node = off_runtime(|| -> Result<Node> {
+            let mut builder = ldk_node::Builder::from_config(config);
+            builder
+                .set_chain_source_esplora(esplora.to_string(), None)
+                .set_entropy_seed_path(dir.join("keys_seed").display().to_string())
+                .set_filesystem_logger(Some(dir.join("ldk_node.log").display().to_string()), None);
+            let node = builder.build().map_err(|e| anyhow!("Failed to set up the LDK node: {}", e))?;
+            node.start().map_err(|e| anyhow!("Failed to start the LDK node: {}", e))?;
+            Ok(node)
+        })?;
+        log::info!("Started embedded LDK node {} ({})", node.node_id(), dir.display());
+        Ok(Self {
+            node: Arc::new(node),
+            name: name.to_string(),
+            pending: Vec::new(),
+            wait,
+        })
+    }
+}
+
+impl Drop for LdkBackend {
+    fn drop(&mut self) {
+        let node = Arc::clone(&self.node);
+        if let Err(e) = off_runtime(move || node.stop()) {
+            log::warn!("failed to stop the LDK node: {}", e);
+        }
+    }
+}
+
+#[async_trait]
+impl NodeBackend for LdkBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn get_local_node_id(&mut self) -> Result<String> {
+        Ok(self.node.node_id().to_string())
+    }
+
+    async fn connect_to_node(&mut self, node_uri: &str) -> Result<()> {
+        let (pubkey, host, port) = super::parse_node_uri(node_uri)?;
+        log::info!("Connecting to node {}@{}:{}...", pubkey, host, port);
+        let pubkey = PublicKey::from_str(&pubkey)?;
+        let address = SocketAddress::from_str(&super::host_port(&host, port))
+            .map_err(|_| anyhow!("Invalid node address in {}", node_uri))?;
+        let node = Arc::clone(&self.node);
+        // Remembered, so the node reconnects to the peer when it starts again.
+        off_runtime(move || node.connect(pubkey, address, true)).map_err(|e| anyhow!("Failed to connect: {}", e))?;
+        self.pending.push(Pending::Channel(pubkey));
+        Ok(())
+    }
+
+    async fn create_invoice(&mut self, request: &InvoiceRequest) -> Result<String> {
+        let description = Description::new(request.description.clone())
+            .map_err(|_| anyhow!("Invoice description too long"))?;
+        let expiry = request.expiry.try_into().context("Invoice expiry too large")?;
+        let node = Arc::clone(&self.node);
+        let amount_msat = request.amount_msat;
+        let invoice = off_runtime(move || {
+            node.bolt11_payment()
+                .receive(amount_msat, &Bolt11InvoiceDescription::Direct(description), expiry)
+        })
+        .map_err(|e| anyhow!("Failed to create invoice: {}", e))?;
+        self.pending.push(Pending::Payment(invoice.payment_hash().to_string()));
+        Ok(invoice.to_string())
+    }
+
+    async fn settle(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        log::info!("Keeping the embedded node up for up to {}s until the service's side arrives...", self.wait.as_secs());
+        let node = Arc::clone(&self.node);
+        let wait = self.wait;
+        let mut pending = std::mem::take(&mut self.pending);
+        off_runtime(move || {
+            let deadline = Instant::now() + wait;
+            while !pending.is_empty() {
+                if Instant::now() >= deadline {
+                    return Err(anyhow!("The service's channel or payment didn't reach the node within {}s", wait.as_secs()));
+                }
+                let Some(event) = node.next_event() else {
+                    std::thread::sleep(Duration::from_millis(250));
+                    continue;
+                };
+                match &event {
+                    Event::PaymentReceived { payment_hash, amount_msat, .. } => {
+                        log::info!("Received {}", crate::amount::show(*amount_msat));
+                        let hash = hex::encode(payment_hash.0);
+                        pending.retain(|p| !matches!(p, Pending::Payment(h) if *h == hash));
+                    }
+                    Event::ChannelPending { counterparty_node_id, funding_txo, .. } => {
+                        log::info!("Channel from {} is being opened ({})", counterparty_node_id, funding_txo);
+                        pending.retain(|p| !matches!(p, Pending::Channel(peer) if peer == counterparty_node_id));
+                    }
+                    _ => {}
+                }
+                node.event_handled().map_err(|e| anyhow!("LDK node event: {}", e))?;
+            }
+            Ok(())
+        })
+    }
+}
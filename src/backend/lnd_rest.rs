@@ -0,0 +1,97 @@
+//! LND over its REST API (port 8080 by default), for setups where gRPC is
+//! blocked, e.g. behind an HTTP-only proxy.
+//!
+//! Same trust and authorization as the gRPC backend: LND's `tls.cert` is the
+//! only root, and the macaroon goes hex encoded in `Grpc-Metadata-macaroon`.
+//! The REST gateway takes and returns 64-bit integers as strings.
+
+use super::{InvoiceRequest, NodeBackend};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Header the REST gateway reads the macaroon from.
+const MACAROON_HEADER: &str = "Grpc-Metadata-macaroon";
+
+pub struct LndRestBackend {
+    url: String,
+    client: reqwest::Client,
+    macaroon: String,
+}
+
+impl LndRestBackend {
+    /// Prepares calls to LND at `url` (e.g. `https://localhost:8080`),
+    /// trusting `tls_cert` and authenticating with the macaroon file `macaroon`.
+    pub fn new(url: &str, tls_cert: &Path, macaroon: &Path) -> Result<Self> {
+        let cert = std::fs::read(tls_cert).with_context(|| format!("Failed to read {}", tls_cert.display()))?;
+        let macaroon = std::fs::read(macaroon).with_context(|| format!("Failed to read {}", macaroon.display()))?;
+        let client = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(&cert).context("Invalid LND tls.cert")?)
+            .timeout(std::time::Duration::from_secs(60))
+            .build()?;
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            client,
+            macaroon: hex::encode(macaroon),
+        })
+    }
+
+    /// Sends a request to `path` (e.g. `/v1/getinfo`), with a JSON `body`
+    /// when given, and returns the JSON answer or LND's error message.
+    async fn call(&self, method: reqwest::Method, path: &str, body: Option<Value>) -> Result<Value> {
+        let mut request = self
+            .client
+            .request(method, format!("{}{}", self.url, path))
+            .header(MACAROON_HEADER, &self.macaroon);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response = request.send().await.with_context(|| format!("LND REST {} failed", path))?;
+        let status = response.status();
+        let value: Value = response.json().await.with_context(|| format!("LND REST {} returned no JSON", path))?;
+        if !status.is_success() {
+            let message = value["message"].as_str().or(value["error"].as_str()).unwrap_or("unknown error");
+            return Err(anyhow!("LND REST {} failed (HTTP {}): {}", path, status.as_u16(), message));
+        }
+        Ok(value)
+    }
+}
+
+#[async_trait]
+impl NodeBackend for LndRestBackend {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    async fn get_local_node_id(&mut self) -> Result<String> {
+        let info = self.call(reqwest::Method::GET, "/v1/getinfo", None).await?;
+        info["identity_pubkey"]
+            .as_str()
+            .map(|p| p.to_string())
+            .ok_or_else(|| anyhow!("LND getinfo has no identity_pubkey"))
+    }
+
+    async fn connect_to_node(&mut self, node_uri: &str) -> Result<()> {
+        let (pubkey, host, port) = super::parse_node_uri(node_uri)?;
+        log::info!("Connecting to node {}@{}:{}...", pubkey, host, port);
+        let body = json!({ "addr": { "pubkey": pubkey, "host": super::host_port(&host, port) }, "perm": false });
+        match self.call(reqwest::Method::POST, "/v1/peers", Some(body)).await {
+            Err(e) if e.to_string().contains("already connected") => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+
+    async fn create_invoice(&mut self, request: &InvoiceRequest) -> Result<String> {
+        let body = json!({
+            "memo": request.description,
+            "value_msat": request.amount_msat.to_string(),
+            "expiry": request.expiry.to_string(),
+        });
+        let invoice = self.call(reqwest::Method::POST, "/v1/invoices", Some(body)).await?;
+        invoice["payment_request"]
+            .as_str()
+            .map(|p| p.to_string())
+            .ok_or_else(|| anyhow!("LND invoice response has no payment_request"))
+    }
+}
@@ -0,0 +1,341 @@
+//! Lightning node backends.
+//!
+//! The LNURL flows need little from the node: its id, a connection to the
+//! service's node (channel requests) and an invoice to be paid into (withdraw
+//! requests). That is the `NodeBackend` trait, so those two flows work with any
+//! backend. Paying (pay requests) is in the trait too but only some backends
+//! can; everything else the client does with a node is Core Lightning
+//! specific for now and goes through `as_cln`.
+
+pub mod btcpay;
+pub mod cln;
+pub mod cln_grpc;
+pub mod commando;
+pub mod eclair;
+mod grpc;
+#[cfg(feature = "ldk")]
+pub mod ldk;
+pub mod lnd;
+pub mod lnd_rest;
+pub mod lndhub;
+pub mod nwc;
+pub mod phoenixd;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::{debug, trace};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Node implementations (`--backend`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Kind {
+    /// Core Lightning RPC socket (`--rpc-socket`).
+    Cln,
+    /// Core Lightning `cln-grpc` plugin over mTLS (`--grpc-url`, `--grpc-certs`).
+    ClnGrpc,
+    /// Core Lightning over the Lightning network with a commando rune (`--commando`, `--commando-addr`).
+    Commando,
+    /// LND gRPC (`--lnd-rpc`, `--tls-cert`, `--macaroon`).
+    Lnd,
+    /// LND REST (`--lnd-rest`, `--tls-cert`, `--macaroon`).
+    LndRest,
+    /// Eclair HTTP API (`--eclair-url`, `--eclair-password`).
+    Eclair,
+    /// phoenixd HTTP API (`--phoenixd-url`, `--phoenixd-password`).
+    Phoenixd,
+    /// Nostr Wallet Connect (`--nwc`).
+    Nwc,
+    /// BTCPay Server Greenfield API (`--btcpay-url`, `--btcpay-store`, `--btcpay-api-key`).
+    Btcpay,
+    /// LNDhub account (`--lndhub`, or `--lndhub-url` and `--lndhub-login`).
+    Lndhub,
+    /// Embedded LDK node (`--ldk-dir`, `--ldk-network`, `--esplora`).
+    #[cfg(feature = "ldk")]
+    Ldk,
+}
+
+/// A node to open, with what is needed to reach it.
+#[derive(Debug, Clone)]
+pub enum Config {
+    Cln { socket: String },
+    ClnGrpc { url: String, certs: PathBuf },
+    Commando { node_id: String, address: String, rune: String, name: String },
+    Lnd { url: String, tls_cert: PathBuf, macaroon: PathBuf },
+    LndRest { url: String, tls_cert: PathBuf, macaroon: PathBuf },
+    Eclair { url: String, password: String },
+    Phoenixd { url: String, password: String },
+    Nwc(nwc::Connection),
+    Btcpay { url: String, store: String, api_key: String },
+    Lndhub { url: String, login: String, password: String },
+    #[cfg(feature = "ldk")]
+    Ldk {
+        dir: PathBuf,
+        name: String,
+        network: String,
+        esplora: String,
+        /// How long `settle` keeps the node up.
+        wait: std::time::Duration,
+    },
+}
+
+impl Config {
+    /// Where the node is reached, as `NodeBackend::name` will report it.
+    pub fn name(&self) -> &str {
+        match self {
+            Config::Cln { socket } => socket,
+            Config::Commando { name, .. } => name,
+            Config::ClnGrpc { url, .. }
+            | Config::Lnd { url, .. }
+            | Config::LndRest { url, .. }
+            | Config::Eclair { url, .. }
+            | Config::Phoenixd { url, .. }
+            | Config::Btcpay { url, .. }
+            | Config::Lndhub { url, .. } => url,
+            Config::Nwc(connection) => &connection.name,
+            #[cfg(feature = "ldk")]
+            Config::Ldk { name, .. } => name,
+        }
+    }
+
+    /// Connects to the node. Its calls are logged at debug level.
+    pub async fn open(&self) -> Result<Box<dyn NodeBackend>> {
+        let started = Instant::now();
+        let node: Box<dyn NodeBackend> = match self {
+            Config::Cln { socket } => Box::new(cln::ClnBackend::connect(socket).await?),
+            Config::ClnGrpc { url, certs } => Box::new(cln_grpc::ClnGrpcBackend::connect(url, certs).await?),
+            Config::Commando { node_id, address, rune, name } => {
+                Box::new(commando::CommandoBackend::connect(name, node_id, address, rune).await?)
+            }
+            Config::Lnd { url, tls_cert, macaroon } => Box::new(lnd::LndBackend::connect(url, tls_cert, macaroon).await?),
+            Config::LndRest { url, tls_cert, macaroon } => Box::new(lnd_rest::LndRestBackend::new(url, tls_cert, macaroon)?),
+            Config::Eclair { url, password } => Box::new(eclair::EclairBackend::new(url, password)?),
+            Config::Phoenixd { url, password } => Box::new(phoenixd::PhoenixdBackend::new(url, password)?),
+            Config::Nwc(connection) => Box::new(nwc::NwcBackend::connect(connection).await?),
+            Config::Btcpay { url, store, api_key } => Box::new(btcpay::BtcpayBackend::new(url, store, api_key)?),
+            Config::Lndhub { url, login, password } => Box::new(lndhub::LndhubBackend::login(url, login, password).await?),
+            #[cfg(feature = "ldk")]
+            Config::Ldk { dir, name, network, esplora, wait } => {
+                Box::new(ldk::LdkBackend::start(dir, name, network, esplora, *wait)?)
+            }
+        };
+        debug!("node {}: connected in {} ms", node.name(), started.elapsed().as_millis());
+        Ok(Box::new(Logged(node)))
+    }
+}
+
+/// Invoice for the node to create.
+#[derive(Debug, Clone)]
+pub struct InvoiceRequest {
+    pub amount_msat: u64,
+    pub description: String,
+    /// Unique name the invoice can be looked up by later.
+    pub label: String,
+    /// Seconds the invoice stays payable.
+    pub expiry: u64,
+}
+
+/// What a pay request checks in the invoice a service returned.
+#[derive(Debug, Clone)]
+pub struct DecodedInvoice {
+    pub amount_msat: Option<u64>,
+    /// SHA-256 the invoice commits to instead of a description (hex).
+    pub description_hash: Option<String>,
+    pub payment_hash: String,
+}
+
+/// A completed payment.
+#[derive(Debug, Clone, Default)]
+pub struct Payment {
+    /// Hex, when the node reports it.
+    pub preimage: Option<String>,
+    /// Amount sent including fees, when the node reports it.
+    pub amount_sent_msat: Option<u64>,
+}
+
+/// Decodes `bolt11` without a node.
+pub fn decode_bolt11(bolt11: &str) -> Result<DecodedInvoice> {
+    use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescriptionRef};
+    let invoice = Bolt11Invoice::from_str(bolt11).map_err(|e| anyhow!("Invalid invoice: {}", e))?;
+    let description_hash = match invoice.description() {
+        Bolt11InvoiceDescriptionRef::Hash(hash) => Some(hash.0.to_string()),
+        Bolt11InvoiceDescriptionRef::Direct(_) => None,
+    };
+    Ok(DecodedInvoice {
+        amount_msat: invoice.amount_milli_satoshis(),
+        description_hash,
+        payment_hash: invoice.payment_hash().to_string(),
+    })
+}
+
+#[async_trait]
+pub trait NodeBackend: Send {
+    /// Where the node is reached, for logs and records (e.g. the socket path).
+    fn name(&self) -> &str;
+
+    /// The node's pubkey (hex).
+    async fn get_local_node_id(&mut self) -> Result<String>;
+
+    /// Connects to a remote node given as `pubkey@host:port`.
+    async fn connect_to_node(&mut self, node_uri: &str) -> Result<()>;
+
+    /// Creates an invoice and returns it as BOLT11.
+    async fn create_invoice(&mut self, request: &InvoiceRequest) -> Result<String>;
+
+    /// Decodes a BOLT11 invoice; locally, unless the node does it.
+    async fn decode_invoice(&mut self, bolt11: &str) -> Result<DecodedInvoice> {
+        decode_bolt11(bolt11)
+    }
+
+    /// Pays a BOLT11 invoice, returning once the payment completed.
+    async fn pay_invoice(&mut self, _bolt11: &str) -> Result<Payment> {
+        Err(anyhow!("Backend {} can't pay invoices", self.name()))
+    }
+
+    /// The node's record of the invoice `label` made by `create_invoice`
+    /// (status, paid_at, payment_preimage, ...), as it reports it; `None` if
+    /// it has none.
+    async fn lookup_invoice(&mut self, _label: &str) -> Result<Option<serde_json::Value>> {
+        Err(anyhow!("Backend {} can't look up invoices", self.name()))
+    }
+
+    /// Waits until the invoice `label` made by `create_invoice` is paid
+    /// (`true`) or expires unpaid (`false`).
+    async fn wait_invoice(&mut self, _label: &str) -> Result<bool> {
+        Err(anyhow!("Backend {} can't wait for invoice payments", self.name()))
+    }
+
+    /// Waits until what the service was asked for (a channel, a payment of
+    /// an invoice) reached the node. Only nodes that stop with the client
+    /// need to; the others return at once.
+    async fn settle(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// The Core Lightning RPC client behind this backend, if there is one.
+    fn as_cln(&mut self) -> Option<&mut cln_rpc::ClnRpc> {
+        None
+    }
+}
+
+/// A backend whose calls are logged with their duration, each in a `node`
+/// span naming the backend and the call.
+struct Logged(Box<dyn NodeBackend>);
+
+impl Logged {
+    fn done<T>(started: Instant, result: &Result<T>) {
+        let ms = started.elapsed().as_millis();
+        match result {
+            Ok(_) => debug!("done in {} ms", ms),
+            Err(e) => debug!("failed after {} ms: {:#}", ms, e),
+        }
+    }
+}
+
+#[async_trait]
+impl NodeBackend for Logged {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    #[tracing::instrument(name = "node", skip_all, fields(backend = %self.0.name(), call = "get node id"))]
+    async fn get_local_node_id(&mut self) -> Result<String> {
+        let started = Instant::now();
+        let result = self.0.get_local_node_id().await;
+        Self::done(started, &result);
+        result
+    }
+
+    #[tracing::instrument(name = "node", skip_all, fields(backend = %self.0.name(), call = "connect"))]
+    async fn connect_to_node(&mut self, node_uri: &str) -> Result<()> {
+        debug!("connecting to {}", node_uri);
+        let _spinner = crate::progress::spinner(format!("Connecting to {}", node_uri));
+        let started = Instant::now();
+        let result = self.0.connect_to_node(node_uri).await;
+        Self::done(started, &result);
+        result
+    }
+
+    #[tracing::instrument(name = "node", skip_all, fields(backend = %self.0.name(), call = "create invoice"))]
+    async fn create_invoice(&mut self, request: &InvoiceRequest) -> Result<String> {
+        trace!("invoice request {:?}", request);
+        let started = Instant::now();
+        let result = self.0.create_invoice(request).await;
+        Self::done(started, &result);
+        if let Ok(bolt11) = &result {
+            trace!("invoice {}", bolt11);
+        }
+        result
+    }
+
+    #[tracing::instrument(name = "node", skip_all, fields(backend = %self.0.name(), call = "decode invoice"))]
+    async fn decode_invoice(&mut self, bolt11: &str) -> Result<DecodedInvoice> {
+        let started = Instant::now();
+        let result = self.0.decode_invoice(bolt11).await;
+        Self::done(started, &result);
+        result
+    }
+
+    #[tracing::instrument(name = "node", skip_all, fields(backend = %self.0.name(), call = "pay invoice"))]
+    async fn pay_invoice(&mut self, bolt11: &str) -> Result<Payment> {
+        let _spinner = crate::progress::spinner("Paying the invoice");
+        let started = Instant::now();
+        let result = self.0.pay_invoice(bolt11).await;
+        Self::done(started, &result);
+        result
+    }
+
+    #[tracing::instrument(name = "node", skip_all, fields(backend = %self.0.name(), call = "look up invoice"))]
+    async fn lookup_invoice(&mut self, label: &str) -> Result<Option<serde_json::Value>> {
+        let started = Instant::now();
+        let result = self.0.lookup_invoice(label).await;
+        Self::done(started, &result);
+        result
+    }
+
+    #[tracing::instrument(name = "node", skip_all, fields(backend = %self.0.name(), call = "wait invoice"))]
+    async fn wait_invoice(&mut self, label: &str) -> Result<bool> {
+        let _spinner = crate::progress::spinner(format!("Waiting for invoice {} to be paid", label));
+        let started = Instant::now();
+        let result = self.0.wait_invoice(label).await;
+        Self::done(started, &result);
+        result
+    }
+
+    #[tracing::instrument(name = "node", skip_all, fields(backend = %self.0.name(), call = "settle"))]
+    async fn settle(&mut self) -> Result<()> {
+        let _spinner = crate::progress::spinner(format!("Waiting for {}", self.0.name()));
+        let started = Instant::now();
+        let result = self.0.settle().await;
+        Self::done(started, &result);
+        result
+    }
+
+    fn as_cln(&mut self) -> Option<&mut cln_rpc::ClnRpc> {
+        self.0.as_cln()
+    }
+}
+
+/// Splits a node URI `pubkey@host:port` into its parts, checking the pubkey.
+pub fn parse_node_uri(node_uri: &str) -> Result<(String, String, u16)> {
+    let (pubkey, address) = node_uri
+        .split_once('@')
+        .ok_or_else(|| anyhow!("Invalid node URI: {}", node_uri))?;
+    let pubkey = secp256k1::PublicKey::from_str(pubkey).map_err(|e| anyhow!("Invalid node URI {}: {}", node_uri, e))?;
+    let (host, port) = address
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("Node URI {} has no port", node_uri))?;
+    let port = port.parse().map_err(|_| anyhow!("Invalid port in node URI {}", node_uri))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Ok((pubkey.to_string(), host.to_string(), port))
+}
+
+/// `host:port`, with IPv6 addresses in brackets.
+pub fn host_port(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
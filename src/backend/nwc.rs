@@ -0,0 +1,284 @@
+//! A remote wallet over Nostr Wallet Connect (NIP-47), e.g. Alby or Mutiny.
+//!
+//! The wallet hands out a connection string
+//! `nostr+walletconnect://<wallet pubkey>?relay=<wss url>&secret=<hex>`. The
+//! secret is a key of the client's own for this connection: requests are
+//! Nostr events (kind 23194) signed with it, their content NIP-04 encrypted
+//! to the wallet, and the wallet answers with a kind 23195 event referring to
+//! the request. Whatever the connection was granted (`make_invoice`,
+//! `pay_invoice`, `get_info`) is enforced by the wallet.
+//!
+//! A wallet has no peers of its own to manage, so channel requests aren't
+//! supported.
+
+use super::{InvoiceRequest, NodeBackend, Payment};
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use base64::Engine;
+use futures_util::{SinkExt, StreamExt};
+use secp256k1::{schnorr, Keypair, Message, Parity, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite;
+
+/// Request and response event kinds.
+const REQUEST_KIND: u64 = 23194;
+const RESPONSE_KIND: u64 = 23195;
+
+/// How long to wait for the wallet's answer; a payment can take a while.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+type Socket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// A parsed `nostr+walletconnect://` connection string.
+#[derive(Debug, Clone)]
+pub struct Connection {
+    wallet: XOnlyPublicKey,
+    relay: String,
+    secret: SecretKey,
+    /// `nwc:<wallet pubkey>`, for logs and records; the string itself holds the secret.
+    pub name: String,
+}
+
+impl FromStr for Connection {
+    type Err = anyhow::Error;
+
+    fn from_str(uri: &str) -> Result<Self> {
+        let url = url::Url::parse(uri).map_err(|_| anyhow!("Invalid NWC connection string"))?;
+        if url.scheme() != "nostr+walletconnect" && url.scheme() != "nostrwalletconnect" {
+            return Err(anyhow!("NWC connection string must start with nostr+walletconnect://"));
+        }
+        let wallet = url.host_str().unwrap_or_else(|| url.path());
+        let wallet = XOnlyPublicKey::from_str(wallet).map_err(|_| anyhow!("Invalid wallet pubkey in NWC connection string"))?;
+        let param = |name: &str| url.query_pairs().find(|(k, _)| k == name).map(|(_, v)| v.to_string());
+        let relay = param("relay").ok_or_else(|| anyhow!("NWC connection string has no relay"))?;
+        let secret = param("secret").ok_or_else(|| anyhow!("NWC connection string has no secret"))?;
+        let secret = SecretKey::from_str(&secret).map_err(|_| anyhow!("Invalid secret in NWC connection string"))?;
+        Ok(Self {
+            name: format!("nwc:{}", wallet),
+            wallet,
+            relay,
+            secret,
+        })
+    }
+}
+
+pub struct NwcBackend {
+    connection: Connection,
+    keys: Keypair,
+    socket: Socket,
+    /// NIP-04 key shared with the wallet.
+    shared_key: [u8; 32],
+}
+
+impl NwcBackend {
+    /// Connects to the connection's relay.
+    pub async fn connect(connection: &Connection) -> Result<Self> {
+        let (socket, _) = tokio::time::timeout(REPLY_TIMEOUT, tokio_tungstenite::connect_async(&connection.relay))
+            .await
+            .map_err(|_| anyhow!("Timed out connecting to relay {}", connection.relay))?
+            .with_context(|| format!("Failed to connect to relay {}", connection.relay))?;
+        let wallet = PublicKey::from_x_only_public_key(connection.wallet, Parity::Even);
+        let point = secp256k1::ecdh::shared_secret_point(&wallet, &connection.secret);
+        let mut shared_key = [0u8; 32];
+        shared_key.copy_from_slice(&point[..32]);
+        Ok(Self {
+            keys: Keypair::from_secret_key(&Secp256k1::new(), &connection.secret),
+            connection: connection.clone(),
+            socket,
+            shared_key,
+        })
+    }
+
+    /// Sends the NIP-47 request `method` and returns its `result`, or the
+    /// wallet's error.
+    async fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let content = encrypt(&self.shared_key, &json!({ "method": method, "params": params }).to_string())?;
+        let tags = json!([["p", self.connection.wallet.to_string()]]);
+        let event = sign_event(&self.keys, REQUEST_KIND, tags, content)?;
+        let id = event["id"].as_str().unwrap_or_default().to_string();
+        let subscription = id[..16].to_string();
+        let filter = json!({ "kinds": [RESPONSE_KIND], "authors": [self.connection.wallet.to_string()], "#e": [id] });
+        self.send(json!(["REQ", subscription, filter])).await?;
+        self.send(json!(["EVENT", event])).await?;
+
+        let response = tokio::time::timeout(REPLY_TIMEOUT, self.response(&subscription, &id))
+            .await
+            .map_err(|_| anyhow!("NWC {}: no answer from the wallet within {}s", method, REPLY_TIMEOUT.as_secs()))??;
+        // Best effort: the relay drops the subscription with the connection anyway.
+        let _ = self.send(json!(["CLOSE", subscription])).await;
+
+        let response: Value = serde_json::from_str(&decrypt(&self.shared_key, &response)?)
+            .with_context(|| format!("NWC {} returned no JSON", method))?;
+        if let Some(error) = response["error"].as_object() {
+            return Err(anyhow!(
+                "NWC {} failed: {} ({})",
+                method,
+                error.get("message").and_then(|m| m.as_str()).unwrap_or("unknown error"),
+                error.get("code").and_then(|c| c.as_str()).unwrap_or("no code")
+            ));
+        }
+        Ok(response["result"].clone())
+    }
+
+    async fn send(&mut self, message: Value) -> Result<()> {
+        self.socket
+            .send(tungstenite::Message::text(message.to_string()))
+            .await
+            .with_context(|| format!("Failed to send to relay {}", self.connection.relay))
+    }
+
+    /// Reads relay messages until the wallet's answer to request `id` comes
+    /// in on `subscription`, and returns its (encrypted) content.
+    async fn response(&mut self, subscription: &str, id: &str) -> Result<String> {
+        while let Some(message) = self.socket.next().await {
+            let text = match message.with_context(|| format!("Relay {} failed", self.connection.relay))? {
+                tungstenite::Message::Text(text) => text,
+                tungstenite::Message::Close(_) => break,
+                _ => continue,
+            };
+            let message: Value = serde_json::from_str(text.as_str()).unwrap_or_default();
+            match message[0].as_str() {
+                Some("EVENT") if message[1] == subscription => {
+                    let event = &message[2];
+                    if let Err(e) = verify_event(event, &self.connection.wallet) {
+                        log::warn!("ignoring NWC event: {:#}", e);
+                        continue;
+                    }
+                    return Ok(event["content"].as_str().unwrap_or_default().to_string());
+                }
+                Some("OK") if message[1] == id && message[2] == false => {
+                    return Err(anyhow!("Relay refused the request: {}", message[3].as_str().unwrap_or("")));
+                }
+                Some("CLOSED") if message[1] == subscription => {
+                    return Err(anyhow!("Relay closed the subscription: {}", message[2].as_str().unwrap_or("")));
+                }
+                Some("NOTICE") => log::debug!("relay notice: {}", message[1]),
+                _ => {}
+            }
+        }
+        Err(anyhow!("Relay {} closed the connection", self.connection.relay))
+    }
+}
+
+/// The event id: SHA-256 of the serialized event (NIP-01).
+fn event_id(pubkey: &str, created_at: u64, kind: u64, tags: &Value, content: &str) -> [u8; 32] {
+    let serialized = json!([0, pubkey, created_at, kind, tags, content]).to_string();
+    Sha256::digest(serialized.as_bytes()).into()
+}
+
+fn sign_event(keys: &Keypair, kind: u64, tags: Value, content: String) -> Result<Value> {
+    let pubkey = keys.x_only_public_key().0.to_string();
+    let created_at = chrono::Utc::now().timestamp() as u64;
+    let id = event_id(&pubkey, created_at, kind, &tags, &content);
+    let mut aux = [0u8; 32];
+    getrandom::getrandom(&mut aux).map_err(|e| anyhow!("No randomness for signing: {}", e))?;
+    let sig = Secp256k1::new().sign_schnorr_with_aux_rand(&Message::from_digest(id), keys, &aux);
+    Ok(json!({
+        "id": hex::encode(id),
+        "pubkey": pubkey,
+        "created_at": created_at,
+        "kind": kind,
+        "tags": tags,
+        "content": content,
+        "sig": sig.to_string(),
+    }))
+}
+
+/// Checks that `event` is a response signed by `wallet`.
+fn verify_event(event: &Value, wallet: &XOnlyPublicKey) -> Result<()> {
+    if event["pubkey"].as_str() != Some(wallet.to_string().as_str()) || event["kind"].as_u64() != Some(RESPONSE_KIND) {
+        return Err(anyhow!("not a response from the wallet"));
+    }
+    let id = event_id(
+        event["pubkey"].as_str().unwrap_or_default(),
+        event["created_at"].as_u64().ok_or_else(|| anyhow!("no created_at"))?,
+        RESPONSE_KIND,
+        &event["tags"],
+        event["content"].as_str().ok_or_else(|| anyhow!("no content"))?,
+    );
+    if event["id"].as_str() != Some(hex::encode(id).as_str()) {
+        return Err(anyhow!("id doesn't match the event"));
+    }
+    let sig = schnorr::Signature::from_str(event["sig"].as_str().unwrap_or_default())?;
+    Secp256k1::verification_only()
+        .verify_schnorr(&sig, &Message::from_digest(id), wallet)
+        .map_err(|_| anyhow!("invalid signature"))
+}
+
+/// NIP-04: AES-256-CBC, as `<base64 ciphertext>?iv=<base64 iv>`.
+fn encrypt(key: &[u8; 32], plaintext: &str) -> Result<String> {
+    let mut iv = [0u8; 16];
+    getrandom::getrandom(&mut iv).map_err(|e| anyhow!("No randomness for encryption: {}", e))?;
+    let len = plaintext.len();
+    let mut buffer = plaintext.as_bytes().to_vec();
+    buffer.resize(len + 16 - len % 16, 0);
+    let ciphertext = cbc::Encryptor::<aes::Aes256>::new(key.into(), &iv.into())
+        .encrypt_padded_mut::<Pkcs7>(&mut buffer, len)
+        .map_err(|_| anyhow!("Failed to encrypt the NWC request"))?;
+    let b64 = base64::engine::general_purpose::STANDARD;
+    Ok(format!("{}?iv={}", b64.encode(ciphertext), b64.encode(iv)))
+}
+
+fn decrypt(key: &[u8; 32], content: &str) -> Result<String> {
+    let (ciphertext, iv) = content
+        .split_once("?iv=")
+        .ok_or_else(|| anyhow!("NWC response isn't NIP-04 encrypted"))?;
+    let b64 = base64::engine::general_purpose::STANDARD;
+    let mut buffer = b64.decode(ciphertext).context("Invalid NWC response")?;
+    let iv: [u8; 16] = b64
+        .decode(iv)
+        .ok()
+        .and_then(|iv| iv.try_into().ok())
+        .ok_or_else(|| anyhow!("Invalid NWC response IV"))?;
+    let plaintext = cbc::Decryptor::<aes::Aes256>::new(key.into(), &iv.into())
+        .decrypt_padded_mut::<Pkcs7>(&mut buffer)
+        .map_err(|_| anyhow!("Failed to decrypt the NWC response"))?;
+    String::from_utf8(plaintext.to_vec()).context("NWC response isn't UTF-8")
+}
+
+#[async_trait]
+impl NodeBackend for NwcBackend {
+    fn name(&self) -> &str {
+        &self.connection.name
+    }
+
+    async fn get_local_node_id(&mut self) -> Result<String> {
+        let info = self.call("get_info", json!({})).await?;
+        info["pubkey"]
+            .as_str()
+            .filter(|p| !p.is_empty())
+            .map(|p| p.to_string())
+            .ok_or_else(|| anyhow!("NWC wallet doesn't disclose a node pubkey"))
+    }
+
+    async fn connect_to_node(&mut self, _node_uri: &str) -> Result<()> {
+        Err(anyhow!("An NWC wallet can't connect to other nodes or take channels from them"))
+    }
+
+    async fn create_invoice(&mut self, request: &InvoiceRequest) -> Result<String> {
+        let params = json!({
+            "amount": request.amount_msat,
+            "description": request.description,
+            "expiry": request.expiry,
+        });
+        let invoice = self.call("make_invoice", params).await?;
+        invoice["invoice"]
+            .as_str()
+            .map(|bolt11| bolt11.to_string())
+            .ok_or_else(|| anyhow!("NWC make_invoice result has no invoice"))
+    }
+
+    async fn pay_invoice(&mut self, bolt11: &str) -> Result<Payment> {
+        let paid = self.call("pay_invoice", json!({ "invoice": bolt11 })).await?;
+        let amount = super::decode_bolt11(bolt11).ok().and_then(|d| d.amount_msat);
+        Ok(Payment {
+            preimage: paid["preimage"].as_str().map(|p| p.to_string()),
+            // Fees are optional in the result.
+            amount_sent_msat: amount.zip(paid["fees_paid"].as_u64()).map(|(a, f)| a + f),
+        })
+    }
+}
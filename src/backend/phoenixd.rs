@@ -0,0 +1,88 @@
+//! ACINQ's phoenixd over its HTTP API (port 9740 by default).
+//!
+//! Calls use HTTP basic auth with an empty user and the `http-password` from
+//! phoenixd's `phoenix.conf`. phoenixd gets its channels from ACINQ's LSP
+//! on demand (the liquidity fee is taken from the first payments), so it
+//! can't connect to other nodes or take a channel from an LNURL service, and
+//! it only invoices whole satoshis.
+
+use super::{InvoiceRequest, NodeBackend};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde_json::Value;
+
+pub struct PhoenixdBackend {
+    url: String,
+    client: reqwest::Client,
+    password: String,
+}
+
+impl PhoenixdBackend {
+    /// Prepares calls to phoenixd at `url` (e.g. `http://localhost:9740`).
+    pub fn new(url: &str, password: &str) -> Result<Self> {
+        let client = reqwest::Client::builder().timeout(std::time::Duration::from_secs(60)).build()?;
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            client,
+            password: password.to_string(),
+        })
+    }
+
+    /// Sends `request` for the API method `method` and returns the JSON
+    /// answer or phoenixd's error message.
+    async fn send(&self, method: &str, request: reqwest::RequestBuilder) -> Result<Value> {
+        let response = request
+            .basic_auth("", Some(&self.password))
+            .send()
+            .await
+            .with_context(|| format!("phoenixd {} failed", method))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(anyhow!("phoenixd rejected the HTTP password"));
+        }
+        // Errors come back as plain text.
+        let body = response.text().await.with_context(|| format!("phoenixd {} failed", method))?;
+        if !status.is_success() {
+            return Err(anyhow!("phoenixd {} failed (HTTP {}): {}", method, status.as_u16(), body.trim()));
+        }
+        serde_json::from_str(&body).with_context(|| format!("phoenixd {} returned no JSON", method))
+    }
+}
+
+#[async_trait]
+impl NodeBackend for PhoenixdBackend {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    async fn get_local_node_id(&mut self) -> Result<String> {
+        let request = self.client.get(format!("{}/getinfo", self.url));
+        let info = self.send("getinfo", request).await?;
+        info["nodeId"]
+            .as_str()
+            .map(|id| id.to_string())
+            .ok_or_else(|| anyhow!("phoenixd getinfo has no nodeId"))
+    }
+
+    async fn connect_to_node(&mut self, _node_uri: &str) -> Result<()> {
+        Err(anyhow!("phoenixd only connects to its LSP and can't take a channel from another node"))
+    }
+
+    async fn create_invoice(&mut self, request: &InvoiceRequest) -> Result<String> {
+        if !request.amount_msat.is_multiple_of(1000) {
+            return Err(anyhow!("phoenixd only invoices whole satoshis, not {} msat", request.amount_msat));
+        }
+        let params = [
+            ("description", request.description.clone()),
+            ("amountSat", (request.amount_msat / 1000).to_string()),
+            ("expirySeconds", request.expiry.to_string()),
+            ("externalId", request.label.clone()),
+        ];
+        let request = self.client.post(format!("{}/createinvoice", self.url)).form(&params);
+        let invoice = self.send("createinvoice", request).await?;
+        invoice["serialized"]
+            .as_str()
+            .map(|bolt11| bolt11.to_string())
+            .ok_or_else(|| anyhow!("phoenixd createinvoice response has no serialized invoice"))
+    }
+}
@@ -0,0 +1,5 @@
+//! Generates the Kotlin and Swift sources for the `uniffi` bindings.
+
+fn main() {
+    uniffi::uniffi_bindgen_main()
+}
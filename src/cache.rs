@@ -0,0 +1,66 @@
+//! Revalidating cache of payRequest answers (`cache.json` in the data
+//! directory).
+//!
+//! A Lightning Address answers every payment with the same payRequest,
+//! metadata included, and payroll or subscription runs pay the same addresses
+//! run after run. An answer that came with an `ETag` or `Last-Modified`
+//! header is kept with it; the next request for the URL, in this run or a
+//! later one, sends it back (`If-None-Match`, `If-Modified-Since`) and a
+//! `304 Not Modified` is served the stored body. Answers without either
+//! header aren't kept, since there would be no telling when they change.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Answers kept; the least recently fetched are dropped first.
+const MAX_ENTRIES: usize = 200;
+
+/// A stored answer and the validators it came with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// When the answer was last fetched or confirmed (RFC 3339).
+    pub fetched_at: String,
+    pub body: String,
+}
+
+fn cache_path() -> std::path::PathBuf {
+    crate::get_data_dir().join("cache.json")
+}
+
+fn load() -> Vec<CachedResponse> {
+    std::fs::read_to_string(cache_path())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn save(entries: &[CachedResponse]) -> Result<()> {
+    let path = cache_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, serde_json::to_string(entries)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// The stored answer for `url`, if any.
+pub fn get(url: &str) -> Option<CachedResponse> {
+    load().into_iter().find(|entry| entry.url == url)
+}
+
+/// Stores `entry`, replacing the one for the same URL.
+pub fn put(entry: CachedResponse) {
+    let mut entries = load();
+    entries.retain(|e| e.url != entry.url);
+    entries.push(entry);
+    if entries.len() > MAX_ENTRIES {
+        entries.sort_by(|a, b| b.fetched_at.cmp(&a.fetched_at));
+        entries.truncate(MAX_ENTRIES);
+    }
+    if let Err(e) = save(&entries) {
+        log::warn!("failed to update the cache: {:#}", e);
+    }
+}
@@ -0,0 +1,2972 @@
+//! `LnurlClient` and the flows it runs: the LNURL requests, the node calls
+//! and the records they leave in the history and audit log.
+
+use crate::{amount, audit, backend, cache, history, k1, keys, lnurl, monitor, net, paylinks, progress, rates, signed, tofu, warnings};
+use crate::{parse_target, LnurlError};
+use serde::{Deserialize, Serialize};
+use cln_rpc::ClnRpc;
+use url::Url;
+use anyhow::{Context, Result, anyhow};
+use clap::Args;
+use log::{debug, info, trace, warn};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use sha2::Digest;
+
+// -----------------------------------------------------------------------------
+// Configuration
+// -----------------------------------------------------------------------------
+
+/// Default HTTP timeout for requests to LNURL servers (connect + read).
+const HTTP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Redirects followed per request, as many as an HTTP agent follows by default.
+const MAX_REDIRECTS: usize = 5;
+
+/// Returns the default path to the Core Lightning RPC socket, when none is
+/// given (`--rpc-socket`): `CLN_RPC_PATH` if set, otherwise a default path for testnet4.
+pub fn get_cln_rpc_path() -> String {
+    std::env::var("CLN_RPC_PATH")
+        .unwrap_or_else(|_| "/home/ugo/.lightning/testnet4/lightning-rpc".to_string())
+}
+
+/// Node sockets to try, in order, when the primary node can't create a
+/// withdraw invoice: `CLN_RPC_FALLBACK` (comma-separated).
+fn get_cln_rpc_fallbacks() -> Vec<String> {
+    let fallback = std::env::var("CLN_RPC_FALLBACK").unwrap_or_default();
+    fallback
+        .split(',')
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .map(|p| p.to_string())
+        .collect()
+}
+
+/// Returns the directory where the client keeps its own state (audit log).
+/// Uses `LNURL_CLIENT_DIR` if set, otherwise `~/.lnurl-client`.
+pub fn get_data_dir() -> PathBuf {
+    if let Ok(dir) = std::env::var("LNURL_CLIENT_DIR") {
+        return PathBuf::from(dir);
+    }
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    PathBuf::from(home).join(".lnurl-client")
+}
+
+/// Whether audit log entries should be signed by the node (`LNURL_AUDIT_SIGN=1`).
+fn audit_sign_enabled() -> bool {
+    matches!(std::env::var("LNURL_AUDIT_SIGN").as_deref(), Ok("1") | Ok("true"))
+}
+
+/// Whether `url` points at this machine (plain HTTP is fine there).
+fn is_local_host(url: &Url) -> bool {
+    match url.host() {
+        Some(url::Host::Domain(d)) => d.eq_ignore_ascii_case("localhost"),
+        Some(url::Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(url::Host::Ipv6(ip)) => ip.is_loopback(),
+        None => false,
+    }
+}
+
+/// Returns the `body` of a 2xx answer from `url`, otherwise fails.
+fn check_status(url: &str, status: u16, body: String) -> Result<String> {
+    if !(200..300).contains(&status) {
+        return Err(LnurlError::http(Some(status), format!("{}: HTTP {}: {}", url, status, body)).into());
+    }
+    Ok(body)
+}
+
+/// Parses the JSON `body` from `url`. A body the type doesn't fit that is
+/// an LNURL `ERROR` answer is the service's refusal.
+fn parse_json<T: serde::de::DeserializeOwned>(url: &str, body: &str) -> Result<T> {
+    serde_json::from_str(body).map_err(|e| {
+        let value: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        if value["status"] == "ERROR" {
+            let reason = value["reason"].as_str().unwrap_or("Unknown error");
+            LnurlError::rejected(format!("{} refused: {}", url, reason), reason).into()
+        } else {
+            LnurlError::json(url, e).into()
+        }
+    })
+}
+
+// -----------------------------------------------------------------------------
+// Payment options
+// -----------------------------------------------------------------------------
+
+/// What to send along with a payment (`pay`, `open`).
+#[derive(Debug, Clone, Args, Serialize)]
+pub struct PayArgs {
+    /// Comment sent with the payment, if the service accepts one (LUD-12).
+    #[arg(long)]
+    pub comment: Option<String>,
+
+    /// Name to disclose if the service asks for payer data (LUD-18).
+    #[arg(long, value_name = "name")]
+    pub payer_name: Option<String>,
+
+    /// Email address to disclose if the service asks for it.
+    #[arg(long, value_name = "email")]
+    pub payer_email: Option<String>,
+
+    /// Identifier (e.g. a lightning address) to disclose if the service asks for it.
+    #[arg(long, value_name = "id")]
+    pub payer_identifier: Option<String>,
+
+    /// Send this service's LNURL-auth linking key as payer pubkey.
+    #[arg(long)]
+    pub payer_pubkey: bool,
+
+    /// Sign the service's payerData k1 with its LNURL-auth linking key.
+    #[arg(long)]
+    pub payer_auth: bool,
+
+    /// How long to poll the service's verify URL (LUD-21) for settlement;
+    /// 0 to skip.
+    #[arg(long, default_value_t = 60, value_name = "secs")]
+    pub verify_timeout: u64,
+}
+
+impl Default for PayArgs {
+    /// The CLI defaults: nothing disclosed, verified for up to a minute.
+    fn default() -> Self {
+        Self {
+            comment: None,
+            payer_name: None,
+            payer_email: None,
+            payer_identifier: None,
+            payer_pubkey: false,
+            payer_auth: false,
+            verify_timeout: 60,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Client
+// -----------------------------------------------------------------------------
+
+/// The LNURL flows with the node, network and policy settings they share.
+/// Configure one with [`LnurlClient::builder`], then call the flows; each logs its progress, records the operation in the
+/// history and audit log like the CLI does and returns what it did. Failures are [`LnurlError`]s.
+pub struct LnurlClient {
+    /// HTTP agent for everything but the flows' requests (rates, updates,
+    /// monitoring). It follows redirects, unlike the default `http`.
+    pub agent: ureq::Agent,
+    /// Makes the flows' requests to LNURL services; an agent from
+    /// `net::build_agent` by default.
+    pub http: Box<dyn net::HttpTransport>,
+    pub annotations: history::Annotations,
+    /// Exchange rate for showing fiat equivalents (`--fiat`).
+    pub fiat: Option<rates::Rate>,
+    /// Where rates come from, for `rate` and fiat amounts (`5usd`).
+    pub rates: Option<rates::RateProvider>,
+    pub withdraw: WithdrawPolicy,
+    pub retry: RetryPolicy,
+    /// How long to wait for opened channels to confirm (`--wait-confirm`).
+    pub confirm_timeout: Option<Duration>,
+    /// Ask for unannounced channels (`--private`).
+    pub private_channels: bool,
+    /// Source of LNURL-auth linking keys (`--auth-key`).
+    pub auth_key: keys::KeySource,
+    /// Ask on the terminal before each withdraw callback, channel open and
+    /// payment (the CLI unless `--yes`).
+    pub prompt: bool,
+    /// Stop each flow before its callback with `LnurlError::DryRun`
+    /// (`--dry-run`).
+    pub dry_run: bool,
+    /// Node the flows use (`--backend`).
+    pub node: backend::Config,
+    pub net: net::NetConfig,
+    /// Timeout of each request to an LNURL service (connect + read).
+    pub timeout: Duration,
+    /// Keep payRequest answers across runs and revalidate them instead of
+    /// fetching them in full (unless `--no-cache`; see `cache`).
+    pub cache: bool,
+    /// HTTP exchanges since the last recorded operation.
+    exchanges: RefCell<Vec<history::Exchange>>,
+}
+
+/// Invoice settings for withdrawals (`--invoice-expiry`, `--expiry-retries`).
+pub struct WithdrawPolicy {
+    /// Expiry of the first invoice, in seconds; doubled on each retry.
+    pub invoice_expiry: u64,
+    /// How often to retry with a fresh invoice when the service reports ours expired.
+    pub expiry_retries: u32,
+    /// Rounding of computed amounts (`--rounding`).
+    pub rounding: amount::Rounding,
+    /// Sent as `balanceNotify` to services with a balanceCheck (LUD-15).
+    pub balance_notify: Option<Url>,
+}
+
+impl Default for WithdrawPolicy {
+    /// The CLI defaults: one-hour invoices, two retries, rounding down.
+    fn default() -> Self {
+        Self {
+            invoice_expiry: 3600,
+            expiry_retries: 2,
+            rounding: amount::Rounding::Floor,
+            balance_notify: None,
+        }
+    }
+}
+
+/// Retries of the requests that are safe to repeat (`--retries`,
+/// `--retry-delay`) after a connection failure, HTTP 429 or 5xx: fetching a
+/// service's parameters, balance checks, verify URLs and auth challenges.
+/// Callbacks are never retried, since the first one may have gone through.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts after the first one; 0 to fail at once.
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each next one, plus up to
+    /// half of it at random so that clients don't retry in step.
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// The CLI defaults: two retries, after 0.5s and 1s.
+    fn default() -> Self {
+        Self { retries: 2, delay: Duration::from_millis(500) }
+    }
+}
+
+impl RetryPolicy {
+    /// The wait before retry `attempt` (0 for the first).
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self.delay.saturating_mul(2u32.saturating_pow(attempt));
+        let mut random = [0u8; 2];
+        let jitter = match getrandom::getrandom(&mut random) {
+            Ok(()) => u16::from_le_bytes(random) as f64 / u16::MAX as f64 / 2.0,
+            Err(_) => 0.0,
+        };
+        delay + delay.mul_f64(jitter)
+    }
+}
+
+/// Settings for an [`LnurlClient`], set once and applied by `build`.
+/// Anything not set keeps the CLI default.
+pub struct LnurlClientBuilder {
+    node: backend::Config,
+    net: net::NetConfig,
+    timeout: Duration,
+    connect_timeout: Option<Duration>,
+    proxy: Option<String>,
+    user_agent: Option<String>,
+    http: Option<Box<dyn net::HttpTransport>>,
+    annotations: history::Annotations,
+    withdraw: WithdrawPolicy,
+    retry: RetryPolicy,
+    confirm_timeout: Option<Duration>,
+    private_channels: bool,
+    auth_key: keys::KeySource,
+    prompt: bool,
+    dry_run: bool,
+    cache: bool,
+    rates: Option<rates::RateProvider>,
+}
+
+impl LnurlClientBuilder {
+    /// Network policy: address family, resolver, callback rules, TLS.
+    pub fn net(mut self, net: net::NetConfig) -> Self {
+        self.net = net;
+        self
+    }
+
+    /// Timeout of each request to an LNURL service (default 60s).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Time to connect to a server, within the request `timeout`.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sends all HTTP requests through this proxy (e.g. `http://proxy:3128`,
+    /// or `socks5h://127.0.0.1:9050` for Tor).
+    pub fn proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    /// `User-Agent` header of all HTTP requests.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Makes the flows' requests with `http` instead of the agent built from
+    /// the settings above.
+    pub fn http(mut self, http: impl net::HttpTransport + 'static) -> Self {
+        self.http = Some(Box::new(http));
+        self
+    }
+
+    /// Tags and note recorded with each operation.
+    pub fn annotations(mut self, annotations: history::Annotations) -> Self {
+        self.annotations = annotations;
+        self
+    }
+
+    /// Invoice expiry, retries and rounding for withdrawals.
+    pub fn withdraw(mut self, withdraw: WithdrawPolicy) -> Self {
+        self.withdraw = withdraw;
+        self
+    }
+
+    /// Retries of the requests that are safe to repeat.
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Waits up to `timeout` for opened channels to confirm.
+    pub fn confirm_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.confirm_timeout = timeout;
+        self
+    }
+
+    /// Asks for unannounced channels.
+    pub fn private_channels(mut self, private: bool) -> Self {
+        self.private_channels = private;
+        self
+    }
+
+    /// Where LNURL-auth linking keys come from.
+    pub fn auth_key(mut self, source: keys::KeySource) -> Self {
+        self.auth_key = source;
+        self
+    }
+
+    /// Source of exchange rates; without one, fiat amounts are refused.
+    pub fn rates(mut self, provider: rates::RateProvider) -> Self {
+        self.rates = Some(provider);
+        self
+    }
+
+    /// Ask for confirmation before funds move (default: don't).
+    pub fn prompt(mut self, prompt: bool) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    /// Fetch and check everything, create the invoice, but call no callback.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Keep and revalidate payRequest answers (default: do).
+    pub fn cache(mut self, cache: bool) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Builds the HTTP agent and the client.
+    pub fn build(self) -> Result<LnurlClient, LnurlError> {
+        let mut net = self.net;
+        if self.connect_timeout.is_some() {
+            net.connect_timeout = self.connect_timeout;
+        }
+        if self.proxy.is_some() {
+            net.proxy = self.proxy;
+        }
+        if self.user_agent.is_some() {
+            net.user_agent = self.user_agent;
+        }
+        let http = match self.http {
+            Some(http) => http,
+            None => Box::new(net::build_agent(&net)?),
+        };
+        Ok(LnurlClient {
+            http,
+            agent: net::build_redirecting_agent(&net)?,
+            annotations: self.annotations,
+            fiat: None,
+            rates: self.rates,
+            withdraw: self.withdraw,
+            retry: self.retry,
+            confirm_timeout: self.confirm_timeout,
+            private_channels: self.private_channels,
+            auth_key: self.auth_key,
+            prompt: self.prompt,
+            dry_run: self.dry_run,
+            cache: self.cache,
+            node: self.node,
+            net,
+            timeout: self.timeout,
+            exchanges: RefCell::new(Vec::new()),
+        })
+    }
+}
+
+impl LnurlClient {
+    /// Starts configuring a client for `node`.
+    pub fn builder(node: backend::Config) -> LnurlClientBuilder {
+        LnurlClientBuilder {
+            node,
+            net: net::NetConfig::default(),
+            timeout: HTTP_TIMEOUT,
+            connect_timeout: None,
+            proxy: None,
+            user_agent: None,
+            http: None,
+            annotations: history::Annotations::default(),
+            withdraw: WithdrawPolicy::default(),
+            retry: RetryPolicy::default(),
+            confirm_timeout: None,
+            private_channels: false,
+            auth_key: keys::KeySource::Seed,
+            prompt: false,
+            dry_run: false,
+            cache: true,
+            rates: None,
+        }
+    }
+
+    /// Requests an inbound channel (LUD-02) and has the service open it.
+    pub fn channel_request(&self, url: &lnurl::Target) -> Result<ChannelOutcome, LnurlError> {
+        channel_request(self, url).map_err(LnurlError::from)
+    }
+
+    /// Withdraws a pending channel request.
+    pub fn channel_cancel(&self, url: &lnurl::Target) -> Result<(), LnurlError> {
+        channel_cancel(self, url).map_err(LnurlError::from)
+    }
+
+    /// Compares the channel offers of `urls` and requests the one at `pick`,
+    /// asking on stdin when it's `None`; `None` if none was picked.
+    pub fn channel_shop(&self, urls: &[lnurl::Target], pick: Option<usize>) -> Result<Option<ChannelOutcome>, LnurlError> {
+        channel_shop(self, urls, pick).map_err(LnurlError::from)
+    }
+
+    /// Withdraws `amount` (LUD-03) to an invoice created on the node.
+    pub fn withdraw_request(&self, url: &lnurl::Target, amount: amount::AmountArg, description: Option<String>) -> Result<Withdrawal, LnurlError> {
+        withdraw_request(self, url, amount, description).map_err(LnurlError::from)
+    }
+
+    /// Withdraws to `bolt11`, an invoice created elsewhere.
+    pub fn withdraw_with_invoice(&self, url: &lnurl::Target, bolt11: &str) -> Result<Withdrawal, LnurlError> {
+        withdraw_with_invoice(self, url, bolt11).map_err(LnurlError::from)
+    }
+
+    /// Withdraws `target` msat in total from several vouchers.
+    pub fn withdraw_combine(&self, target: u64, urls: &[lnurl::Target], description: Option<String>) -> Result<(), LnurlError> {
+        withdraw_combine(self, target, urls, description).map_err(LnurlError::from)
+    }
+
+    /// Withdraws repeatedly until the link's balance or `target` msat is
+    /// exhausted, at most `max_withdrawals` times.
+    pub fn withdraw_drain(
+        &self,
+        url: &lnurl::Target,
+        target: Option<u64>,
+        max_withdrawals: u32,
+        settle_timeout: Duration,
+    ) -> Result<(), LnurlError> {
+        withdraw_drain(self, url, target, max_withdrawals, settle_timeout).map_err(LnurlError::from)
+    }
+
+    /// Gets the balance left at each service a withdrawal recorded a
+    /// balanceCheck URL for (LUD-14), or at `url` only.
+    pub fn balance_check(&self, url: Option<&lnurl::Target>) -> Result<Vec<Balance>, LnurlError> {
+        balance_check(self, url).map_err(LnurlError::from)
+    }
+
+    /// Pays `amount` to a pay link or lightning address (LUD-06/16) with the node.
+    pub fn pay_request(&self, url: &lnurl::Target, amount: amount::AmountArg, extra: &PayArgs) -> Result<Payment, LnurlError> {
+        pay_request(self, url, amount, extra).map_err(LnurlError::from)
+    }
+
+    /// Signs a server's `/auth-challenge` with the node and sends the
+    /// signature to its `/auth-response`.
+    pub fn auth_request(&self, url: &lnurl::Target) -> Result<(), LnurlError> {
+        auth_request(self, url).map_err(LnurlError::from)
+    }
+
+    /// Logs in with a `tag=login` URL (LUD-04), signing with the domain's
+    /// linking key.
+    pub fn login(&self, url: &lnurl::Target) -> Result<(), LnurlError> {
+        login(self, url).map_err(LnurlError::from)
+    }
+
+    /// Runs whichever flow the LNURL behind `url` is for.
+    pub fn open(&self, url: &lnurl::Target, amount: Option<amount::AmountArg>, description: Option<String>, extra: &PayArgs) -> Result<Opened, LnurlError> {
+        open_target(self, url, amount, description, extra).map_err(LnurlError::from)
+    }
+
+    /// Bundles everything known about the recorded operation `id`, the
+    /// node's record of its invoice included, and writes it to `output`.
+    pub fn history_proof(&self, id: &str, output: Option<&std::path::Path>) -> Result<serde_json::Value, LnurlError> {
+        history_proof(self, id, output).map_err(LnurlError::from)
+    }
+
+    /// Gets the BTC price in `currency` from the rate source.
+    pub fn show_rate(&self, currency: &str) -> Result<rates::Rate, LnurlError> {
+        show_rate(self, currency).map_err(LnurlError::from)
+    }
+
+    /// Checks the endpoints `targets` (and those in `targets_file`) every
+    /// `interval` seconds, `rounds` times or until stopped, without calling
+    /// any callback.
+    pub fn monitor(
+        &self,
+        targets: Vec<Url>,
+        targets_file: Option<PathBuf>,
+        interval: u64,
+        rounds: Option<u64>,
+        metrics_file: Option<PathBuf>,
+        notify_url: Option<String>,
+    ) -> Result<(), LnurlError> {
+        monitor(self, targets, targets_file, interval, rounds, metrics_file, notify_url).map_err(LnurlError::from)
+    }
+}
+
+impl LnurlClient {
+    /// GETs `url`, returning the status and body of any HTTP answer (errors
+    /// are transport failures only), after following redirects. Each hop is
+    /// checked like a callback first, and every exchange is kept for the
+    /// history.
+    fn http_get(&self, url: &str) -> Result<(u16, String)> {
+        self.http_request(url, &[]).map(|response| (response.status, response.body))
+    }
+
+    /// `http_get` sending `headers` along, returning the whole answer.
+    fn http_request(&self, url: &str, headers: &[(&str, &str)]) -> Result<net::HttpResponse> {
+        let mut url = url.to_string();
+        for _ in 0..=MAX_REDIRECTS {
+            let response = self.http_get_once(&url, headers)?;
+            let status = response.status;
+            let Some(location) = response.location.clone().filter(|_| matches!(status, 301 | 302 | 303 | 307 | 308)) else {
+                return Ok(response);
+            };
+            let current = Url::parse(&url).with_context(|| format!("Invalid URL '{}'", url))?;
+            let next = current
+                .join(&location)
+                .map_err(|_| LnurlError::invalid(format!("{} redirects to an invalid URL '{}'", url, location)))?;
+            debug!("HTTP {}: {} redirects to {}", status, url, next);
+            self.check_callback(&current, next.as_str())?;
+            url = next.into();
+        }
+        Err(LnurlError::invalid(format!("More than {} redirects, last to {}", MAX_REDIRECTS, url)).into())
+    }
+
+    /// One GET of `http_request`, redirects included. Runs in an `http` span,
+    /// so messages logged meanwhile (the transport's too) carry the URL.
+    #[tracing::instrument(name = "http", skip_all, fields(url = %url))]
+    fn http_get_once(&self, url: &str, headers: &[(&str, &str)]) -> Result<net::HttpResponse> {
+        let mut exchange = history::Exchange {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            url: url.to_string(),
+            status: None,
+            body: String::new(),
+            signature: None,
+            error: None,
+        };
+        debug!("GET");
+        let host = Url::parse(url).ok().and_then(|u| u.host_str().map(|h| h.to_string())).unwrap_or_default();
+        let _spinner = progress::spinner(format!("Waiting for {}", host));
+        let started = std::time::Instant::now();
+        // A proxy hides the callback host from the resolver's check.
+        let result: Result<net::HttpResponse> = match self
+            .net
+            .check_proxied(url)
+            .and_then(|()| self.http.get(url, self.timeout, headers))
+        {
+            Ok(response) => {
+                debug!("HTTP {} in {} ms, {} bytes", response.status, started.elapsed().as_millis(), response.body.len());
+                trace!("response body: {}", response.body);
+                exchange.signature = response.signature.clone();
+                Ok(response)
+            }
+            Err(e) => {
+                debug!("GET failed after {} ms: {:#}", started.elapsed().as_millis(), e);
+                match e.downcast::<LnurlError>() {
+                    Ok(e) => Err(e.into()),
+                    Err(e) => Err(LnurlError::http(None, format!("{:#}", e)).into()),
+                }
+            }
+        };
+        match &result {
+            Ok(response) => {
+                exchange.status = Some(response.status);
+                exchange.body = response.body.clone();
+            }
+            Err(e) => exchange.error = Some(e.to_string()),
+        }
+        let verified = match &result {
+            Ok(response) => signed::verify(
+                &self.net.pinned_keys,
+                &host,
+                response.body.as_bytes(),
+                exchange.signature.as_deref(),
+            ),
+            Err(_) => Ok(()),
+        };
+        if let Err(e) = &verified {
+            exchange.error = Some(e.to_string());
+        }
+        self.exchanges.borrow_mut().push(exchange);
+        verified?;
+        result
+    }
+
+    /// GETs `url` and returns the body, failing on non-2xx answers.
+    fn get_text(&self, url: &str) -> Result<String> {
+        let (status, body) = self.http_get(url)?;
+        check_status(url, status, body)
+    }
+
+    /// GETs `url` and parses the JSON body (see `parse_json`).
+    fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let body = self.get_text(url)?;
+        parse_json(url, &body)
+    }
+
+    /// `get_text` for a request that is safe to repeat (see `RetryPolicy`).
+    fn fetch_text(&self, url: &str) -> Result<String> {
+        self.retrying(|| self.get_text(url))
+    }
+
+    /// `get_json` for a request that is safe to repeat (see `RetryPolicy`).
+    /// With `cache` set, a payRequest answer is kept with its validators and
+    /// revalidated next time (see `cache`). Other first steps aren't kept: a
+    /// channelRequest's `k1` is single-use, like a withdrawRequest's. Hosts
+    /// with a pinned key always send their answer in full, to be verified.
+    fn fetch_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let pinned = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|host| signed::is_pinned(&self.net.pinned_keys, host)))
+            .unwrap_or(false);
+        if !self.cache || pinned {
+            return self.retrying(|| self.get_json(url));
+        }
+        let cached = cache::get(url);
+        let mut headers = Vec::new();
+        if let Some(entry) = &cached {
+            if let Some(etag) = &entry.etag {
+                headers.push(("If-None-Match", etag.as_str()));
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                headers.push(("If-Modified-Since", last_modified.as_str()));
+            }
+        }
+        let response = self.retrying(|| self.http_request(url, &headers))?;
+        if let (304, Some(mut entry)) = (response.status, cached) {
+            debug!("{} not modified, from the cache", url);
+            // The history keeps the payRequest, metadata included, as the proof.
+            if let Some(exchange) = self.exchanges.borrow_mut().last_mut() {
+                exchange.body = entry.body.clone();
+            }
+            let parsed = parse_json(url, &entry.body);
+            entry.fetched_at = chrono::Utc::now().to_rfc3339();
+            cache::put(entry);
+            return parsed;
+        }
+        let net::HttpResponse { status, etag, last_modified, body, .. } = response;
+        let body = check_status(url, status, body)?;
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap_or_default();
+        if value["tag"] == "payRequest" && (etag.is_some() || last_modified.is_some()) {
+            cache::put(cache::CachedResponse {
+                url: url.to_string(),
+                etag,
+                last_modified,
+                fetched_at: chrono::Utc::now().to_rfc3339(),
+                body: body.clone(),
+            });
+        }
+        parse_json(url, &body)
+    }
+
+    fn retrying<T>(&self, mut request: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut attempt = 0;
+        loop {
+            match request() {
+                Err(e)
+                    if attempt < self.retry.retries
+                        && e.downcast_ref::<LnurlError>().is_some_and(|e| e.is_retryable()) =>
+                {
+                    let delay = self.retry.backoff(attempt);
+                    attempt += 1;
+                    warn!("{:#}; retrying in {} ms ({}/{})", e, delay.as_millis(), attempt, self.retry.retries);
+                    std::thread::sleep(delay);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Marks the host of a URL the user gave as trusted, so it may be on the
+    /// local network (see `net::TrustedHosts`).
+    fn trust(&self, url: &Url) {
+        if let Some(host) = url.host_str() {
+            self.net.trusted.add(host.trim_start_matches('[').trim_end_matches(']'));
+        }
+    }
+
+    /// Refuses a callback (or balanceCheck URL) from a service response that
+    /// isn't http(s), or that points at another host on a port other than 80,
+    /// 443 or a `--callback-port`. Other hosts are also subject to the
+    /// `--same-origin` policy unless they are delegate domains. Internal
+    /// addresses are refused by the resolver when the request is made (by
+    /// `NetConfig::check_proxied` behind a proxy).
+    fn check_callback(&self, origin: &Url, callback: &str) -> Result<()> {
+        let target = Url::parse(callback).with_context(|| format!("Invalid callback URL '{}'", callback))?;
+        if !matches!(target.scheme(), "http" | "https") {
+            return Err(LnurlError::invalid(format!("Refusing callback with scheme '{}': {}", target.scheme(), callback)).into());
+        }
+        if target.scheme() == "http" && !is_local_host(&target) {
+            // Plain HTTP after an HTTPS LNURL is a downgrade of the funds-moving step.
+            let severity = if origin.scheme() == "https" {
+                warnings::Severity::High
+            } else {
+                warnings::Severity::Low
+            };
+            warnings::raise("http-callback", severity, format!("callback is not encrypted: {}", callback))?;
+        }
+        if target.host_str() == origin.host_str() {
+            return Ok(());
+        }
+        let target_host = target.host_str().unwrap_or("");
+        if !self.net.is_delegate(target_host) {
+            let origin_host = origin.host_str().unwrap_or("");
+            match self.net.same_origin {
+                net::SameOrigin::Off => {}
+                net::SameOrigin::Warn => warnings::raise(
+                    "foreign-callback",
+                    warnings::Severity::Medium,
+                    format!("callback goes to {} instead of {}: {}", target_host, origin_host, callback),
+                )?,
+                net::SameOrigin::Strict => {
+                    return Err(LnurlError::invalid(format!(
+                        "Refusing callback to {}, which is not {} (--same-origin strict; allow it with --delegate-domain {})",
+                        target_host,
+                        origin_host,
+                        target_host
+                    ))
+                    .into())
+                }
+            }
+        }
+        let port = target.port_or_known_default().unwrap_or(0);
+        if port != 80 && port != 443 && !self.net.callback_ports.contains(&port) {
+            return Err(LnurlError::invalid(format!(
+                "Refusing callback to unexpected port {}: {} (allow it with --callback-port {})",
+                port,
+                callback,
+                port
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Warns about a malformed, weak or reused k1 from the service at `url`,
+    /// sent in `response` (reuse in an identical response is less suspicious).
+    fn check_k1(&self, url: &Url, k1: &str, response: &impl Serialize) -> Result<()> {
+        let domain = url.host_str().unwrap_or("");
+        let response = serde_json::to_string(response)?;
+        for (code, severity, issue) in k1::check(domain, k1, &response) {
+            warnings::raise(code, severity, format!("{}: {}", domain, issue))?;
+        }
+        Ok(())
+    }
+
+    /// Warns when a service response's `tag` isn't the one the flow expects.
+    fn check_tag(&self, url: &Url, tag: &str, expected: &str) -> Result<()> {
+        if tag == expected {
+            return Ok(());
+        }
+        warnings::raise(
+            "unexpected-tag",
+            warnings::Severity::Medium,
+            format!("{} answered with tag '{}', expected '{}'", url, tag, expected),
+        )
+    }
+
+    /// Shows `summary` and asks whether to go on to `step`, if the client
+    /// prompts. Declining, or having no terminal to ask on, stops the flow.
+    fn confirm(&self, step: &str, summary: &[(&str, String)]) -> Result<()> {
+        if !self.prompt || self.dry_run {
+            return Ok(());
+        }
+        if !std::io::stdin().is_terminal() {
+            return Err(LnurlError::invalid(format!("Cannot ask before the {}: stdin is not a terminal (use --yes)", step)).into());
+        }
+        eprintln!("About to send the {}:", step);
+        for (key, value) in summary {
+            eprintln!("  {:<10} {}", format!("{}:", key), value);
+        }
+        eprint!("Proceed? [y/N]: ");
+        std::io::stderr().flush()?;
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        match line.trim().to_ascii_lowercase().as_str() {
+            "y" | "yes" => Ok(()),
+            _ => Err(LnurlError::invalid(format!("Cancelled the {}", step)).into()),
+        }
+    }
+
+    fn rate_provider(&self) -> Result<&rates::RateProvider> {
+        self.rates.as_ref().ok_or_else(|| anyhow!("No exchange rate source configured"))
+    }
+
+    /// `amount` in msat (see `AmountArg::resolve`); a fiat amount is
+    /// converted at a rate younger than the provider's `amount_max_age`.
+    fn amount_msat(&self, amount: amount::AmountArg, max: u64) -> Result<u64> {
+        let price = match &amount {
+            amount::AmountArg::Fiat { currency, .. } => {
+                let provider = self.rate_provider()?;
+                let rate = provider.get(&self.agent, currency)?;
+                if rate.age() > provider.amount_max_age {
+                    return Err(LnurlError::invalid(format!(
+                        "The {} rate from {} is {}s old, more than the {}s allowed for fiat amounts (--fiat-amount-max-age)",
+                        currency.to_uppercase(),
+                        rate.source,
+                        rate.age().as_secs(),
+                        provider.amount_max_age.as_secs()
+                    ))
+                    .into());
+                }
+                info!("Rate: 1 BTC = {:.2} {} ({}, fetched {}s ago)", rate.price_per_btc, currency.to_uppercase(), rate.source, rate.age().as_secs());
+                Some(rate.price_per_btc)
+            }
+            _ => None,
+        };
+        amount.resolve(max, self.withdraw.rounding, price)
+    }
+
+    /// In dry-run mode, stops the flow where it would send `step`, the
+    /// request to `url`.
+    fn stop_if_dry_run(&self, step: &str, url: &str) -> Result<()> {
+        if self.dry_run {
+            return Err(LnurlError::dry_run(step, url).into());
+        }
+        Ok(())
+    }
+
+    /// LNURL-auth linking key for `domain`, from the key source chosen with `--auth-key`.
+    fn linking_key(&self, domain: &str) -> Result<secp256k1::SecretKey> {
+        match self.auth_key {
+            keys::KeySource::Seed => keys::linking_key(&keys::load_or_create_seed()?, domain),
+            keys::KeySource::Node => {
+                let rt = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .context("Failed to create Tokio runtime")?;
+                let mut node = open_backend(self, &rt)?;
+                let ln_client = cln_client(node.as_mut(), "derive LUD-13 linking keys")?;
+                let sign_request = cln_rpc::model::requests::SignmessageRequest { message: keys::node_message() };
+                let signature = match rt.block_on(ln_client.call(cln_rpc::Request::SignMessage(sign_request))).map_err(LnurlError::node)? {
+                    cln_rpc::model::Response::SignMessage(r) => r.signature,
+                    _ => return Err(anyhow!("Unexpected response type from signmessage")),
+                };
+                let signature = hex::decode(&signature).context("signmessage returned a non-hex signature")?;
+                keys::node_linking_key(&signature, domain)
+            }
+        }
+    }
+
+    /// Hands over the exchanges recorded since the last call.
+    fn take_exchanges(&self) -> Vec<history::Exchange> {
+        std::mem::take(&mut *self.exchanges.borrow_mut())
+    }
+
+    /// Formats an msat amount in the `--unit`, with its fiat equivalent when
+    /// `--fiat` is set.
+    pub fn fmt_msat(&self, msat: u64) -> String {
+        match &self.fiat {
+            Some(rate) => {
+                let fiat = rate.msat_to_fiat(msat);
+                let precision = if fiat >= 1.0 { 2 } else { 4 };
+                format!("{} (≈ {:.*} {})", amount::show(msat), precision, fiat, rate.currency.to_uppercase())
+            }
+            None => amount::show(msat),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Lightning RPC helpers
+// -----------------------------------------------------------------------------
+
+/// Opens the node the flows use (`--backend`).
+fn open_backend(ctx: &LnurlClient, rt: &tokio::runtime::Runtime) -> Result<Box<dyn backend::NodeBackend>> {
+    rt.block_on(ctx.node.open()).map_err(|e| LnurlError::node(e).into())
+}
+
+/// Core Lightning client of `node`, for the calls outside `NodeBackend`.
+fn cln_client<'a>(node: &'a mut dyn backend::NodeBackend, what: &str) -> Result<&'a mut ClnRpc> {
+    let name = node.name().to_string();
+    node.as_cln()
+        .ok_or_else(|| LnurlError::node(format!("Backend {} can't {}: that needs Core Lightning", name, what)).into())
+}
+
+/// Creates an invoice on the first node that answers: the configured one,
+/// then the `CLN_RPC_FALLBACK` sockets. Falling back warns, so a time-limited
+/// voucher isn't lost just because the primary node is down. Returns the
+/// backend that was used and the invoice.
+fn create_invoice_with_failover(
+    ctx: &LnurlClient,
+    rt: &tokio::runtime::Runtime,
+    request: &backend::InvoiceRequest,
+) -> Result<(Box<dyn backend::NodeBackend>, String)> {
+    let mut nodes = vec![ctx.node.clone()];
+    nodes.extend(get_cln_rpc_fallbacks().into_iter().map(|socket| backend::Config::Cln { socket }));
+    let mut last_err = None;
+    for (i, node) in nodes.iter().enumerate() {
+        let path = node.name();
+        if i > 0 {
+            warn!("falling back to backend {} for invoice creation", path);
+        }
+        let attempt = rt.block_on(node.open()).and_then(|mut node| {
+            let bolt11 = rt.block_on(node.create_invoice(request))?;
+            Ok((node, bolt11))
+        });
+        match attempt {
+            Ok(created) => return Ok(created),
+            Err(e) => {
+                if i + 1 < nodes.len() {
+                    warn!("backend {} failed to create invoice: {:#}", path, e);
+                }
+                last_err = Some(e.context(format!("Backend {} failed to create invoice", path)));
+            }
+        }
+    }
+    Err(LnurlError::node(last_err.unwrap_or_else(|| anyhow!("No node backend configured"))).into())
+}
+
+/// Appends `op` to the history with the HTTP exchanges made since the last record.
+fn record_history(ctx: &LnurlClient, op: &mut history::Operation) {
+    op.exchanges = ctx.take_exchanges();
+    if let Err(e) = history::append(op) {
+        warn!("failed to write history: {}", e);
+    }
+}
+
+/// Records a finished operation, with the HTTP exchanges made for it, in the
+/// history and, if it moved funds, in the audit log. Failures only warn: the
+/// operation itself already happened.
+fn record_operation(
+    ctx: &LnurlClient,
+    node: Option<&mut dyn backend::NodeBackend>,
+    rt: &tokio::runtime::Runtime,
+    mut op: history::Operation,
+) {
+    record_history(ctx, &mut op);
+    if matches!(op.kind.as_str(), "withdraw" | "channel-open" | "pay") {
+        record_audit(node, rt, audit::AuditRecord {
+            operation: op.kind.clone(),
+            url: op.url.clone(),
+            amount_msat: op.amount_msat,
+            detail: audit_detail(&op),
+            status: op.status.clone(),
+        });
+    }
+}
+
+/// Audit log `detail` for an operation: its details as `key=value` pairs.
+fn audit_detail(op: &history::Operation) -> String {
+    op.details.iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Appends a funds-moving operation to the audit log, signed by the node when
+/// enabled and one was used.
+fn record_audit(node: Option<&mut dyn backend::NodeBackend>, rt: &tokio::runtime::Runtime, record: audit::AuditRecord) {
+    if let Err(e) = try_record_audit(node, rt, record) {
+        warn!("failed to write audit log: {}", e);
+    }
+}
+
+fn try_record_audit(
+    node: Option<&mut dyn backend::NodeBackend>,
+    rt: &tokio::runtime::Runtime,
+    record: audit::AuditRecord,
+) -> Result<()> {
+    // Locked until the entry is written, signing included.
+    let mut log = audit::open()?;
+    let mut entry = log.prepare(record)?;
+    if let Some(node) = node.filter(|_| audit_sign_enabled()) {
+        entry.pubkey = Some(rt.block_on(node.get_local_node_id()).map_err(LnurlError::node)?);
+        let ln_client = cln_client(node, "sign audit entries")?;
+        let sign_request = cln_rpc::model::requests::SignmessageRequest {
+            message: entry.hash.clone(),
+        };
+        let sign_response = rt.block_on(ln_client.call(cln_rpc::Request::SignMessage(sign_request))).map_err(LnurlError::node)?;
+        entry.signature = match sign_response {
+            cln_rpc::model::Response::SignMessage(r) => Some(r.zbase),
+            _ => return Err(anyhow!("Unexpected response type from signmessage")),
+        };
+    }
+    log.append(&entry)
+}
+
+// -----------------------------------------------------------------------------
+// Channel request (LNURL channel open)
+// -----------------------------------------------------------------------------
+
+/// Response from GET /request-channel (LNURL channel open parameters).
+#[derive(Debug, Deserialize, Serialize)]
+struct ChannelRequestResponse {
+    uri: String,
+    /// Absent for hosted channels, which are requested over the peer connection.
+    #[serde(default)]
+    callback: String,
+    k1: String,
+    tag: String,
+    /// Name of the host, for hosted channels (LUD-07).
+    alias: Option<String>,
+}
+
+/// Tag of a hosted channel offer (LUD-07).
+const HOSTED_CHANNEL_TAG: &str = "hostedChannelRequest";
+
+/// Response from the channel-open callback (remoteid + k1).
+#[derive(Debug, Deserialize)]
+#[allow(dead_code)]
+struct ChannelOpenResponse {
+    status: String,
+    reason: Option<String>,
+    txid: Option<String>,
+    channel_id: Option<String>,
+}
+
+/// What `channel_request` got from the service.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChannelOutcome {
+    Opened(ChannelOpened),
+    Hosted(HostedChannel),
+}
+
+/// A channel the service agreed to open (its callback answered `OK`).
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelOpened {
+    /// The service's node, `pubkey@host:port`.
+    pub uri: String,
+    /// Our node id, sent as `remoteid`.
+    pub remote_id: String,
+    pub private: bool,
+    pub status: String,
+    pub txid: Option<String>,
+    pub channel_id: Option<String>,
+    /// Short channel id, capacity, reserves and fees once the channel
+    /// confirmed within `confirm_timeout`; empty otherwise.
+    pub confirmed: BTreeMap<String, String>,
+}
+
+/// A hosted channel offer (LUD-07) whose host the node is now connected to.
+/// Asking for the channel itself is left to a hosted-channels plugin.
+#[derive(Debug, Clone, Serialize)]
+pub struct HostedChannel {
+    /// The host's node, `pubkey@host:port`.
+    pub uri: String,
+    pub alias: Option<String>,
+    /// The offer's k1, which the plugin presents to the host.
+    pub secret: String,
+}
+
+/// GETs `/request-channel` from `url`, with a hint for common network issues.
+fn fetch_channel_request(ctx: &LnurlClient, url: &lnurl::Target) -> Result<ChannelRequestResponse> {
+    ctx.trust(url);
+    let request_url = url.endpoint("request-channel");
+    ctx.fetch_json(&request_url).map_err(|e| match e.downcast::<LnurlError>() {
+        Ok(LnurlError::Http { status: None, message }) => LnurlError::http(
+            None,
+            format!(
+                "{}. Check: same network (e.g. 192.168.x.x), firewall, server listening on 0.0.0.0:{}",
+                message,
+                url.port_or_known_default().unwrap_or(80)
+            ),
+        )
+        .into(),
+        Ok(e) => e.into(),
+        Err(e) => e,
+    })
+}
+
+/// Performs the LNURL channel-open flow: get params, connect to remote node,
+/// then call the open-channel callback with our pubkey and k1.
+pub(crate) fn channel_request(ctx: &LnurlClient, url: &lnurl::Target) -> Result<ChannelOutcome> {
+    info!("Requesting channel info from {}...", url);
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to create Tokio runtime")?;
+    let mut node = open_backend(ctx, &rt)?;
+
+    let pubkey = rt.block_on(node.get_local_node_id()).map_err(LnurlError::node)?;
+
+    info!("Node pubkey: {}", pubkey);
+
+    let resp = fetch_channel_request(ctx, url)?;
+
+    if resp.tag == HOSTED_CHANNEL_TAG {
+        return hosted_channel_request(ctx, node.as_mut(), &rt, url, &resp).map(ChannelOutcome::Hosted);
+    }
+
+    info!("Received channel request:");
+    info!("  URI: {}", resp.uri);
+    info!("  Callback: {}", resp.callback);
+    info!("  k1: {}", resp.k1);
+    ctx.check_tag(url, &resp.tag, "channelRequest")?;
+    ctx.check_k1(url, &resp.k1, &resp)?;
+    ctx.check_callback(url, &resp.callback)?;
+
+    ctx.confirm(
+        "channel open request",
+        &[
+            ("Service", url.host_str().unwrap_or_default().to_string()),
+            ("Peer", resp.uri.clone()),
+            ("Private", if ctx.private_channels { "yes" } else { "no" }.to_string()),
+        ],
+    )?;
+    let open_url = lnurl::callback_url(
+        &resp.callback,
+        &format!("remoteid={}&k1={}&private={}", pubkey, resp.k1, ctx.private_channels as u8),
+    );
+    ctx.stop_if_dry_run("channel open request", &open_url)?;
+    rt.block_on(node.connect_to_node(&resp.uri)).map_err(LnurlError::node)?;
+
+    info!("Requesting channel open...");
+    info!("Open URL: {}", open_url);
+
+    let open_resp: ChannelOpenResponse = ctx.get_json(&open_url).context("Failed to open channel")?;
+    info!("Open response: {:?}", open_resp);
+
+    let mut op = history::Operation::new("channel-open", url, &ctx.annotations).detail("remoteid", pubkey.as_str());
+    if ctx.private_channels {
+        op = op.detail("private", "true");
+    }
+    if let Some(txid) = &open_resp.txid {
+        op = op.detail("txid", txid.as_str());
+    }
+    if let Some(channel_id) = &open_resp.channel_id {
+        op = op.detail("channel_id", channel_id.as_str());
+    }
+    op.status = open_resp.status.clone();
+    op.reason = open_resp.reason.clone();
+    if open_resp.status == "OK" {
+        if let Err(e) = rt.block_on(node.settle()) {
+            warn!("{:#}", e);
+        }
+    }
+
+    let mut confirmed = Vec::new();
+    if let (Some(timeout), "OK") = (ctx.confirm_timeout, open_resp.status.as_str()) {
+        let peer = resp.uri.split('@').next().unwrap_or(&resp.uri);
+        info!("Waiting up to {}s for the channel to confirm...", timeout.as_secs());
+        // The channel exists either way; a slow confirmation only leaves the record incomplete.
+        let confirmation = cln_client(node.as_mut(), "wait for confirmations")
+            .and_then(|ln_client| wait_channel_confirmed(ln_client, &rt, peer, open_resp.txid.as_deref(), timeout));
+        match confirmation {
+            Ok(details) => confirmed = details,
+            Err(e) => warn!("{:#}", e),
+        }
+        for (key, value) in &confirmed {
+            op = op.detail(key, value.as_str());
+        }
+    }
+    record_operation(ctx, Some(node.as_mut()), &rt, op);
+    if open_resp.status != "OK" {
+        let reason = open_resp.reason.unwrap_or_else(|| "Unknown error".to_string());
+        return Err(LnurlError::rejected(format!("Service refused to open the channel: {}", reason), reason).into());
+    }
+
+    Ok(ChannelOutcome::Opened(ChannelOpened {
+        uri: resp.uri,
+        remote_id: pubkey,
+        private: ctx.private_channels,
+        status: open_resp.status,
+        txid: open_resp.txid,
+        channel_id: open_resp.channel_id,
+        confirmed: confirmed.into_iter().map(|(key, value)| (key.to_string(), value)).collect(),
+    }))
+}
+
+/// Hosted channel flow (LUD-07): there is no callback; the node connects to
+/// the host and asks for the channel over that connection, with `k1` as the
+/// secret. Core Lightning can't do the second part itself, so this connects,
+/// records the offer and leaves the request to a hosted-channels plugin.
+fn hosted_channel_request(
+    ctx: &LnurlClient,
+    node: &mut dyn backend::NodeBackend,
+    rt: &tokio::runtime::Runtime,
+    url: &lnurl::Target,
+    resp: &ChannelRequestResponse,
+) -> Result<HostedChannel> {
+    info!("Received hosted channel request:");
+    info!("  URI: {}", resp.uri);
+    if let Some(alias) = &resp.alias {
+        info!("  Alias: {}", alias);
+    }
+    info!("  Secret (k1): {}", resp.k1);
+    ctx.check_k1(url, &resp.k1, resp)?;
+    ctx.stop_if_dry_run("connection to the host", &resp.uri)?;
+
+    rt.block_on(node.connect_to_node(&resp.uri)).map_err(LnurlError::node)?;
+
+    let mut op = history::Operation::new("hosted-channel", url, &ctx.annotations)
+        .detail("uri", resp.uri.as_str())
+        .detail("secret", resp.k1.as_str());
+    if let Some(alias) = &resp.alias {
+        op = op.detail("alias", alias.as_str());
+    }
+    op.status = "OK".to_string();
+    record_history(ctx, &mut op);
+
+    Ok(HostedChannel {
+        uri: resp.uri.clone(),
+        alias: resp.alias.clone(),
+        secret: resp.k1.clone(),
+    })
+}
+
+/// Cancels a channel request (LUD-02 `cancel=1`), e.g. one that was accepted
+/// but not opened yet, so the service releases what it reserved for it.
+fn channel_cancel(ctx: &LnurlClient, url: &lnurl::Target) -> Result<()> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to create Tokio runtime")?;
+    let mut node = open_backend(ctx, &rt)?;
+    let pubkey = rt.block_on(node.get_local_node_id()).map_err(LnurlError::node)?;
+
+    info!("Requesting channel info from {}...", url);
+    let resp = fetch_channel_request(ctx, url)?;
+    if resp.tag == HOSTED_CHANNEL_TAG {
+        return Err(anyhow!("{} offers a hosted channel, which has no callback to cancel", url));
+    }
+    ctx.check_tag(url, &resp.tag, "channelRequest")?;
+    ctx.check_callback(url, &resp.callback)?;
+
+    info!("Cancelling the channel request...");
+    let cancel_url = lnurl::callback_url(&resp.callback, &format!("remoteid={}&k1={}&cancel=1", pubkey, resp.k1));
+    let cancel_resp: ChannelOpenResponse = ctx.get_json(&cancel_url).context("Failed to cancel channel request")?;
+    let mut op = history::Operation::new("channel-cancel", url, &ctx.annotations).detail("remoteid", pubkey.as_str());
+    op.status = cancel_resp.status.clone();
+    op.reason = cancel_resp.reason.clone();
+    record_history(ctx, &mut op);
+    if cancel_resp.status != "OK" {
+        let reason = cancel_resp.reason.unwrap_or_else(|| "Unknown error".to_string());
+        return Err(LnurlError::rejected(format!("Service refused to cancel: {}", reason), reason).into());
+    }
+    info!("Channel request cancelled.");
+    Ok(())
+}
+
+/// How often `listpeerchannels` is polled while waiting for a confirmation.
+const CONFIRM_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Polls `listpeerchannels` until the channel with `peer` (the one funded by
+/// `txid`, when the service reported it) is in CHANNELD_NORMAL, then returns
+/// its final parameters as history details.
+fn wait_channel_confirmed(
+    ln_client: &mut ClnRpc,
+    rt: &tokio::runtime::Runtime,
+    peer: &str,
+    txid: Option<&str>,
+    timeout: Duration,
+) -> Result<Vec<(&'static str, String)>> {
+    let _spinner = progress::spinner("Waiting for the channel to confirm");
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        let response: serde_json::Value = rt
+            .block_on(ln_client.call_raw("listpeerchannels", &serde_json::json!({ "id": peer })))
+            .map_err(|e| anyhow!("listpeerchannels failed: {}", e))?;
+        let channel = response["channels"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|c| txid.is_none_or(|t| c["funding_txid"].as_str() == Some(t)));
+        if let Some(channel) = channel {
+            if channel["state"] == "CHANNELD_NORMAL" && channel["short_channel_id"].is_string() {
+                return Ok(channel_details(channel));
+            }
+        }
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            return Err(anyhow!(
+                "Channel with {} not confirmed after {}s; its details were not recorded",
+                peer,
+                timeout.as_secs()
+            ));
+        }
+        std::thread::sleep(remaining.min(CONFIRM_POLL_INTERVAL));
+    }
+}
+
+/// Short channel id, capacity, reserves and both fee policies of a
+/// `listpeerchannels` entry. Older CLN versions report amounts as "<n>msat"
+/// strings and only our own fees, at the top level.
+fn channel_details(channel: &serde_json::Value) -> Vec<(&'static str, String)> {
+    let local = &channel["updates"]["local"];
+    let remote = &channel["updates"]["remote"];
+    let fields = [
+        ("short_channel_id", &channel["short_channel_id"]),
+        ("capacity_msat", &channel["total_msat"]),
+        ("our_reserve_msat", &channel["our_reserve_msat"]),
+        ("their_reserve_msat", &channel["their_reserve_msat"]),
+        ("our_fee_base_msat", if local.is_null() { &channel["fee_base_msat"] } else { &local["fee_base_msat"] }),
+        (
+            "our_fee_ppm",
+            if local.is_null() { &channel["fee_proportional_millionths"] } else { &local["fee_proportional_millionths"] },
+        ),
+        ("their_fee_base_msat", &remote["fee_base_msat"]),
+        ("their_fee_ppm", &remote["fee_proportional_millionths"]),
+    ];
+    fields
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let value = match value {
+                serde_json::Value::String(s) => s.trim_end_matches("msat").to_string(),
+                serde_json::Value::Number(n) => n.to_string(),
+                _ => return None,
+            };
+            Some((key, value))
+        })
+        .collect()
+}
+
+// -----------------------------------------------------------------------------
+// Channel shop (compare channel offers)
+// -----------------------------------------------------------------------------
+
+/// Reads an offer number from stdin (empty line cancels).
+fn prompt_offer(count: usize) -> Result<Option<usize>> {
+    eprint!("Open which offer? [1-{}, empty to cancel]: ", count);
+    std::io::stderr().flush()?;
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    line.parse::<usize>()
+        .map(Some)
+        .map_err(|_| anyhow!("Invalid offer number '{}'", line))
+}
+
+/// Fetches channel offers from every server, prints a comparison table, then
+/// runs the channel-open flow against the chosen one. The table and prompt
+/// go to stderr, leaving stdout to the result.
+fn channel_shop(ctx: &LnurlClient, urls: &[lnurl::Target], pick: Option<usize>) -> Result<Option<ChannelOutcome>> {
+    let mut offers = Vec::new();
+    eprintln!("{:>3}  {:<32} {:<20} {:<24} callback", "#", "server", "node", "address");
+    for url in urls {
+        match fetch_channel_request(ctx, url) {
+            Ok(resp) => {
+                let (node, address) = resp.uri.split_once('@').unwrap_or((resp.uri.as_str(), "-"));
+                let callback_host = Url::parse(&resp.callback)
+                    .ok()
+                    .and_then(|u| u.host_str().map(|h| h.to_string()))
+                    .unwrap_or_else(|| "-".to_string());
+                offers.push(url);
+                eprintln!(
+                    "{:>3}  {:<32} {:<20} {:<24} {}",
+                    offers.len(),
+                    url.as_str().trim_end_matches('/'),
+                    format!("{}...", &node[..16.min(node.len())]),
+                    address,
+                    callback_host
+                );
+            }
+            Err(e) => warn!("{}: no channel offer: {:#}", url, e),
+        }
+    }
+    if offers.is_empty() {
+        return Err(anyhow!("No server returned a channel offer"));
+    }
+
+    let choice = match pick {
+        Some(n) => n,
+        None => match prompt_offer(offers.len())? {
+            Some(n) => n,
+            None => {
+                info!("No offer selected.");
+                return Ok(None);
+            }
+        },
+    };
+    let url = offers
+        .get(choice.wrapping_sub(1))
+        .ok_or_else(|| anyhow!("Offer {} does not exist (1-{})", choice, offers.len()))?;
+    channel_request(ctx, url).map(Some)
+}
+
+// -----------------------------------------------------------------------------
+// Withdraw request (LNURL withdraw)
+// -----------------------------------------------------------------------------
+
+/// Response from GET /request-withdraw (withdraw parameters and limits).
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(dead_code)]
+struct WithdrawRequestResponse {
+    callback: String,
+    k1: String,
+    tag: String,
+    #[serde(rename = "defaultDescription")]
+    default_description: String,
+    #[serde(rename = "minWithdrawable")]
+    min_withdrawable: u64,
+    #[serde(rename = "maxWithdrawable")]
+    max_withdrawable: u64,
+    /// LUD-14: URL returning a fresh withdraw request with the remaining balance.
+    #[serde(rename = "balanceCheck")]
+    balance_check: Option<String>,
+    /// LUD-19: pay link of the same service, e.g. to top the balance up.
+    #[serde(rename = "payLink")]
+    pay_link: Option<String>,
+}
+
+/// Response from the withdraw callback (status and optional reason).
+#[derive(Debug, Deserialize)]
+struct WithdrawResponse {
+    status: String,
+    reason: Option<String>,
+}
+
+/// Withdraw parameters carried in the query of a fast withdraw link
+/// (LUD-08), or `None` if any are missing.
+fn fast_withdraw_params(url: &Url) -> Result<Option<WithdrawRequestResponse>> {
+    let query: std::collections::HashMap<_, _> = url.query_pairs().collect();
+    if query.get("tag").map(|t| t.as_ref()) != Some("withdrawRequest") {
+        return Ok(None);
+    }
+    let (Some(callback), Some(k1), Some(min), Some(max)) = (
+        query.get("callback"),
+        query.get("k1"),
+        query.get("minWithdrawable"),
+        query.get("maxWithdrawable"),
+    ) else {
+        return Ok(None);
+    };
+    Ok(Some(WithdrawRequestResponse {
+        callback: callback.to_string(),
+        k1: k1.to_string(),
+        tag: "withdrawRequest".to_string(),
+        default_description: query.get("defaultDescription").map(|d| d.to_string()).unwrap_or_default(),
+        min_withdrawable: min.parse().with_context(|| format!("Invalid minWithdrawable '{}' in the LNURL", min))?,
+        max_withdrawable: max.parse().with_context(|| format!("Invalid maxWithdrawable '{}' in the LNURL", max))?,
+        balance_check: query.get("balanceCheck").map(|b| b.to_string()),
+        pay_link: query.get("payLink").map(|p| p.to_string()),
+    }))
+}
+
+/// GETs `/request-withdraw` from `url`, unless the link already carries the
+/// parameters (LUD-08). A pay link is followed to the withdraw link it names
+/// (`withdrawLink`, LUD-19).
+fn fetch_withdraw_request(ctx: &LnurlClient, url: &lnurl::Target) -> Result<WithdrawRequestResponse> {
+    ctx.trust(url);
+    if let Some(params) = fast_withdraw_params(url)? {
+        info!("Using the withdraw parameters in the link (fast withdraw).");
+        return Ok(params);
+    }
+    let request_url = url.endpoint("request-withdraw");
+    let params: serde_json::Value = ctx.fetch_json(&request_url)?;
+    if params["status"] == "ERROR" {
+        let reason = params["reason"].as_str().unwrap_or("Unknown error");
+        return Err(LnurlError::rejected(format!("{} refused: {}", request_url, reason), reason).into());
+    }
+    if params["tag"] != "payRequest" {
+        return serde_json::from_value(params).with_context(|| format!("Invalid withdraw request from {}", request_url));
+    }
+    let link = params["withdrawLink"]
+        .as_str()
+        .ok_or_else(|| anyhow!("{} is a pay link without a withdrawLink; use pay", request_url))?;
+    let link = parse_target(link).with_context(|| format!("Invalid withdrawLink from {}", request_url))?;
+    ctx.check_callback(url, link.as_str())?;
+    info!("{} is a pay link; withdrawing through its withdraw link {}...", request_url, link);
+    ctx.fetch_json(link.as_str())
+}
+
+/// Blocks until the invoice `label` that `node` created is paid, or
+/// `timeout` passes.
+fn wait_invoice_paid(
+    rt: &tokio::runtime::Runtime,
+    node: &mut dyn backend::NodeBackend,
+    label: &str,
+    timeout: Duration,
+) -> Result<()> {
+    let paid = rt
+        .block_on(async { tokio::time::timeout(timeout, node.wait_invoice(label)).await })
+        .map_err(|_| anyhow!("Invoice {} not paid after {}s", label, timeout.as_secs()))??;
+    if !paid {
+        return Err(anyhow!("Invoice {} expired unpaid", label));
+    }
+    Ok(())
+}
+
+/// LNURL withdraw flow: get withdraw params, create a BOLT11 invoice for the
+/// requested amount, then call the withdraw callback with k1 and the invoice (pr).
+pub(crate) fn withdraw_request(ctx: &LnurlClient, url: &lnurl::Target, amount: amount::AmountArg, description: Option<String>) -> Result<Withdrawal> {
+    info!("Requesting withdrawal info from {}...", url);
+    let resp = fetch_withdraw_request(ctx, url)?;
+    let amount_msat = ctx.amount_msat(amount, resp.max_withdrawable)?;
+    withdraw_with_params(ctx, url, &resp, amount_msat, description)
+}
+
+/// A withdrawal the service accepted (its callback answered `OK`). The
+/// service pays the invoice on its own time.
+#[derive(Debug, Clone, Serialize)]
+pub struct Withdrawal {
+    pub amount_msat: u64,
+    pub bolt11: String,
+    /// Invoice label on the node; `None` for an invoice made elsewhere.
+    pub label: Option<String>,
+    /// Node that created the invoice, a fallback one included; `None` for an
+    /// invoice made elsewhere.
+    pub backend: Option<String>,
+    pub status: String,
+}
+
+/// Withdraw flow with an invoice made elsewhere (`--invoice`): the client's
+/// node isn't involved, but the invoice amount must still be one the service
+/// allows.
+fn withdraw_with_invoice(ctx: &LnurlClient, url: &lnurl::Target, bolt11: &str) -> Result<Withdrawal> {
+    let decoded = backend::decode_bolt11(bolt11)?;
+    let amount_msat = decoded
+        .amount_msat
+        .ok_or_else(|| LnurlError::invalid("The invoice has no amount; a withdrawal needs one"))?;
+    info!("Requesting withdrawal info from {}...", url);
+    let resp = fetch_withdraw_request(ctx, url)?;
+    check_withdraw_params(ctx, url, &resp, amount_msat)?;
+    confirm_withdraw(ctx, url, &resp, amount_msat)?;
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to create Tokio runtime")?;
+    info!("Using the given invoice for {}...", ctx.fmt_msat(amount_msat));
+    match withdraw_attempt(ctx, &rt, url, &resp, amount_msat, WithdrawInvoice::Given(bolt11))? {
+        WithdrawAttempt::Paid(withdrawal, _) => Ok(withdrawal),
+        WithdrawAttempt::Rejected { error, .. } => Err(error.into()),
+    }
+}
+
+/// Logs the withdraw params and checks them and `amount_msat` against the
+/// service's limits.
+fn check_withdraw_params(ctx: &LnurlClient, url: &Url, resp: &WithdrawRequestResponse, amount_msat: u64) -> Result<()> {
+    info!("Received withdraw request:");
+    info!("  Callback: {}", resp.callback);
+    info!("  k1: {}", resp.k1);
+    info!("  Min withdrawable: {}", ctx.fmt_msat(resp.min_withdrawable));
+    info!("  Max withdrawable: {}", ctx.fmt_msat(resp.max_withdrawable));
+    info!("  Default description: {}", resp.default_description);
+    if let Some(pay_link) = &resp.pay_link {
+        info!("  Pay link: {}", pay_link);
+    }
+    ctx.check_tag(url, &resp.tag, "withdrawRequest")?;
+    ctx.check_k1(url, &resp.k1, resp)?;
+    ctx.check_callback(url, &resp.callback)?;
+
+    if amount_msat < resp.min_withdrawable || amount_msat > resp.max_withdrawable {
+        return Err(LnurlError::invalid(format!(
+            "Amount {} is outside allowed range [{}, {}]",
+            amount::show(amount_msat),
+            amount::show(resp.min_withdrawable),
+            amount::show(resp.max_withdrawable)
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+fn confirm_withdraw(ctx: &LnurlClient, url: &Url, resp: &WithdrawRequestResponse, amount_msat: u64) -> Result<()> {
+    let callback_host = Url::parse(&resp.callback).ok().and_then(|u| u.host_str().map(|h| h.to_string()));
+    ctx.confirm(
+        "withdraw callback",
+        &[
+            ("Amount", ctx.fmt_msat(amount_msat)),
+            ("Service", url.host_str().unwrap_or_default().to_string()),
+            ("Callback", callback_host.unwrap_or_else(|| resp.callback.clone())),
+        ],
+    )
+}
+
+/// Second half of the withdraw flow, once the params are known: create the
+/// invoice and call the withdraw callback.
+fn withdraw_with_params(
+    ctx: &LnurlClient,
+    url: &Url,
+    resp: &WithdrawRequestResponse,
+    amount_msat: u64,
+    description: Option<String>,
+) -> Result<Withdrawal> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to create Tokio runtime")?;
+    withdraw_on_node(ctx, &rt, url, resp, amount_msat, description).map(|(withdrawal, _)| withdrawal)
+}
+
+/// `withdraw_with_params`, also returning the node that created the invoice.
+fn withdraw_on_node(
+    ctx: &LnurlClient,
+    rt: &tokio::runtime::Runtime,
+    url: &Url,
+    resp: &WithdrawRequestResponse,
+    amount_msat: u64,
+    description: Option<String>,
+) -> Result<(Withdrawal, Option<Box<dyn backend::NodeBackend>>)> {
+    check_withdraw_params(ctx, url, resp, amount_msat)?;
+    confirm_withdraw(ctx, url, resp, amount_msat)?;
+
+    let description = description.unwrap_or_else(|| resp.default_description.clone());
+    info!("Creating invoice for {} with description: {}...", ctx.fmt_msat(amount_msat), description);
+
+    let mut expiry = ctx.withdraw.invoice_expiry;
+    let mut retries_left = ctx.withdraw.expiry_retries;
+    loop {
+        match withdraw_attempt(ctx, rt, url, resp, amount_msat, WithdrawInvoice::Create { description: &description, expiry })? {
+            WithdrawAttempt::Paid(withdrawal, node) => return Ok((withdrawal, node)),
+            WithdrawAttempt::Rejected { error, reason } => {
+                if retries_left == 0 || !reason.to_lowercase().contains("expired") {
+                    return Err(error.into());
+                }
+                retries_left -= 1;
+                expiry *= 2;
+                warn!("service reports the invoice expired; retrying with a new invoice ({}s expiry)", expiry);
+            }
+        }
+    }
+}
+
+/// Where the invoice of a withdraw attempt comes from.
+enum WithdrawInvoice<'a> {
+    /// Created on the node, valid for `expiry` seconds.
+    Create { description: &'a str, expiry: u64 },
+    /// Given by the user (`--invoice`).
+    Given(&'a str),
+}
+
+/// Result of one invoice + callback round of the withdraw flow.
+enum WithdrawAttempt {
+    /// With the node that created the invoice, if the client's.
+    Paid(Withdrawal, Option<Box<dyn backend::NodeBackend>>),
+    /// The service refused the invoice; `reason` is its error text.
+    Rejected { error: LnurlError, reason: String },
+}
+
+/// Gets the invoice (creating it on the node unless one was given) and
+/// submits it to the withdraw callback. Each attempt is recorded in the
+/// history.
+fn withdraw_attempt(
+    ctx: &LnurlClient,
+    rt: &tokio::runtime::Runtime,
+    url: &Url,
+    resp: &WithdrawRequestResponse,
+    amount_msat: u64,
+    invoice: WithdrawInvoice,
+) -> Result<WithdrawAttempt> {
+    let (mut node, bolt11, label) = match invoice {
+        WithdrawInvoice::Create { description, expiry } => {
+            // Create a BOLT11 invoice on our node so the server can pay us.
+            let label = format!("lnurl-withdraw-{}", std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis());
+
+            let invoice_request = backend::InvoiceRequest {
+                amount_msat,
+                description: description.to_string(),
+                label: label.clone(),
+                expiry,
+            };
+
+            let (node, bolt11) = create_invoice_with_failover(ctx, rt, &invoice_request)?;
+            info!("Invoice created: {}...", &bolt11[..50.min(bolt11.len())]);
+            (Some(node), bolt11, label)
+        }
+        WithdrawInvoice::Given(bolt11) => (None, bolt11.to_string(), String::new()),
+    };
+    let backend = node.as_ref().map(|n| n.name().to_string()).unwrap_or_default();
+
+    info!("Submitting withdrawal request...");
+    let mut params = format!("k1={}&pr={}", resp.k1, urlencoding::encode(&bolt11));
+    if let (Some(notify), Some(_)) = (&ctx.withdraw.balance_notify, &resp.balance_check) {
+        params.push_str(&format!("&balanceNotify={}", urlencoding::encode(notify.as_str())));
+    }
+    let withdraw_url = lnurl::callback_url(&resp.callback, &params);
+    ctx.stop_if_dry_run("withdraw callback", &withdraw_url)?;
+
+    let http_resp = ctx.http_get(&withdraw_url);
+    let mut op = history::Operation::new("withdraw", url, &ctx.annotations);
+    if !label.is_empty() {
+        op = op.detail("label", label.as_str());
+    }
+    op = op.detail("bolt11", bolt11.as_str());
+    if let Some(balance_check) = &resp.balance_check {
+        // Kept for `balance-check`, which outlives the one-time k1.
+        op = op.detail("balance_check", balance_check.as_str());
+    }
+    if node.is_some() && backend != ctx.node.name() {
+        // Funds land on a secondary wallet; make that visible in the records.
+        op = op.detail("backend", backend.as_str());
+    }
+    op.amount_msat = Some(amount_msat);
+    let withdraw_resp = match http_resp {
+        Ok((200..=299, body)) => match serde_json::from_str::<WithdrawResponse>(&body) {
+            Ok(withdraw_resp) => withdraw_resp,
+            Err(e) => {
+                // The service may be paying the invoice anyway, so the
+                // attempt is recorded with what is known.
+                let error = LnurlError::json(&resp.callback, e);
+                op.status = "unknown".to_string();
+                op.reason = Some(error.to_string());
+                record_operation(ctx, node.as_mut().map(|n| n.as_mut() as _), rt, op);
+                return Err(error.into());
+            }
+        },
+        Ok((code, body)) => {
+            // Surface server error body (e.g. payment failure reason).
+            op.status = format!("HTTP {}", code);
+            op.reason = Some(body.clone());
+            record_operation(ctx, node.as_mut().map(|n| n.as_mut() as _), rt, op);
+            return Ok(WithdrawAttempt::Rejected {
+                error: LnurlError::http(Some(code), format!("Withdraw request failed (HTTP {}): {}", code, body)),
+                reason: body,
+            });
+        }
+        Err(e) => {
+            op.status = "unknown".to_string();
+            op.reason = Some(format!("{:#}", e));
+            record_operation(ctx, node.as_mut().map(|n| n.as_mut() as _), rt, op);
+            return Err(e.context("Withdraw request failed"));
+        }
+    };
+    op.status = withdraw_resp.status.clone();
+    op.reason = withdraw_resp.reason.clone();
+    record_operation(ctx, node.as_mut().map(|n| n.as_mut() as _), rt, op);
+
+    info!("Withdraw response: {:?}", withdraw_resp);
+
+    if withdraw_resp.status != "OK" {
+        let reason = withdraw_resp.reason.unwrap_or_else(|| "Unknown error".to_string());
+        return Ok(WithdrawAttempt::Rejected {
+            error: LnurlError::rejected(format!("Withdrawal failed: {}", reason), reason.as_str()),
+            reason,
+        });
+    }
+    if let Some(node) = node.as_mut() {
+        if let Err(e) = rt.block_on(node.settle()) {
+            warn!("{:#}", e);
+        }
+    }
+
+    let withdrawal = Withdrawal {
+        amount_msat,
+        bolt11,
+        label: node.is_some().then_some(label),
+        backend: node.is_some().then_some(backend),
+        status: withdraw_resp.status,
+    };
+    Ok(WithdrawAttempt::Paid(withdrawal, node))
+}
+
+// -----------------------------------------------------------------------------
+// Combined withdraw (several vouchers towards one target)
+// -----------------------------------------------------------------------------
+
+/// Splits `target` msat over vouchers with `(min, max)` limits, in order,
+/// giving each as much as it allows. A voucher whose minimum exceeds what is
+/// still needed takes the shortfall from earlier vouchers when they have room
+/// above their own minimum. Returns `None` if the target can't be reached.
+fn plan_vouchers(limits: &[(u64, u64)], target: u64) -> Option<Vec<u64>> {
+    let mut amounts = vec![0u64; limits.len()];
+    let mut remaining = target;
+    for (i, &(min, max)) in limits.iter().enumerate() {
+        if remaining == 0 {
+            break;
+        }
+        if min > max {
+            continue;
+        }
+        if remaining >= min {
+            amounts[i] = remaining.min(max);
+            remaining -= amounts[i];
+            continue;
+        }
+        let deficit = min - remaining;
+        let slack: u64 = (0..i).map(|j| amounts[j].saturating_sub(limits[j].0)).sum();
+        if slack < deficit {
+            continue;
+        }
+        let mut to_free = deficit;
+        for j in (0..i).rev() {
+            let take = to_free.min(amounts[j].saturating_sub(limits[j].0));
+            amounts[j] -= take;
+            to_free -= take;
+        }
+        amounts[i] = min;
+        remaining = 0;
+    }
+    // Each voucher is redeemed for 0 or an amount within its limits.
+    let within = amounts.iter().zip(limits).all(|(&amount, &(min, max))| amount == 0 || (min..=max).contains(&amount));
+    (remaining == 0 && within && amounts.iter().sum::<u64>() == target).then_some(amounts)
+}
+
+/// Redeems several withdraw vouchers so that together they pay `target` msat,
+/// then reports what was received.
+fn withdraw_combine(ctx: &LnurlClient, target: u64, urls: &[lnurl::Target], description: Option<String>) -> Result<()> {
+    let mut limits = Vec::new();
+    for url in urls {
+        let resp = fetch_withdraw_request(ctx, url)
+            .with_context(|| format!("Failed to fetch withdraw params from {}", url))?;
+        info!(
+            "{}: {} - {}",
+            url,
+            ctx.fmt_msat(resp.min_withdrawable),
+            ctx.fmt_msat(resp.max_withdrawable)
+        );
+        limits.push((resp.min_withdrawable, resp.max_withdrawable));
+    }
+    let plan = plan_vouchers(&limits, target).ok_or_else(|| {
+        let total: u64 = limits.iter().filter(|(min, max)| min <= max).map(|(_, max)| max).sum();
+        anyhow!(
+            "Cannot reach {} with these vouchers (at most {}, within each voucher's limits)",
+            amount::show(target),
+            amount::show(total)
+        )
+    })?;
+
+    info!("Plan:");
+    for (url, amount) in urls.iter().zip(&plan).filter(|(_, a)| **a > 0) {
+        info!("  {} from {}", ctx.fmt_msat(*amount), url);
+    }
+
+    let mut received = 0u64;
+    let mut failed = 0;
+    for (url, amount) in urls.iter().zip(&plan).filter(|(_, a)| **a > 0) {
+        match withdraw_request(ctx, url, amount::AmountArg::Msat(*amount), description.clone()) {
+            Ok(_) => received += amount,
+            Err(e) if matches!(e.downcast_ref(), Some(LnurlError::DryRun { .. })) => return Err(e),
+            Err(e) => {
+                warn!("withdraw of {} from {} failed: {:#}", amount::show(*amount), url, e);
+                failed += 1;
+            }
+        }
+    }
+
+    info!("Received {} of {} target.", ctx.fmt_msat(received), ctx.fmt_msat(target));
+    if failed > 0 {
+        return Err(anyhow!("{} voucher(s) failed; received {} of {}", failed, amount::show(received), amount::show(target)));
+    }
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------
+// Drain withdraw (repeat while balanceCheck reports a balance)
+// -----------------------------------------------------------------------------
+
+/// Withdraws `maxWithdrawable` repeatedly: after each withdrawal, waits for the
+/// invoice to be paid, then refreshes the limits from the service's
+/// balanceCheck URL (LUD-14), until the balance or `target` is exhausted.
+/// `max_withdrawals` bounds the loop in case the reported balance never drops.
+fn withdraw_drain(
+    ctx: &LnurlClient,
+    url: &lnurl::Target,
+    target: Option<u64>,
+    max_withdrawals: u32,
+    settle_timeout: Duration,
+) -> Result<()> {
+    // Each round waits for the invoice to be paid before refreshing the
+    // balance, which only Core Lightning nodes (and their fallbacks) can.
+    if !matches!(ctx.node, backend::Config::Cln { .. }) {
+        return Err(LnurlError::invalid(format!(
+            "withdraw-drain waits for each invoice to be paid, which backend {} can't; use a Core Lightning node (--backend cln)",
+            ctx.node.name()
+        ))
+        .into());
+    }
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to create Tokio runtime")?;
+    info!("Requesting withdrawal info from {}...", url);
+    let mut resp = fetch_withdraw_request(ctx, url)?;
+    let mut received = 0u64;
+    let mut rounds = 0u32;
+    loop {
+        let remaining = target.map(|t| t - received).unwrap_or(u64::MAX);
+        let amount = resp.max_withdrawable.min(remaining);
+        if amount == 0 || amount < resp.min_withdrawable {
+            break;
+        }
+        if rounds == max_withdrawals {
+            warn!("stopping after {} withdrawals (--max-withdrawals) with balance left", rounds);
+            break;
+        }
+
+        let (withdrawal, node) = withdraw_on_node(ctx, &rt, url, &resp, amount, None)?;
+        let (Some(mut node), Some(label)) = (node, withdrawal.label) else {
+            return Err(anyhow!("The withdrawal's invoice wasn't made on the node"));
+        };
+        info!("Waiting for payment of {}...", label);
+        wait_invoice_paid(&rt, node.as_mut(), &label, settle_timeout)
+            .with_context(|| format!("Stopped after receiving {}", amount::show(received)))?;
+        received += amount;
+        rounds += 1;
+        match target {
+            Some(t) => info!("Progress: {} of {} received ({} withdrawals).", ctx.fmt_msat(received), ctx.fmt_msat(t), rounds),
+            None => info!("Progress: {} received ({} withdrawals).", ctx.fmt_msat(received), rounds),
+        }
+
+        let Some(balance_check) = resp.balance_check.clone() else {
+            if target.is_none_or(|t| received < t) {
+                warn!("service does not support balanceCheck; stopping after one withdrawal");
+            }
+            break;
+        };
+        ctx.check_callback(url, &balance_check)?;
+        resp = ctx.fetch_json(&balance_check)
+            .with_context(|| format!("Balance check at {} failed", balance_check))?;
+    }
+
+    if rounds == 0 {
+        return Err(LnurlError::invalid(format!(
+            "Nothing to withdraw (service allows {} - {})",
+            amount::show(resp.min_withdrawable),
+            amount::show(resp.max_withdrawable)
+        ))
+        .into());
+    }
+    info!("Done: {} received in {} withdrawals.", ctx.fmt_msat(received), rounds);
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------
+// Balance check (LUD-14)
+// -----------------------------------------------------------------------------
+
+/// What a service says is left to withdraw.
+#[derive(Debug, Clone, Serialize)]
+pub struct Balance {
+    pub service: String,
+    pub min_withdrawable: u64,
+    pub max_withdrawable: u64,
+}
+
+/// Gets the remaining balance of each service a withdrawal recorded a
+/// balanceCheck URL for (the latest one per service), or of `url` only.
+fn balance_check(ctx: &LnurlClient, url: Option<&lnurl::Target>) -> Result<Vec<Balance>> {
+    let mut checks = std::collections::BTreeMap::new();
+    for op in history::load()? {
+        if op.kind != "withdraw" || url.is_some_and(|u| op.url != u.as_str()) {
+            continue;
+        }
+        if let Some(check) = op.details.get("balance_check") {
+            checks.insert(op.url.clone(), check.clone());
+        }
+    }
+    if checks.is_empty() {
+        let Some(url) = url else {
+            info!("No balanceCheck URLs recorded; they are kept with withdrawals from services that send one.");
+            return Ok(Vec::new());
+        };
+        let resp = fetch_withdraw_request(ctx, url)?;
+        return Ok(vec![Balance {
+            service: url.to_string(),
+            min_withdrawable: resp.min_withdrawable,
+            max_withdrawable: resp.max_withdrawable,
+        }]);
+    }
+
+    let mut balances = Vec::new();
+    let mut failed = 0;
+    for (service, check) in &checks {
+        let result = Url::parse(service).map_err(anyhow::Error::from).and_then(|origin| {
+            ctx.trust(&origin);
+            ctx.check_callback(&origin, check)?;
+            ctx.fetch_json::<WithdrawRequestResponse>(check)
+        });
+        match result {
+            Ok(resp) => balances.push(Balance {
+                service: service.clone(),
+                min_withdrawable: resp.min_withdrawable,
+                max_withdrawable: resp.max_withdrawable,
+            }),
+            Err(e) => {
+                warn!("{}: {:#}", service, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed == checks.len() {
+        return Err(anyhow!("No balance check succeeded"));
+    }
+    Ok(balances)
+}
+
+// -----------------------------------------------------------------------------
+// Pay request (LNURL-pay, LUD-06)
+// -----------------------------------------------------------------------------
+
+/// Response from the pay endpoint (payRequest parameters and limits).
+#[derive(Debug, Deserialize)]
+struct PayRequestResponse {
+    callback: String,
+    tag: String,
+    #[serde(rename = "minSendable")]
+    min_sendable: u64,
+    #[serde(rename = "maxSendable")]
+    max_sendable: u64,
+    /// JSON array of `[mime type, content]` pairs; the invoice commits to its hash.
+    metadata: String,
+    /// Longest comment the callback accepts; 0 if none (LUD-12).
+    #[serde(rename = "commentAllowed", default)]
+    comment_allowed: usize,
+    /// LUD-19: withdraw link of the same service.
+    #[serde(rename = "withdrawLink")]
+    withdraw_link: Option<String>,
+    /// `false` if the link may be saved and paid again (LUD-11).
+    disposable: Option<bool>,
+    /// Payer data fields the service asks for (LUD-18).
+    #[serde(rename = "payerData")]
+    payer_data: Option<std::collections::BTreeMap<String, PayerDataField>>,
+}
+
+/// One requested payer data field.
+#[derive(Debug, Deserialize, Serialize)]
+struct PayerDataField {
+    #[serde(default)]
+    mandatory: bool,
+    /// Challenge to sign, for `auth`.
+    k1: Option<String>,
+}
+
+/// Response from the pay callback: the invoice to pay.
+#[derive(Debug, Deserialize)]
+struct PayCallbackResponse {
+    pr: Option<String>,
+    status: Option<String>,
+    reason: Option<String>,
+    /// Shown once the payment succeeded (LUD-09).
+    #[serde(rename = "successAction")]
+    success_action: Option<lnurl::success::SuccessAction>,
+    /// Where the service reports whether the invoice was paid (LUD-21).
+    verify: Option<String>,
+}
+
+/// Response from a verify URL (LUD-21).
+#[derive(Debug, Deserialize)]
+struct VerifyResponse {
+    status: Option<String>,
+    reason: Option<String>,
+    #[serde(default)]
+    settled: bool,
+    preimage: Option<String>,
+}
+
+/// How often the verify URL is polled.
+const VERIFY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Polls `verify` until the service reports the invoice settled, returning
+/// the preimage it gives once checked against `payment_hash`, or `None` if it
+/// isn't settled within `timeout`.
+fn verify_payment(ctx: &LnurlClient, verify: &str, payment_hash: &str, timeout: Duration) -> Result<Option<String>> {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        let resp: VerifyResponse = ctx.fetch_json(verify)?;
+        if resp.status.as_deref() == Some("ERROR") {
+            let reason = resp.reason.unwrap_or_else(|| "Unknown error".to_string());
+            return Err(LnurlError::rejected(format!("Service could not verify the payment: {}", reason), reason).into());
+        }
+        if resp.settled {
+            let preimage = resp.preimage.ok_or_else(|| anyhow!("Settled payment has no preimage"))?;
+            let hash = hex::decode(&preimage).map(|p| hex::encode(sha2::Sha256::digest(p)));
+            if hash.ok().as_deref() != Some(payment_hash) {
+                return Err(anyhow!("Preimage from {} does not match the payment hash", verify));
+            }
+            return Ok(Some(preimage));
+        }
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            return Ok(None);
+        }
+        std::thread::sleep(remaining.min(VERIFY_POLL_INTERVAL));
+    }
+}
+
+/// Builds the `payerdata` JSON for the fields the service asks for, from what
+/// the user agreed to disclose. Fails if a mandatory field can't be filled.
+/// Returns the JSON and the names of the disclosed fields, or `None` if
+/// nothing is sent.
+fn payer_data(
+    ctx: &LnurlClient,
+    url: &lnurl::Target,
+    requested: &std::collections::BTreeMap<String, PayerDataField>,
+    args: &PayArgs,
+) -> Result<Option<(String, Vec<String>)>> {
+    let domain = url.host_str().ok_or_else(|| anyhow!("Pay URL has no host"))?;
+    let linking_key = || ctx.linking_key(domain);
+    let mut data = serde_json::Map::new();
+    for (name, field) in requested {
+        let value = match name.as_str() {
+            "name" => args.payer_name.clone().map(serde_json::Value::from),
+            "email" => args.payer_email.clone().map(serde_json::Value::from),
+            "identifier" => args.payer_identifier.clone().map(serde_json::Value::from),
+            "pubkey" if args.payer_pubkey => Some(keys::public_hex(&linking_key()?).into()),
+            "auth" if args.payer_auth => {
+                let k1 = field.k1.as_deref().ok_or_else(|| anyhow!("payerData auth has no k1"))?;
+                ctx.check_k1(url, k1, field)?;
+                let key = linking_key()?;
+                let signature = keys::sign_k1(&key, &hex::decode(k1).context("payerData k1 is not hex")?)?;
+                Some(serde_json::json!({
+                    "key": keys::public_hex(&key),
+                    "k1": k1,
+                    "sig": hex::encode(signature.serialize_der()),
+                }))
+            }
+            "pubkey" | "auth" => None,
+            _ if field.mandatory => return Err(LnurlError::invalid(format!("Service requires payer data '{}', which is not supported", name)).into()),
+            _ => None,
+        };
+        match value {
+            Some(value) => {
+                data.insert(name.clone(), value);
+            }
+            None if field.mandatory => {
+                return Err(LnurlError::invalid(format!("Service requires payer data '{}'; pass --payer-{}", name, name)).into());
+            }
+            None => {}
+        }
+    }
+    let offered = [
+        ("name", args.payer_name.is_some()),
+        ("email", args.payer_email.is_some()),
+        ("identifier", args.payer_identifier.is_some()),
+        ("pubkey", args.payer_pubkey),
+        ("auth", args.payer_auth),
+    ];
+    for (name, given) in offered {
+        if given && !requested.contains_key(name) {
+            info!("Service doesn't ask for payer {}, not sending it", name);
+        }
+    }
+    if data.is_empty() {
+        return Ok(None);
+    }
+    let names = data.keys().cloned().collect();
+    Ok(Some((serde_json::Value::Object(data).to_string(), names)))
+}
+
+/// Decodes `bolt11` with the node and checks that it is for `amount_msat` and
+/// commits to `metadata` (LUD-06; followed by the payer data sent, LUD-18), so
+/// the service can't swap the invoice.
+fn check_pay_invoice(
+    node: &mut dyn backend::NodeBackend,
+    rt: &tokio::runtime::Runtime,
+    bolt11: &str,
+    amount_msat: u64,
+    metadata: &str,
+) -> Result<String> {
+    let decoded = rt.block_on(node.decode_invoice(bolt11)).map_err(LnurlError::node)?;
+    if decoded.amount_msat != Some(amount_msat) {
+        return Err(LnurlError::invalid(format!(
+            "Invoice is for {} msat, expected {} msat",
+            decoded.amount_msat.map_or("any".to_string(), |a| a.to_string()),
+            amount_msat
+        ))
+        .into());
+    }
+    let expected_hash = hex::encode(sha2::Sha256::digest(metadata.as_bytes()));
+    if decoded.description_hash.as_deref() != Some(expected_hash.as_str()) {
+        return Err(LnurlError::invalid("Invoice description hash does not match the payRequest metadata (and payer data)").into());
+    }
+    Ok(decoded.payment_hash)
+}
+
+/// A payment the node made (or the service's verify URL confirmed).
+#[derive(Debug, Clone, Serialize)]
+pub struct Payment {
+    pub amount_msat: u64,
+    pub bolt11: String,
+    pub payment_hash: String,
+    pub preimage: Option<String>,
+    /// Routing fee, when the node reported what it sent.
+    pub fee_msat: Option<u64>,
+    /// Settlement was confirmed by the service's verify URL (LUD-21).
+    pub verified: bool,
+    /// Payer data fields sent (LUD-18), e.g. `name`.
+    pub disclosed: Vec<String>,
+    pub success_action: Option<lnurl::success::SuccessAction>,
+    /// Plaintext of an `aes` success action.
+    pub success_secret: Option<String>,
+}
+
+/// LNURL-pay flow: get pay params, request an invoice for the amount from the
+/// callback, check it, then pay it with the node.
+pub(crate) fn pay_request(ctx: &LnurlClient, url: &lnurl::Target, amount: amount::AmountArg, extra: &PayArgs) -> Result<Payment> {
+    ctx.trust(url);
+    // Pay links are usually full endpoint URLs (e.g. /lnurlp/<name>), so only
+    // a bare server gets the default path.
+    let request_url = if url.path() == "/" && url.query().is_none() {
+        url.endpoint("request-pay")
+    } else {
+        url.to_string()
+    };
+    info!("Requesting pay info from {}...", request_url);
+    let resp: PayRequestResponse = ctx.fetch_json(&request_url)?;
+
+    info!("Received pay request:");
+    info!("  Callback: {}", resp.callback);
+    info!("  Min sendable: {}", ctx.fmt_msat(resp.min_sendable));
+    info!("  Max sendable: {}", ctx.fmt_msat(resp.max_sendable));
+    let metadata = match lnurl::metadata::Metadata::parse(&resp.metadata) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            warnings::raise("malformed-metadata", warnings::Severity::Medium, format!("{}: {:#}", url, e))?;
+            lnurl::metadata::Metadata::default()
+        }
+    };
+    if let Some(description) = &metadata.description {
+        info!("  Description: {}", description);
+    }
+    if let Some(long_description) = &metadata.long_description {
+        info!("  Details: {}", long_description);
+    }
+    if let Some(identifier) = &metadata.identifier {
+        info!("  Paying: {}", identifier);
+    }
+    if let Some((mime, size)) = &metadata.image {
+        info!("  Image: {}, {} bytes", mime, size);
+    }
+    if resp.comment_allowed > 0 {
+        info!("  Comments: up to {} characters", resp.comment_allowed);
+    }
+    if let Some(withdraw_link) = &resp.withdraw_link {
+        info!("  Withdraw link: {}", withdraw_link);
+    }
+    // Links are one-time unless the service says otherwise.
+    let disposable = resp.disposable != Some(false);
+    if !disposable {
+        info!("  Reusable: yes (saved for pay-links)");
+    }
+    if let Some(previous) = paylinks::get(&request_url).filter(|p| p.disposable && disposable) {
+        warn!(
+            "this pay link is disposable and was already paid {} time(s), last on {}; it may not be meant for reuse",
+            previous.uses, previous.last_used
+        );
+    }
+    ctx.check_tag(url, &resp.tag, "payRequest")?;
+    ctx.check_callback(url, &resp.callback)?;
+
+    let amount_msat = ctx.amount_msat(amount, resp.max_sendable)?;
+    if amount_msat < resp.min_sendable || amount_msat > resp.max_sendable {
+        return Err(LnurlError::invalid(format!(
+            "Amount {} is outside allowed range [{}, {}]",
+            amount::show(amount_msat),
+            amount::show(resp.min_sendable),
+            amount::show(resp.max_sendable)
+        ))
+        .into());
+    }
+
+    let mut params = format!("amount={}", amount_msat);
+    if let Some(comment) = &extra.comment {
+        if resp.comment_allowed == 0 {
+            return Err(LnurlError::invalid("Service doesn't accept comments (no commentAllowed)").into());
+        }
+        let length = comment.chars().count();
+        if length > resp.comment_allowed {
+            return Err(LnurlError::invalid(format!(
+                "Comment is {} characters, the service accepts at most {}",
+                length,
+                resp.comment_allowed
+            ))
+            .into());
+        }
+        params.push_str(&format!("&comment={}", urlencoding::encode(comment)));
+    }
+    let disclosed = match &resp.payer_data {
+        Some(requested) => payer_data(ctx, url, requested, extra)?,
+        None => None,
+    };
+    if let Some((json, _)) = &disclosed {
+        params.push_str(&format!("&payerdata={}", urlencoding::encode(json)));
+    }
+
+    let callback = lnurl::callback_url(&resp.callback, &params);
+    ctx.stop_if_dry_run("pay callback", &callback)?;
+    info!("Requesting invoice for {}...", ctx.fmt_msat(amount_msat));
+    let invoice: PayCallbackResponse = ctx.get_json(&callback)?;
+    if let Some(action) = &invoice.success_action {
+        action.check(&resp.callback)?;
+    }
+    let bolt11 = match invoice.pr {
+        Some(pr) if invoice.status.as_deref() != Some("ERROR") => pr,
+        _ => {
+            let reason = invoice.reason.unwrap_or_else(|| "Unknown error".to_string());
+            return Err(LnurlError::rejected(format!("Service refused to issue an invoice: {}", reason), reason).into());
+        }
+    };
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to create Tokio runtime")?;
+    let mut node = open_backend(ctx, &rt)?;
+    let committed = match &disclosed {
+        Some((json, _)) => format!("{}{}", resp.metadata, json),
+        None => resp.metadata.clone(),
+    };
+    let payment_hash = check_pay_invoice(node.as_mut(), &rt, &bolt11, amount_msat, &committed)?;
+    ctx.confirm(
+        "payment",
+        &[
+            ("Amount", ctx.fmt_msat(amount_msat)),
+            ("Service", url.host_str().unwrap_or_default().to_string()),
+            ("Invoice", format!("{}...", &bolt11[..50.min(bolt11.len())])),
+        ],
+    )?;
+
+    info!("Paying invoice {}...", &bolt11[..50.min(bolt11.len())]);
+    let mut op = history::Operation::new("pay", url, &ctx.annotations)
+        .detail("bolt11", bolt11.as_str())
+        .detail("payment_hash", payment_hash.as_str());
+    op.amount_msat = Some(amount_msat);
+    if let Some(comment) = &extra.comment {
+        op = op.detail("comment", comment.as_str());
+    }
+    if let Some((json, _)) = &disclosed {
+        op = op.detail("payer_data", json.as_str());
+    }
+    let mut preimage = None;
+    let mut fee_msat = None;
+    match rt.block_on(node.pay_invoice(&bolt11)) {
+        Ok(payment) => {
+            op.status = "OK".to_string();
+            preimage = payment.preimage;
+            fee_msat = payment.amount_sent_msat.map(|sent| sent.saturating_sub(amount_msat));
+            if let Some(fee) = fee_msat {
+                op = op.detail("fee_msat", fee.to_string());
+            }
+        }
+        Err(e) => {
+            op.status = "ERROR".to_string();
+            op.reason = Some(e.to_string());
+        }
+    }
+    // The service's word that the invoice settled also counts when the node
+    // RPC failed or timed out before reporting it.
+    let mut verified = false;
+    if let Some(verify) = invoice.verify.as_deref().filter(|_| extra.verify_timeout > 0) {
+        ctx.check_callback(url, verify)?;
+        info!("Checking settlement with {}...", verify);
+        match verify_payment(ctx, verify, &payment_hash, Duration::from_secs(extra.verify_timeout)) {
+            Ok(Some(settled)) => {
+                info!("Service confirms the payment settled.");
+                op.status = "OK".to_string();
+                op.reason = None;
+                op = op.detail("verified", "true");
+                verified = true;
+                preimage.get_or_insert(settled);
+            }
+            Ok(None) => warn!("{} did not report the payment settled within {}s", verify, extra.verify_timeout),
+            Err(e) => warn!("Failed to verify the payment: {:#}", e),
+        }
+    }
+    if let Some(preimage) = &preimage {
+        op = op.detail("preimage", preimage.as_str());
+    }
+    // Plaintext of an aes successAction, decrypted once the preimage is known.
+    let mut secret = None;
+    if let Some(action) = invoice.success_action.as_ref().filter(|_| op.status == "OK") {
+        op = op.detail("success_action", action.tag()).detail("success_text", action.to_string());
+        if let Some(preimage) = &preimage {
+            // The payment is done; a secret that doesn't decrypt is only worth a warning.
+            match action.decrypt(preimage) {
+                Ok(decrypted) => secret = decrypted,
+                Err(e) => warn!("Failed to decrypt successAction: {:#}", e),
+            }
+        }
+        if let Some(plaintext) = &secret {
+            op = op.detail("success_secret", plaintext.as_str());
+        }
+    }
+    let status = op.status.clone();
+    let reason = op.reason.clone();
+    record_operation(ctx, Some(node.as_mut()), &rt, op);
+    if status != "OK" {
+        return Err(LnurlError::node(format!("Payment failed: {}", reason.unwrap_or_default())).into());
+    }
+
+    if let Err(e) = paylinks::record(&request_url, disposable, metadata.description.clone(), &resp.metadata) {
+        warn!("failed to remember the pay link: {:#}", e);
+    }
+
+    Ok(Payment {
+        amount_msat,
+        bolt11,
+        payment_hash,
+        preimage,
+        fee_msat,
+        verified,
+        disclosed: disclosed.map(|(_, names)| names).unwrap_or_default(),
+        success_action: invoice.success_action,
+        success_secret: secret,
+    })
+}
+
+/// The reusable pay links saved by `pay`, by URL.
+pub fn pay_links_list() -> BTreeMap<String, paylinks::PayLink> {
+    paylinks::load().into_iter().filter(|(_, link)| !link.disposable).collect()
+}
+
+// -----------------------------------------------------------------------------
+// Auth request (LNURL-auth style: challenge + signed response)
+// -----------------------------------------------------------------------------
+
+/// Response from the auth callback (status and optional reason).
+#[derive(Debug, Deserialize)]
+struct AuthResponse {
+    status: String,
+    reason: Option<String>,
+}
+
+/// Parses the k1 challenge from the server response.
+/// Accepts either a JSON object `{"k1":"<hex>"}` or a raw hex string (e.g. from GET /auth-challenge).
+fn parse_k1_from_challenge(body: &str) -> Result<String> {
+    let body = body.trim();
+    if body.starts_with('{') {
+        #[derive(Deserialize)]
+        struct K1Response { k1: String }
+        Ok(serde_json::from_str::<K1Response>(body).map_err(|e| LnurlError::json("/auth-challenge", e))?.k1)
+    } else {
+        Ok(body.to_string())
+    }
+}
+
+/// LNURL-auth style flow: GET /auth-challenge for k1, sign k1 with the local node
+/// (CLN signmessage), then GET /auth-response with k1, signature (zbase from CLN), and pubkey.
+/// The server verifies the signature (e.g. via CLN checkmessage).
+pub(crate) fn auth_request(ctx: &LnurlClient, url: &lnurl::Target) -> Result<()> {
+    let base = url.as_str().trim_end_matches('/');
+    info!("Requesting auth challenge from {}...", base);
+    ctx.trust(url);
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to create Tokio runtime")?;
+    let mut node = open_backend(ctx, &rt)?;
+
+    let pubkey = rt.block_on(node.get_local_node_id()).map_err(LnurlError::node)?;
+    info!("Node pubkey: {}", pubkey);
+
+    let challenge_url = format!("{}/auth-challenge", base);
+    let body = ctx.fetch_text(&challenge_url)?;
+    let k1 = parse_k1_from_challenge(&body)?;
+    info!("Received k1: {}", k1);
+    ctx.check_k1(url, &k1, &body)?;
+
+    info!("Signing challenge...");
+    let sign_request = cln_rpc::model::requests::SignmessageRequest {
+        message: k1.clone(),
+    };
+    let ln_client = cln_client(node.as_mut(), "sign auth challenges")?;
+    let sign_response = rt.block_on(ln_client.call(cln_rpc::Request::SignMessage(sign_request))).map_err(LnurlError::node)?;
+    // Use CLN's zbase field directly; server expects this format (e.g. for checkmessage).
+    let signature = match sign_response {
+        cln_rpc::model::Response::SignMessage(r) => r.zbase.to_string(),
+        _ => return Err(anyhow!("Unexpected response type from signmessage")),
+    };
+    info!("Signature (zbase): {}...", &signature[..signature.len().min(24)]);
+
+    let response_url = format!(
+        "{}/auth-response?k1={}&signature={}&pubkey={}",
+        base,
+        urlencoding::encode(&k1),
+        urlencoding::encode(&signature),
+        urlencoding::encode(&pubkey)
+    );
+    ctx.stop_if_dry_run("auth response", &response_url)?;
+    info!("Submitting auth response...");
+
+    let http_resp = ctx.http_get(&response_url);
+    let mut op = history::Operation::new("auth", url, &ctx.annotations).detail("pubkey", pubkey.as_str());
+    let auth_resp = match http_resp {
+        Ok((200..=299, body)) => serde_json::from_str::<AuthResponse>(&body).map_err(|e| LnurlError::json(base, e))?,
+        Ok((code, body)) => {
+            op.status = format!("HTTP {}", code);
+            op.reason = Some(body.clone());
+            record_operation(ctx, Some(node.as_mut()), &rt, op);
+            return Err(LnurlError::http(Some(code), format!("Auth response failed (HTTP {}): {}", code, body)).into());
+        }
+        Err(e) => return Err(e.context("Auth request failed")),
+    };
+    op.status = auth_resp.status.clone();
+    op.reason = auth_resp.reason.clone();
+    record_operation(ctx, Some(node.as_mut()), &rt, op);
+
+    if auth_resp.status == "OK" {
+        info!("Authentication successful!");
+    } else {
+        let reason = auth_resp.reason.unwrap_or_else(|| "Unknown error".to_string());
+        return Err(LnurlError::rejected(format!("Authentication failed: {}", reason), reason).into());
+    }
+
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------
+// LNURL-auth login (LUD-04/05: linking key per domain)
+// -----------------------------------------------------------------------------
+
+/// Signs the k1 of a login URL with the domain's linking key and calls the
+/// URL back with `sig` and `key`.
+fn login(ctx: &LnurlClient, url: &lnurl::Target) -> Result<()> {
+    ctx.trust(url);
+    let param = |name: &str| url.query_pairs().find(|(k, _)| k == name).map(|(_, v)| v.into_owned());
+    if param("tag").as_deref() != Some("login") {
+        return Err(LnurlError::invalid(format!("Not an LNURL-auth URL (tag=login missing): {}", url)).into());
+    }
+    let k1 = param("k1").ok_or_else(|| anyhow!("LNURL-auth URL has no k1"))?;
+    ctx.check_k1(url, &k1, &url.as_str())?;
+    let challenge = hex::decode(&k1).context("k1 is not hex")?;
+    let domain = url.host_str().ok_or_else(|| anyhow!("LNURL-auth URL has no host"))?;
+    let action = param("action").unwrap_or_else(|| "login".to_string());
+
+    let key = ctx.linking_key(domain)?;
+    let public = keys::public_hex(&key);
+    let signature = keys::sign_k1(&key, &challenge)?;
+    info!("Signing {} challenge for {} with linking key {}...", action, domain, public);
+
+    let callback = lnurl::callback_url(
+        url.as_str(),
+        &format!("sig={}&key={}", hex::encode(signature.serialize_der()), public),
+    );
+    ctx.stop_if_dry_run(&format!("{} callback", action), &callback)?;
+    let http_resp = ctx.http_get(&callback);
+    let mut op = history::Operation::new("auth", url, &ctx.annotations)
+        .detail("linking_key", public.as_str())
+        .detail("action", action.as_str());
+    if ctx.auth_key == keys::KeySource::Node {
+        op = op.detail("key_source", "node");
+    }
+    let mut http_status = None;
+    let result = match http_resp {
+        Ok((200..=299, body)) => serde_json::from_str::<AuthResponse>(&body)
+            .map_err(|e| LnurlError::json(domain, e).into()),
+        Ok((code, body)) => {
+            http_status = Some(code);
+            Ok(AuthResponse {
+                status: format!("HTTP {}", code),
+                reason: Some(body),
+            })
+        }
+        Err(e) => Err(e.context("Auth request failed")),
+    };
+    let auth_resp = match result {
+        Ok(r) => r,
+        Err(e) => {
+            op.status = "ERROR".to_string();
+            op.reason = Some(format!("{:#}", e));
+            record_history(ctx, &mut op);
+            return Err(e);
+        }
+    };
+    op.status = auth_resp.status.clone();
+    op.reason = auth_resp.reason.clone();
+    record_history(ctx, &mut op);
+
+    if auth_resp.status != "OK" {
+        let reason = auth_resp.reason.unwrap_or_else(|| "Unknown error".to_string());
+        let message = format!("{} refused the {}: {}", domain, action, reason);
+        return Err(match http_status {
+            Some(code) => LnurlError::http(Some(code), message),
+            None => LnurlError::rejected(message, reason),
+        }
+        .into());
+    }
+    info!("Logged in to {} ({}).", domain, action);
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------
+// Open (dispatch an LNURL to its flow)
+// -----------------------------------------------------------------------------
+
+/// The flow `open` ran, with its result where it has one.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "flow", content = "result", rename_all = "snake_case")]
+pub enum Opened {
+    Pay(Payment),
+    Withdraw(Withdrawal),
+    Channel(ChannelOutcome),
+    Login,
+}
+
+/// Runs the flow `url` is for. The protocol comes from the LUD-17 scheme or
+/// `tag=login` when present; otherwise the endpoint is fetched and its `tag`
+/// decides (the flow then fetches it again for fresh parameters).
+fn open_target(
+    ctx: &LnurlClient,
+    url: &lnurl::Target,
+    amount: Option<amount::AmountArg>,
+    description: Option<String>,
+    extra: &PayArgs,
+) -> Result<Opened> {
+    let kind = match url.kind {
+        Some(kind) => kind,
+        None if !url.lnurl => {
+            return Err(anyhow!(
+                "{} is a server address, not an LNURL; use the command for the flow (pay, request-withdraw, ...)",
+                url
+            ))
+        }
+        None => {
+            ctx.trust(url);
+            let params: serde_json::Value = ctx.fetch_json(url.as_str())?;
+            let tag = params["tag"].as_str().unwrap_or("");
+            lnurl::Kind::from_tag(tag).ok_or_else(|| anyhow!("{} answered with unsupported tag '{}'", url, tag))?
+        }
+    };
+    info!("{} is an LNURL-{} link.", url, kind.name());
+    match kind {
+        lnurl::Kind::Pay => {
+            let amount = amount.ok_or_else(|| LnurlError::invalid("Paying needs an amount"))?;
+            pay_request(ctx, url, amount, extra).map(Opened::Pay)
+        }
+        lnurl::Kind::Withdraw => {
+            withdraw_request(ctx, url, amount.unwrap_or(amount::AmountArg::Percent(100.0)), description).map(Opened::Withdraw)
+        }
+        lnurl::Kind::Channel => channel_request(ctx, url).map(Opened::Channel),
+        lnurl::Kind::Login => login(ctx, url).map(|()| Opened::Login),
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Audit log verification
+// -----------------------------------------------------------------------------
+
+/// Verifies the audit log hash chain, and checks node signatures with CLN
+/// `checkmessage` when any entry is signed: on the `node` socket for a Core
+/// Lightning backend, the default one otherwise.
+pub fn audit_verify(node: &backend::Config) -> Result<()> {
+    let path = audit::audit_log_path();
+    info!("Verifying audit log {}...", path.display());
+
+    let entries = audit::verify_chain()?;
+    for entry in &entries {
+        info!(
+            "  #{} {} {} {} {}",
+            entry.seq,
+            entry.timestamp,
+            entry.operation,
+            entry.amount_msat.map(amount::show).unwrap_or_else(|| "-".to_string()),
+            entry.status
+        );
+    }
+
+    let signed: Vec<&audit::AuditEntry> = entries.iter().filter(|e| e.signature.is_some()).collect();
+    if !signed.is_empty() {
+        info!("Checking {} node signature(s)...", signed.len());
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Failed to create Tokio runtime")?;
+        let cln_rpc_path = match node {
+            backend::Config::Cln { socket } => socket.clone(),
+            _ => get_cln_rpc_path(),
+        };
+        let mut ln_client = rt.block_on(cln_rpc::ClnRpc::new(&cln_rpc_path))?;
+
+        for entry in signed {
+            let pubkey = match &entry.pubkey {
+                Some(p) => Some(cln_rpc::primitives::PublicKey::from_str(p)?),
+                None => None,
+            };
+            let check_request = cln_rpc::model::requests::CheckmessageRequest {
+                pubkey,
+                message: entry.hash.clone(),
+                zbase: entry.signature.clone().unwrap_or_default(),
+            };
+            let check_response = rt.block_on(ln_client.call(cln_rpc::Request::CheckMessage(check_request)))?;
+            match check_response {
+                cln_rpc::model::Response::CheckMessage(r) if r.verified => {}
+                cln_rpc::model::Response::CheckMessage(_) => {
+                    return Err(anyhow!("Entry {} has an invalid signature", entry.seq));
+                }
+                _ => return Err(anyhow!("Unexpected response type from checkmessage")),
+            }
+        }
+    }
+
+    info!("Audit log OK: {} entries.", entries.len());
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------
+// Exchange rates
+// -----------------------------------------------------------------------------
+
+/// The current BTC price in `currency` from the configured source.
+fn show_rate(ctx: &LnurlClient, currency: &str) -> Result<rates::Rate> {
+    ctx.rate_provider()?.get(&ctx.agent, currency)
+}
+
+// -----------------------------------------------------------------------------
+// History
+// -----------------------------------------------------------------------------
+
+/// The recorded operations matching `query`.
+pub fn history_list(query: &history::Query) -> Result<Vec<history::Operation>> {
+    let all = history::load()?;
+    if all.is_empty() {
+        info!("No operations recorded in {}", history::history_path().display());
+        return Ok(Vec::new());
+    }
+    Ok(history::query(all, query))
+}
+
+/// The history as beancount/ledger transactions.
+pub fn history_export(format: history::ExportFormat, accounts: &history::Accounts, tags: &[String]) -> Result<String> {
+    let ops: Vec<history::Operation> = history::load()?
+        .into_iter()
+        .filter(|op| op.has_tags(tags))
+        .collect();
+    history::export(&ops, format, accounts)
+}
+
+/// Asks the node that created the invoice of a withdraw operation for its
+/// current state (status, paid_at, payment_preimage, ...): the fallback
+/// recorded with the operation, otherwise the client's node.
+fn lookup_invoice(ctx: &LnurlClient, op: &history::Operation) -> Result<Option<serde_json::Value>> {
+    let Some(label) = op.details.get("label") else {
+        return Ok(None);
+    };
+    let node = match op.details.get("backend") {
+        Some(name) if name != ctx.node.name() => get_cln_rpc_fallbacks()
+            .into_iter()
+            .map(|socket| backend::Config::Cln { socket })
+            .find(|node| node.name() == name)
+            .ok_or_else(|| anyhow!("The invoice was made on {}, which is no longer a fallback backend", name))?,
+        _ => ctx.node.clone(),
+    };
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to create Tokio runtime")?;
+    let mut node = rt.block_on(node.open())?;
+    rt.block_on(node.lookup_invoice(label))
+}
+
+/// Builds a JSON bundle with everything known about operation `id`: the
+/// history record (original URL, HTTP exchanges, invoice, txid, timestamps),
+/// the invoice state and preimage from the node, and the matching audit entry.
+/// It is also written to `output` when given.
+fn history_proof(ctx: &LnurlClient, id: &str, output: Option<&std::path::Path>) -> Result<serde_json::Value> {
+    let op = history::find(id)?;
+
+    let invoice = lookup_invoice(ctx, &op).unwrap_or_else(|e| {
+        warn!("could not query the node for the invoice: {:#}", e);
+        None
+    });
+    let audit_entry = match audit::verify_chain() {
+        Ok(entries) => {
+            let detail = audit_detail(&op);
+            entries.into_iter().find(|e| e.operation == op.kind && e.url == op.url && e.detail == detail)
+        }
+        Err(e) => {
+            warn!("audit log does not verify, leaving it out: {:#}", e);
+            None
+        }
+    };
+
+    let bundle = serde_json::json!({
+        "generated_at": chrono::Utc::now().to_rfc3339(),
+        "client": format!("lnurl-client {}", env!("CARGO_PKG_VERSION")),
+        "operation": op,
+        "invoice": invoice,
+        "audit_entry": audit_entry,
+    });
+    if let Some(path) = output {
+        let text = serde_json::to_string_pretty(&bundle)?;
+        std::fs::write(path, text + "\n").with_context(|| format!("Failed to write {}", path.display()))?;
+        info!("Proof bundle for {} written to {}", id, path.display());
+    }
+    Ok(bundle)
+}
+
+// -----------------------------------------------------------------------------
+// Monitor (health checks of LNURL services)
+// -----------------------------------------------------------------------------
+
+/// Collects the targets from the command line and `--targets-file`, then runs the monitor.
+fn monitor(
+    ctx: &LnurlClient,
+    mut targets: Vec<Url>,
+    targets_file: Option<PathBuf>,
+    interval: u64,
+    rounds: Option<u64>,
+    metrics_file: Option<PathBuf>,
+    notify_url: Option<String>,
+) -> Result<()> {
+    if let Some(path) = &targets_file {
+        targets.extend(monitor::read_targets(path)?);
+    }
+    for url in &targets {
+        ctx.trust(url);
+    }
+    if let Some(host) = notify_url.as_deref().and_then(|u| Url::parse(u).ok()).and_then(|u| u.host_str().map(|h| h.to_string())) {
+        ctx.net.trusted.add(&host);
+    }
+    let config = monitor::MonitorConfig {
+        targets,
+        interval: Duration::from_secs(interval.max(1)),
+        rounds,
+        metrics_file,
+        notify_url,
+    };
+    monitor::run(&ctx.agent, &ctx.net.pinned_keys, &config)
+}
+
+// -----------------------------------------------------------------------------
+// Known TLS hosts
+// -----------------------------------------------------------------------------
+
+/// The trust-on-first-use store, by host.
+pub fn known_hosts_list() -> BTreeMap<String, tofu::KnownHost> {
+    let hosts = tofu::load();
+    if hosts.is_empty() {
+        info!("No TLS keys recorded in {}", tofu::known_hosts_path().display());
+    }
+    hosts
+}
+
+/// Replaces the key of `host` in the trust-on-first-use store with the new
+/// one it presented.
+pub fn known_hosts_accept(host: &str) -> Result<()> {
+    let mut hosts = tofu::load();
+    let known = hosts
+        .get_mut(host)
+        .ok_or_else(|| anyhow!("{} is not in {}", host, tofu::known_hosts_path().display()))?;
+    let pending = known.pending.take().ok_or_else(|| anyhow!("{} hasn't presented a new key", host))?;
+    known.key = pending;
+    known.first_seen = chrono::Utc::now().to_rfc3339();
+    let key = known.key.clone();
+    tofu::save(&hosts)?;
+    info!("Accepted {} for {}.", key, host);
+    Ok(())
+}
+
+/// Removes `host` from the trust-on-first-use store.
+pub fn known_hosts_forget(host: &str) -> Result<()> {
+    let mut hosts = tofu::load();
+    if hosts.remove(host).is_none() {
+        return Err(anyhow!("{} is not in {}", host, tofu::known_hosts_path().display()));
+    }
+    tofu::save(&hosts)?;
+    info!("Forgot the key of {}; the next one seen will be trusted.", host);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_a_single_voucher() {
+        assert_eq!(plan_vouchers(&[(1_000, 50_000)], 20_000), Some(vec![20_000]));
+        assert_eq!(plan_vouchers(&[(1_000, 50_000)], 50_000), Some(vec![50_000]));
+        assert_eq!(plan_vouchers(&[(1_000, 50_000)], 60_000), None);
+        assert_eq!(plan_vouchers(&[(30_000, 50_000)], 20_000), None);
+    }
+
+    #[test]
+    fn moves_amounts_to_meet_a_minimum() {
+        // 45k from the first leaves 5k, below the second's 10k minimum.
+        assert_eq!(plan_vouchers(&[(1_000, 45_000), (10_000, 20_000)], 50_000), Some(vec![40_000, 10_000]));
+        // Without room above the first's minimum, the second is skipped.
+        assert_eq!(plan_vouchers(&[(45_000, 45_000), (10_000, 20_000), (1_000, 5_000)], 50_000), Some(vec![45_000, 0, 5_000]));
+        assert_eq!(plan_vouchers(&[(45_000, 45_000), (10_000, 20_000)], 50_000), None);
+    }
+
+    #[test]
+    fn refuses_a_target_above_all_maxima() {
+        assert_eq!(plan_vouchers(&[(1_000, 20_000), (1_000, 30_000)], 50_000), Some(vec![20_000, 30_000]));
+        assert_eq!(plan_vouchers(&[(1_000, 20_000), (1_000, 30_000)], 50_001), None);
+        // Inverted limits can't be redeemed and don't count.
+        assert_eq!(plan_vouchers(&[(1_000, 20_000), (40_000, 30_000)], 30_000), None);
+    }
+
+    fn client(net: net::NetConfig) -> LnurlClient {
+        let net = net::NetConfig { tls_tofu: false, ..net };
+        LnurlClient::builder(backend::Config::Cln { socket: "/nonexistent".into() }).net(net).build().unwrap()
+    }
+
+    #[test]
+    fn checks_callbacks() {
+        let origin = Url::parse("https://service.example/lnurl").unwrap();
+        let ctx = client(net::NetConfig::default());
+        assert!(ctx.check_callback(&origin, "https://service.example:8443/cb").is_ok());
+        assert!(ctx.check_callback(&origin, "https://pay.other.example/cb").is_ok());
+        assert!(ctx.check_callback(&origin, "ftp://service.example/cb").is_err());
+        assert!(ctx.check_callback(&origin, "file:///etc/passwd").is_err());
+        assert!(ctx.check_callback(&origin, "not a url").is_err());
+        assert!(ctx.check_callback(&origin, "https://other.example:8443/cb").is_err());
+        let ports = client(net::NetConfig { callback_ports: vec![8443], ..net::NetConfig::default() });
+        assert!(ports.check_callback(&origin, "https://other.example:8443/cb").is_ok());
+    }
+
+    #[test]
+    fn checks_callback_origins() {
+        let origin = Url::parse("https://service.example/lnurl").unwrap();
+        let strict = client(net::NetConfig {
+            same_origin: net::SameOrigin::Strict,
+            delegate_domains: vec!["payments.example".into()],
+            ..net::NetConfig::default()
+        });
+        assert!(strict.check_callback(&origin, "https://service.example/cb").is_ok());
+        assert!(strict.check_callback(&origin, "https://other.example/cb").is_err());
+        assert!(strict.check_callback(&origin, "https://api.payments.example/cb").is_ok());
+    }
+
+    /// Answers `/start` with a redirect to `/next`, `/ftp` with one to an
+    /// ftp URL and `/loop` with one to itself.
+    struct Redirects;
+
+    impl net::HttpTransport for Redirects {
+        fn get(&self, url: &str, _timeout: Duration, _headers: &[(&str, &str)]) -> Result<net::HttpResponse> {
+            let location = match Url::parse(url)?.path() {
+                "/start" => Some("/next"),
+                "/ftp" => Some("ftp://service.example/next"),
+                "/loop" => Some("/loop"),
+                _ => None,
+            };
+            Ok(net::HttpResponse {
+                status: if location.is_some() { 302 } else { 200 },
+                signature: None,
+                location: location.map(|l| l.to_string()),
+                etag: None,
+                last_modified: None,
+                body: url.to_string(),
+            })
+        }
+    }
+
+    #[test]
+    fn checks_each_redirect() {
+        let net = net::NetConfig { tls_tofu: false, ..net::NetConfig::default() };
+        let ctx = LnurlClient::builder(backend::Config::Cln { socket: "/nonexistent".into() })
+            .net(net)
+            .http(Redirects)
+            .build()
+            .unwrap();
+        assert_eq!(ctx.http_get("https://service.example/start").unwrap(), (200, "https://service.example/next".to_string()));
+        assert!(ctx.http_get("https://service.example/ftp").is_err());
+        assert!(ctx.http_get("https://service.example/loop").unwrap_err().to_string().contains("redirects"));
+    }
+}
@@ -0,0 +1,90 @@
+//! The system clipboard (`--clipboard`), through the platform's command line
+//! tools: `pbcopy`/`pbpaste` on macOS, PowerShell on Windows, and on Linux
+//! `wl-copy`/`wl-paste` under Wayland, otherwise `xclip` or `xsel`.
+//!
+//! The tools keep what was copied after this process exits, which a
+//! clipboard owned by the process itself (as on X11) would not.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Commands that print the clipboard, in the order tried.
+fn paste_commands() -> Vec<&'static [&'static str]> {
+    if cfg!(target_os = "macos") {
+        vec![&["pbpaste"]]
+    } else if cfg!(windows) {
+        vec![&["powershell", "-NoProfile", "-Command", "Get-Clipboard"]]
+    } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        vec![&["wl-paste", "--no-newline"], &["xclip", "-selection", "clipboard", "-o"], &["xsel", "--clipboard", "--output"]]
+    } else {
+        vec![&["xclip", "-selection", "clipboard", "-o"], &["xsel", "--clipboard", "--output"]]
+    }
+}
+
+/// Commands that set the clipboard to their stdin, in the order tried.
+fn copy_commands() -> Vec<&'static [&'static str]> {
+    if cfg!(target_os = "macos") {
+        vec![&["pbcopy"]]
+    } else if cfg!(windows) {
+        vec![&["clip"]]
+    } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        vec![&["wl-copy"], &["xclip", "-selection", "clipboard"], &["xsel", "--clipboard", "--input"]]
+    } else {
+        vec![&["xclip", "-selection", "clipboard"], &["xsel", "--clipboard", "--input"]]
+    }
+}
+
+fn missing_tool(commands: &[&[&str]]) -> anyhow::Error {
+    let names: Vec<&str> = commands.iter().map(|command| command[0]).collect();
+    anyhow!("No clipboard tool found (tried {})", names.join(", "))
+}
+
+/// The text on the clipboard, trimmed.
+pub fn read() -> Result<String> {
+    let commands = paste_commands();
+    for command in &commands {
+        let output = match Command::new(command[0]).args(&command[1..]).stderr(Stdio::null()).output() {
+            Ok(output) => output,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to run {}", command[0])),
+        };
+        if !output.status.success() {
+            bail!("{} failed to read the clipboard ({})", command[0], output.status);
+        }
+        let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if text.is_empty() {
+            bail!("The clipboard is empty");
+        }
+        log::debug!("read {} bytes from the clipboard with {}", text.len(), command[0]);
+        return Ok(text);
+    }
+    Err(missing_tool(&commands))
+}
+
+/// Puts `text` on the clipboard.
+pub fn write(text: &str) -> Result<()> {
+    let commands = copy_commands();
+    for command in &commands {
+        let mut child = match Command::new(command[0])
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to run {}", command[0])),
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes()).with_context(|| format!("Failed to write to {}", command[0]))?;
+        }
+        let status = child.wait().with_context(|| format!("Failed to run {}", command[0]))?;
+        if !status.success() {
+            bail!("{} failed to set the clipboard ({})", command[0], status);
+        }
+        return Ok(());
+    }
+    Err(missing_tool(&commands))
+}
@@ -0,0 +1,63 @@
+//! Colored statuses on the console: green `OK`, red errors, yellow
+//! warnings.
+//!
+//! Off unless the CLI turns them on, separately for stdout and stderr, for
+//! those that are terminals; `--no-color` and a non-empty `NO_COLOR`
+//! (<https://no-color.org>) keep them off. The log file, `--json` documents
+//! and plugin notifications are never colored.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static STDOUT: AtomicBool = AtomicBool::new(false);
+static STDERR: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Color {
+    Green,
+    Red,
+    Yellow,
+}
+
+pub fn enable(stdout: bool, stderr: bool) {
+    STDOUT.store(stdout, Ordering::Relaxed);
+    STDERR.store(stderr, Ordering::Relaxed);
+}
+
+/// Whether `NO_COLOR` asks for no colors.
+pub fn no_color_env() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+/// `text` in `color`, if colors are on for `stream`.
+pub fn paint(stream: Stream, color: Color, text: &str) -> String {
+    let enabled = match stream {
+        Stream::Stdout => &STDOUT,
+        Stream::Stderr => &STDERR,
+    };
+    if !enabled.load(Ordering::Relaxed) {
+        return text.to_string();
+    }
+    let code = match color {
+        Color::Green => 32,
+        Color::Red => 31,
+        Color::Yellow => 33,
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+/// An operation status for stdout, as padded by the caller: `OK` green,
+/// `ERROR` and HTTP failures red, anything else as is.
+pub fn status(text: &str) -> String {
+    match text.trim_end() {
+        "OK" => paint(Stream::Stdout, Color::Green, text),
+        "ERROR" => paint(Stream::Stdout, Color::Red, text),
+        status if status.starts_with("HTTP") => paint(Stream::Stdout, Color::Red, text),
+        _ => text.to_string(),
+    }
+}
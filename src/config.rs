@@ -0,0 +1,99 @@
+//! Defaults read from `~/.config/lnurl-client/config.toml`.
+//!
+//! Each top-level key is a global flag without its dashes, e.g.
+//! `backend = "lnd"` or `log_level = "debug"`; `true` turns a switch on and
+//! an array repeats a flag. The command line wins over the file, except for
+//! repeatable flags, whose values add up. A `[servers]` table names endpoints
+//! that can then be given instead of a URL:
+//!
+//! ```toml
+//! backend = "lnd"
+//! lnd_rpc = "https://node.local:10009"
+//!
+//! [servers]
+//! lsp = "https://lsp.example.com"
+//! ```
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+#[derive(Debug, Default)]
+pub struct FileConfig {
+    /// Flags in file order, by long name: `None` for a switch.
+    pub flags: Vec<(String, Option<String>)>,
+    pub servers: BTreeMap<String, String>,
+}
+
+static SERVERS: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+
+/// `LNURL_CONFIG` if set, otherwise `config.toml` in `$XDG_CONFIG_HOME`
+/// (`~/.config`) under `lnurl-client`.
+pub fn path() -> PathBuf {
+    if let Ok(path) = std::env::var("LNURL_CONFIG") {
+        return PathBuf::from(path);
+    }
+    let base = match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| ".".to_string())).join(".config"),
+    };
+    base.join("lnurl-client").join("config.toml")
+}
+
+/// The config file, or an empty one if there is none.
+pub fn load() -> Result<FileConfig> {
+    let path = path();
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(FileConfig::default()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    parse(&text).with_context(|| format!("Invalid config file {}", path.display()))
+}
+
+fn parse(text: &str) -> Result<FileConfig> {
+    let table: toml::Table = text.parse()?;
+    let mut config = FileConfig::default();
+    for (key, value) in table {
+        if key == "servers" {
+            let servers = value.as_table().ok_or_else(|| anyhow!("`servers` must be a table of names and URLs"))?;
+            for (name, url) in servers {
+                let url = url.as_str().ok_or_else(|| anyhow!("server `{}` must be a URL string", name))?;
+                config.servers.insert(name.clone(), url.to_string());
+            }
+            continue;
+        }
+        let flag = key.replace('_', "-");
+        let values = match value {
+            toml::Value::Array(items) => items,
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                toml::Value::Boolean(true) => config.flags.push((flag.clone(), None)),
+                toml::Value::Boolean(false) => {}
+                toml::Value::String(s) => config.flags.push((flag.clone(), Some(s))),
+                toml::Value::Integer(n) => config.flags.push((flag.clone(), Some(n.to_string()))),
+                toml::Value::Float(x) => config.flags.push((flag.clone(), Some(x.to_string()))),
+                _ => bail!("`{}` must be a string, number, boolean or array of them", key),
+            }
+        }
+    }
+    Ok(config)
+}
+
+/// Makes the `[servers]` names available to `server`.
+pub fn set_servers(servers: BTreeMap<String, String>) {
+    let _ = SERVERS.set(servers);
+}
+
+/// The `[servers]` of the config file, by name.
+pub fn servers() -> BTreeMap<String, String> {
+    SERVERS.get().cloned().unwrap_or_default()
+}
+
+/// The URL of the server named `name` in the config file.
+pub fn server(name: &str) -> Option<&'static str> {
+    SERVERS.get()?.get(name).map(|url| url.as_str())
+}
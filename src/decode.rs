@@ -0,0 +1,101 @@
+//! What an LNURL, LUD-17 URI or BOLT11 invoice contains (`decode`), read
+//! locally: nothing is fetched and no flow runs, so a link can be checked
+//! before it is used.
+
+use crate::{parse_target, LnurlError};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Decoded {
+    Lnurl(LnurlDetails),
+    Invoice(InvoiceDetails),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LnurlDetails {
+    pub url: String,
+    /// Protocol known without fetching: from a LUD-17 scheme or the URL's
+    /// `tag` (fast withdraw and login links).
+    pub kind: Option<String>,
+    /// The query parameters of the URL, e.g. the k1 of a login link.
+    pub params: BTreeMap<String, String>,
+    /// LUD-01 wallets only accept https, or http for onion services.
+    pub secure: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InvoiceDetails {
+    /// `bitcoin`, `testnet`, `signet` or `regtest`.
+    pub network: String,
+    /// `None` for an invoice that lets the payer choose.
+    pub amount_msat: Option<u64>,
+    /// The payee's node id (hex).
+    pub payee: String,
+    pub payment_hash: String,
+    pub description: Option<String>,
+    /// SHA-256 the invoice commits to instead of a description (hex).
+    pub description_hash: Option<String>,
+    /// RFC 3339.
+    pub created_at: String,
+    pub expiry_secs: u64,
+    /// RFC 3339.
+    pub expires_at: String,
+    pub expired: bool,
+    pub min_final_cltv_expiry_delta: u64,
+    pub route_hints: usize,
+}
+
+/// Decodes `input`: a BOLT11 invoice (`lnbc...`, `lntb...`, with or without
+/// `lightning:`), otherwise anything `parse_target` accepts.
+pub fn decode(input: &str) -> Result<Decoded> {
+    let input = input.trim();
+    let bare = match input.get(..10) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("lightning:") => &input[10..],
+        _ => input,
+    };
+    let lower = bare.to_ascii_lowercase();
+    if lower.starts_with("ln") && !lower.starts_with("lnurl") {
+        return invoice(bare).map(Decoded::Invoice);
+    }
+    let target = parse_target(input)?;
+    let url = &target.url;
+    let onion = url.host_str().is_some_and(|h| h.ends_with(".onion"));
+    Ok(Decoded::Lnurl(LnurlDetails {
+        url: url.to_string(),
+        kind: target.kind.map(|kind| kind.name().to_string()),
+        params: url.query_pairs().map(|(k, v)| (k.into_owned(), v.into_owned())).collect(),
+        secure: url.scheme() == "https" || url.scheme() == "http" && onion,
+    }))
+}
+
+fn invoice(bolt11: &str) -> Result<InvoiceDetails> {
+    use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescriptionRef};
+    let invoice = Bolt11Invoice::from_str(bolt11).map_err(|e| LnurlError::invalid(format!("Invalid invoice: {}", e)))?;
+    let (description, description_hash) = match invoice.description() {
+        Bolt11InvoiceDescriptionRef::Direct(description) => (Some(description.to_string()), None),
+        Bolt11InvoiceDescriptionRef::Hash(hash) => (None, Some(hash.0.to_string())),
+    };
+    let created = invoice.duration_since_epoch();
+    let rfc3339 = |since_epoch: Duration| {
+        chrono::DateTime::<chrono::Utc>::from(UNIX_EPOCH + since_epoch).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    };
+    Ok(InvoiceDetails {
+        network: invoice.network().to_string(),
+        amount_msat: invoice.amount_milli_satoshis(),
+        payee: invoice.get_payee_pub_key().to_string(),
+        payment_hash: invoice.payment_hash().to_string(),
+        description,
+        description_hash,
+        created_at: rfc3339(created),
+        expiry_secs: invoice.expiry_time().as_secs(),
+        expires_at: rfc3339(created + invoice.expiry_time()),
+        expired: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default() > created + invoice.expiry_time(),
+        min_final_cltv_expiry_delta: invoice.min_final_cltv_expiry_delta(),
+        route_hints: invoice.route_hints().len(),
+    })
+}
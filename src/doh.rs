@@ -0,0 +1,121 @@
+//! DNS-over-HTTPS lookups (RFC 8484 wire format), so LNURL hostnames are
+//! not resolved through the system's plaintext DNS.
+
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+/// Resolves `host` through the DoH endpoint at `doh_url`, asking for A and/or
+/// AAAA records.
+pub fn resolve(agent: &ureq::Agent, doh_url: &str, host: &str, want_v4: bool, want_v6: bool) -> io::Result<Vec<IpAddr>> {
+    let mut addrs = Vec::new();
+    if want_v6 {
+        addrs.extend(query(agent, doh_url, host, TYPE_AAAA)?);
+    }
+    if want_v4 {
+        addrs.extend(query(agent, doh_url, host, TYPE_A)?);
+    }
+    log::debug!("doh: {} -> {:?}", host, addrs);
+    Ok(addrs)
+}
+
+fn query(agent: &ureq::Agent, doh_url: &str, host: &str, qtype: u16) -> io::Result<Vec<IpAddr>> {
+    let request = encode_query(host, qtype)?;
+    let response = agent
+        .post(doh_url)
+        .set("Content-Type", "application/dns-message")
+        .set("Accept", "application/dns-message")
+        .send_bytes(&request)
+        .map_err(|e| io::Error::other(format!("DoH query to {} failed: {}", doh_url, e)))?;
+    let mut body = Vec::new();
+    response.into_reader().read_to_end(&mut body)?;
+    decode_answers(&body, qtype)
+}
+
+/// Builds a DNS query message with a single question and recursion desired.
+fn encode_query(host: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    // id = 0 as recommended by RFC 8484 for cache friendliness; flags = RD.
+    let mut msg = vec![0, 0, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid hostname {}", host)));
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    msg.extend_from_slice(&qtype.to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(msg)
+}
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed DNS response")
+}
+
+/// Returns the offset just past the (possibly compressed) name at `pos`.
+fn skip_name(msg: &[u8], mut pos: usize) -> io::Result<usize> {
+    loop {
+        let len = *msg.get(pos).ok_or_else(malformed)? as usize;
+        if len == 0 {
+            return Ok(pos + 1);
+        }
+        if len & 0xC0 == 0xC0 {
+            return Ok(pos + 2);
+        }
+        pos += len + 1;
+    }
+}
+
+fn read_u16(msg: &[u8], pos: usize) -> io::Result<u16> {
+    msg.get(pos..pos + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(malformed)
+}
+
+/// Extracts the addresses of type `qtype` from the answer section. CNAME
+/// records in the chain are skipped; the resolver includes their targets.
+fn decode_answers(msg: &[u8], qtype: u16) -> io::Result<Vec<IpAddr>> {
+    if msg.len() < 12 {
+        return Err(malformed());
+    }
+    let rcode = msg[3] & 0x0F;
+    // NXDOMAIN just means no addresses of this type.
+    if rcode == 3 {
+        return Ok(Vec::new());
+    }
+    if rcode != 0 {
+        return Err(io::Error::other(format!("DNS server returned rcode {}", rcode)));
+    }
+    let qdcount = read_u16(msg, 4)?;
+    let ancount = read_u16(msg, 6)?;
+
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        pos = skip_name(msg, pos)? + 4;
+    }
+
+    let mut addrs = Vec::new();
+    for _ in 0..ancount {
+        pos = skip_name(msg, pos)?;
+        let rtype = read_u16(msg, pos)?;
+        let rdlen = read_u16(msg, pos + 8)? as usize;
+        let rdata = msg.get(pos + 10..pos + 10 + rdlen).ok_or_else(malformed)?;
+        pos += 10 + rdlen;
+        match (rtype, rdata.len()) {
+            (TYPE_A, 4) if qtype == TYPE_A => {
+                addrs.push(IpAddr::V4(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3])));
+            }
+            (TYPE_AAAA, 16) if qtype == TYPE_AAAA => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(rdata);
+                addrs.push(IpAddr::V6(Ipv6Addr::from(octets)));
+            }
+            _ => {}
+        }
+    }
+    Ok(addrs)
+}
@@ -0,0 +1,122 @@
+//! Errors returned by the `LnurlClient` flows.
+//!
+//! Inside the crate errors are `anyhow` chains. The places that know what
+//! went wrong (the HTTP calls, node calls, a service's `ERROR` answer, the
+//! checks before funds move) raise an `LnurlError` into that chain, and the
+//! public methods turn the chain back into one, under the variant raised and
+//! with the full message. Anything no such place raised is `Other`.
+
+use std::fmt::Display;
+
+#[derive(Debug, Clone, thiserror::Error, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error))]
+pub enum LnurlError {
+    /// The request got no answer (`status` is `None`: DNS, connection, TLS,
+    /// timeout) or a non-2xx one.
+    #[error("{message}")]
+    Http { status: Option<u16>, message: String },
+    /// An answer that isn't the JSON the protocol calls for.
+    #[error("{message}")]
+    Json { message: String },
+    /// The node couldn't be reached or failed a call.
+    #[error("{message}")]
+    Node { message: String },
+    /// The service answered `{"status": "ERROR", "reason": ...}`.
+    #[error("{message}")]
+    Rejected { reason: String, message: String },
+    /// A check before funds move failed: an amount outside the service's
+    /// limits, an invoice that doesn't match, a refused callback, a step the
+    /// user declined.
+    #[error("{message}")]
+    Invalid { message: String },
+    /// Anything else, e.g. a local file that can't be written.
+    #[error("{message}")]
+    Other { message: String },
+    /// Dry-run mode stopped the flow before `step`, a request to `url`.
+    #[error("{message}")]
+    DryRun { step: String, url: String, message: String },
+}
+
+// The constructors are only used by the flows.
+#[cfg_attr(not(feature = "native"), allow(dead_code))]
+impl LnurlError {
+    pub(crate) fn http(status: Option<u16>, message: impl Into<String>) -> Self {
+        LnurlError::Http { status, message: message.into() }
+    }
+
+    /// An answer from `from` (a URL or host) that doesn't parse as expected.
+    pub(crate) fn json(from: &str, e: serde_json::Error) -> Self {
+        LnurlError::Json { message: format!("Invalid JSON from {}: {}", from, e) }
+    }
+
+    /// A node error from `e`, keeping its whole chain.
+    pub(crate) fn node(e: impl Display) -> Self {
+        LnurlError::Node { message: format!("{:#}", e) }
+    }
+
+    pub(crate) fn rejected(message: impl Into<String>, reason: impl Into<String>) -> Self {
+        LnurlError::Rejected { reason: reason.into(), message: message.into() }
+    }
+
+    pub(crate) fn invalid(message: impl Into<String>) -> Self {
+        LnurlError::Invalid { message: message.into() }
+    }
+
+    pub(crate) fn dry_run(step: &str, url: &str) -> Self {
+        LnurlError::DryRun {
+            step: step.to_string(),
+            url: url.to_string(),
+            message: format!("Dry run: stopped before the {}", step),
+        }
+    }
+
+    /// Whether trying again later may succeed: the service was unreachable,
+    /// overloaded (HTTP 429) or failing (5xx).
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            LnurlError::Http { status: None, .. } => true,
+            LnurlError::Http { status: Some(status), .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// The process exit code for this error, so that scripts can tell the
+    /// kind of failure: 2 network, 3 protocol (an `ERROR` answer or invalid
+    /// JSON), 4 validation, 5 node, 1 anything else.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            LnurlError::Http { .. } => 2,
+            LnurlError::Json { .. } | LnurlError::Rejected { .. } => 3,
+            LnurlError::Invalid { .. } => 4,
+            LnurlError::Node { .. } => 5,
+            LnurlError::Other { .. } | LnurlError::DryRun { .. } => 1,
+        }
+    }
+
+    fn message_mut(&mut self) -> &mut String {
+        match self {
+            LnurlError::Http { message, .. }
+            | LnurlError::Json { message }
+            | LnurlError::Node { message }
+            | LnurlError::Rejected { message, .. }
+            | LnurlError::Invalid { message }
+            | LnurlError::Other { message }
+            | LnurlError::DryRun { message, .. } => message,
+        }
+    }
+}
+
+impl From<anyhow::Error> for LnurlError {
+    fn from(e: anyhow::Error) -> Self {
+        // The message includes the context added above the error raised.
+        let message = format!("{:#}", e);
+        match e.downcast::<LnurlError>() {
+            Ok(mut error) => {
+                *error.message_mut() = message;
+                error
+            }
+            Err(_) => LnurlError::Other { message },
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! UniFFI bindings (feature `uniffi`), for Kotlin and Swift wallets.
+//!
+//! The foreign side gets an `LnurlClient` object, a `Node` to build it for
+//! and `LnurlError` as its exception type. Everything crosses as plain
+//! strings, numbers and records: targets are what `parse_target` takes and
+//! paths are strings. Calls block until the flow is done, so apps run them
+//! off their main thread.
+
+use crate::{amount, backend, parse_target, LnurlError, PayArgs};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The node the flows use, as `--backend` and its flags would select it.
+#[derive(uniffi::Enum)]
+pub enum Node {
+    Cln { socket: String },
+    ClnGrpc { url: String, certs: String },
+    /// `rune` must allow `getinfo`, `connect` and `invoice`.
+    Commando { node_id: String, address: String, rune: String },
+    Lnd { url: String, tls_cert: String, macaroon: String },
+    LndRest { url: String, tls_cert: String, macaroon: String },
+    Eclair { url: String, password: String },
+    Phoenixd { url: String, password: String },
+    /// A `nostr+walletconnect://` connection string.
+    Nwc { uri: String },
+    Btcpay { url: String, store: String, api_key: String },
+    /// An `lndhub://<login>:<password>@<url>` account.
+    Lndhub { uri: String },
+}
+
+impl Node {
+    fn to_config(&self) -> anyhow::Result<backend::Config> {
+        Ok(match self {
+            Node::Cln { socket } => backend::Config::Cln { socket: socket.clone() },
+            Node::ClnGrpc { url, certs } => backend::Config::ClnGrpc { url: url.clone(), certs: certs.into() },
+            Node::Commando { node_id, address, rune } => backend::Config::Commando {
+                name: format!("commando:{}@{}", node_id, address),
+                node_id: node_id.clone(),
+                address: address.clone(),
+                rune: rune.clone(),
+            },
+            Node::Lnd { url, tls_cert, macaroon } => backend::Config::Lnd {
+                url: url.clone(),
+                tls_cert: tls_cert.into(),
+                macaroon: macaroon.into(),
+            },
+            Node::LndRest { url, tls_cert, macaroon } => backend::Config::LndRest {
+                url: url.clone(),
+                tls_cert: tls_cert.into(),
+                macaroon: macaroon.into(),
+            },
+            Node::Eclair { url, password } => backend::Config::Eclair { url: url.clone(), password: password.clone() },
+            Node::Phoenixd { url, password } => backend::Config::Phoenixd { url: url.clone(), password: password.clone() },
+            Node::Nwc { uri } => backend::Config::Nwc(uri.parse()?),
+            Node::Btcpay { url, store, api_key } => backend::Config::Btcpay {
+                url: url.clone(),
+                store: store.clone(),
+                api_key: api_key.clone(),
+            },
+            Node::Lndhub { uri } => {
+                let (url, login, password) = backend::lndhub::parse_uri(uri)?;
+                backend::Config::Lndhub { url, login, password }
+            }
+        })
+    }
+}
+
+/// Builder settings a wallet may want to change; `None` keeps the default.
+#[derive(uniffi::Record)]
+pub struct ClientOptions {
+    /// Timeout of each request to an LNURL service (connect + read).
+    #[uniffi(default = None)]
+    pub timeout: Option<Duration>,
+    /// Time to connect to a service, within `timeout`.
+    #[uniffi(default = None)]
+    pub connect_timeout: Option<Duration>,
+    /// `http://`, `https://` or `socks5h://` proxy for LNURL requests.
+    #[uniffi(default = None)]
+    pub proxy: Option<String>,
+    #[uniffi(default = None)]
+    pub user_agent: Option<String>,
+    /// Ask for unannounced channels.
+    #[uniffi(default = false)]
+    pub private_channels: bool,
+}
+
+/// How much to withdraw or pay.
+#[derive(uniffi::Enum)]
+pub enum Amount {
+    Msat { msat: u64 },
+    /// A share of what the service allows, e.g. 50.0 for half of it.
+    Percent { percent: f64 },
+}
+
+impl From<Amount> for amount::AmountArg {
+    fn from(amount: Amount) -> Self {
+        match amount {
+            Amount::Msat { msat } => amount::AmountArg::Msat(msat),
+            Amount::Percent { percent } => amount::AmountArg::Percent(percent),
+        }
+    }
+}
+
+/// [`crate::ChannelOutcome`].
+#[derive(uniffi::Enum)]
+pub enum ChannelOutcome {
+    Opened {
+        uri: String,
+        remote_id: String,
+        private: bool,
+        status: String,
+        txid: Option<String>,
+        channel_id: Option<String>,
+        confirmed: HashMap<String, String>,
+    },
+    /// The node is connected to the host; the channel is left to a
+    /// hosted-channels plugin, with `secret`.
+    Hosted { uri: String, alias: Option<String>, secret: String },
+}
+
+impl From<crate::ChannelOutcome> for ChannelOutcome {
+    fn from(outcome: crate::ChannelOutcome) -> Self {
+        match outcome {
+            crate::ChannelOutcome::Opened(opened) => ChannelOutcome::Opened {
+                uri: opened.uri,
+                remote_id: opened.remote_id,
+                private: opened.private,
+                status: opened.status,
+                txid: opened.txid,
+                channel_id: opened.channel_id,
+                confirmed: opened.confirmed.into_iter().collect(),
+            },
+            crate::ChannelOutcome::Hosted(hosted) => ChannelOutcome::Hosted {
+                uri: hosted.uri,
+                alias: hosted.alias,
+                secret: hosted.secret,
+            },
+        }
+    }
+}
+
+/// [`crate::Withdrawal`].
+#[derive(uniffi::Record)]
+pub struct Withdrawal {
+    pub amount_msat: u64,
+    pub bolt11: String,
+    pub label: Option<String>,
+    pub backend: Option<String>,
+    pub status: String,
+}
+
+impl From<crate::Withdrawal> for Withdrawal {
+    fn from(withdrawal: crate::Withdrawal) -> Self {
+        Withdrawal {
+            amount_msat: withdrawal.amount_msat,
+            bolt11: withdrawal.bolt11,
+            label: withdrawal.label,
+            backend: withdrawal.backend,
+            status: withdrawal.status,
+        }
+    }
+}
+
+/// [`crate::LnurlClient`] for foreign callers, which may share it between
+/// threads; flows on one client run one at a time.
+#[derive(uniffi::Object)]
+pub struct LnurlClient(Mutex<crate::LnurlClient>);
+
+#[uniffi::export]
+impl LnurlClient {
+    #[uniffi::constructor]
+    pub fn new(node: Node, options: ClientOptions) -> Result<Arc<Self>, LnurlError> {
+        let mut builder = crate::LnurlClient::builder(node.to_config()?).private_channels(options.private_channels);
+        if let Some(timeout) = options.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = options.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(proxy) = options.proxy {
+            builder = builder.proxy(proxy);
+        }
+        if let Some(user_agent) = options.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        Ok(Arc::new(Self(Mutex::new(builder.build()?))))
+    }
+
+    /// Requests an inbound channel (LUD-02) from `target`.
+    pub fn channel_request(&self, target: String) -> Result<ChannelOutcome, LnurlError> {
+        self.client().channel_request(&parse_target(&target)?).map(ChannelOutcome::from)
+    }
+
+    /// Withdraws `amount` (LUD-03) from `target` to an invoice created on the node.
+    pub fn withdraw_request(&self, target: String, amount: Amount, description: Option<String>) -> Result<Withdrawal, LnurlError> {
+        self.client()
+            .withdraw_request(&parse_target(&target)?, amount.into(), description)
+            .map(Withdrawal::from)
+    }
+
+    /// Pays `amount` to a pay link or lightning address (LUD-06/16), with
+    /// `comment` if the service accepts one.
+    pub fn pay_request(&self, target: String, amount: Amount, comment: Option<String>) -> Result<(), LnurlError> {
+        let extra = PayArgs { comment, ..PayArgs::default() };
+        self.client().pay_request(&parse_target(&target)?, amount.into(), &extra).map(|_| ())
+    }
+
+    /// Logs in with a `tag=login` URL (LUD-04).
+    pub fn login(&self, target: String) -> Result<(), LnurlError> {
+        self.client().login(&parse_target(&target)?)
+    }
+}
+
+impl LnurlClient {
+    fn client(&self) -> std::sync::MutexGuard<'_, crate::LnurlClient> {
+        // A flow that panicked leaves nothing half-done in the client.
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
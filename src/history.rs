@@ -0,0 +1,333 @@
+//! Operation history: one JSON line per finished flow, kept in the client
+//! data directory, plus export to plaintext-accounting formats.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+/// A finished (successful or failed) LNURL operation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub id: String,
+    pub timestamp: String,
+    /// Operation type: `withdraw`, `pay`, `channel-open`, `channel-cancel`, `hosted-channel`, `auth`.
+    pub kind: String,
+    pub url: String,
+    pub amount_msat: Option<u64>,
+    /// `OK`, `ERROR`, or `HTTP <code>` when the callback failed at the HTTP level.
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Flow-specific values (bolt11, txid, channel_id, ...).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub details: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// HTTP requests made for this operation, kept as evidence for `history proof`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exchanges: Vec<Exchange>,
+}
+
+/// One HTTP GET made during an operation and what the service answered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exchange {
+    pub timestamp: String,
+    pub url: String,
+    /// HTTP status, absent when the request failed at the transport level.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub body: String,
+    /// `X-LNURL-Signature` header, when the service signed the response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// User-supplied labels attached to an operation at execution time.
+#[derive(Debug, Clone, Default)]
+pub struct Annotations {
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+}
+
+/// Validates a tag: letters, digits, `-`, `_`, `/` and `.`, so it can be
+/// used verbatim as a beancount/ledger tag.
+pub fn parse_tag(tag: &str) -> Result<String> {
+    let valid = !tag.is_empty()
+        && tag.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '/' | '.'));
+    if !valid {
+        return Err(anyhow!("Invalid tag '{}': use letters, digits, '-', '_', '/' or '.'", tag));
+    }
+    Ok(tag.to_string())
+}
+
+impl Operation {
+    /// Starts a new operation record timestamped now.
+    pub fn new(kind: &str, url: &url::Url, annotations: &Annotations) -> Self {
+        let now = chrono::Utc::now();
+        Self {
+            id: now.format("%Y%m%d%H%M%S%3f").to_string(),
+            timestamp: now.to_rfc3339(),
+            kind: kind.to_string(),
+            url: url.to_string(),
+            amount_msat: None,
+            status: String::new(),
+            reason: None,
+            details: BTreeMap::new(),
+            tags: annotations.tags.clone(),
+            note: annotations.note.clone(),
+            exchanges: Vec::new(),
+        }
+    }
+
+    /// Whether the operation carries every tag in `tags`.
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|t| self.tags.contains(t))
+    }
+
+    pub fn detail(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.details.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Host of the LNURL server, used as payee in exports.
+    pub fn domain(&self) -> String {
+        url::Url::parse(&self.url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+            .unwrap_or_else(|| self.url.clone())
+    }
+}
+
+/// Returns the path of the history file inside the client data directory.
+pub fn history_path() -> PathBuf {
+    crate::get_data_dir().join("history.jsonl")
+}
+
+/// Appends an operation to the history file.
+pub fn append(op: &Operation) -> Result<()> {
+    let path = history_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open history {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(op)?)?;
+    Ok(())
+}
+
+/// Finds the operation with `id`.
+pub fn find(id: &str) -> Result<Operation> {
+    load()?
+        .into_iter()
+        .find(|op| op.id == id)
+        .ok_or_else(|| anyhow!("No operation with id {} in {}", id, history_path().display()))
+}
+
+/// Loads all operations, oldest first (empty if there is no history yet).
+pub fn load() -> Result<Vec<Operation>> {
+    let path = history_path();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let file = std::fs::File::open(&path)
+        .with_context(|| format!("Failed to open history {}", path.display()))?;
+    let mut ops = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        ops.push(
+            serde_json::from_str::<Operation>(&line)
+                .with_context(|| format!("Malformed history entry on line {}", i + 1))?,
+        );
+    }
+    Ok(ops)
+}
+
+/// Operations recorded at or after `timestamp` (RFC 3339), e.g. by the
+/// command that is running.
+pub fn since(timestamp: &str) -> Vec<Operation> {
+    load().unwrap_or_default().into_iter().filter(|op| op.timestamp.as_str() >= timestamp).collect()
+}
+
+// -----------------------------------------------------------------------------
+// Search
+// -----------------------------------------------------------------------------
+
+/// Sort key for listing operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    Date,
+    Amount,
+}
+
+/// Filter, order and page over the history.
+#[derive(Debug, Clone)]
+pub struct Query {
+    /// Whitespace-separated terms; each must appear (case-insensitively) in
+    /// the domain, URL, note, tags, kind or id.
+    pub search: Option<String>,
+    pub tags: Vec<String>,
+    pub sort: SortKey,
+    pub descending: bool,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl Operation {
+    fn matches_terms(&self, terms: &[String]) -> bool {
+        let haystack = format!(
+            "{} {} {} {} {} {}",
+            self.domain(),
+            self.url,
+            self.note.as_deref().unwrap_or(""),
+            self.tags.join(" "),
+            self.kind,
+            self.id
+        )
+        .to_lowercase();
+        terms.iter().all(|t| haystack.contains(t.as_str()))
+    }
+}
+
+/// Applies `query` to `ops`. Operations without an amount sort before any
+/// amount; ties keep history order.
+pub fn query(ops: Vec<Operation>, query: &Query) -> Vec<Operation> {
+    let terms: Vec<String> = query
+        .search
+        .as_deref()
+        .unwrap_or("")
+        .split_whitespace()
+        .map(|t| t.to_lowercase())
+        .collect();
+    let mut ops: Vec<Operation> = ops
+        .into_iter()
+        .filter(|op| op.has_tags(&query.tags) && op.matches_terms(&terms))
+        .collect();
+    // History is appended chronologically, so date order is file order.
+    if query.sort == SortKey::Amount {
+        ops.sort_by_key(|op| op.amount_msat);
+    }
+    if query.descending {
+        ops.reverse();
+    }
+    ops.into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+// -----------------------------------------------------------------------------
+// Accounting export
+// -----------------------------------------------------------------------------
+
+/// Plaintext-accounting output format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Beancount,
+    Ledger,
+}
+
+/// Account names and commodity used for postings.
+#[derive(Debug, Clone)]
+pub struct Accounts {
+    /// Where received sats land (the node's wallet).
+    pub assets: String,
+    /// Counter-account for withdrawals.
+    pub income: String,
+    /// Counter-account for payments.
+    pub expenses: String,
+    pub commodity: String,
+}
+
+/// Formats msat as a sat amount with msat precision (e.g. `1234.567`).
+fn format_sat(msat: u64) -> String {
+    format!("{}.{:03}", msat / 1000, msat % 1000)
+}
+
+/// Text for a beancount string: backslashes and quotes escaped, and line
+/// breaks (which would end the directive) turned into spaces.
+fn quote(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace("\r\n", " ").replace(['\r', '\n'], " ")
+}
+
+/// Renders successful funds-moving operations as double-entry transactions.
+/// Withdrawals and payments (including their routing fee) move sats; channel
+/// opens and auth are skipped.
+pub fn export(ops: &[Operation], format: ExportFormat, accounts: &Accounts) -> Result<String> {
+    let mut out = String::new();
+    for op in ops.iter().filter(|op| op.status == "OK") {
+        let (counter, narration, outgoing) = match op.kind.as_str() {
+            "withdraw" => (&accounts.income, "LNURL withdraw", false),
+            "pay" => (&accounts.expenses, "LNURL pay", true),
+            _ => continue,
+        };
+        let fee: u64 = op.details.get("fee_msat").and_then(|f| f.parse().ok()).unwrap_or(0);
+        let msat = op
+            .amount_msat
+            .ok_or_else(|| anyhow!("Operation {} has no amount", op.id))?
+            + fee;
+        let date = chrono::DateTime::parse_from_rfc3339(&op.timestamp)
+            .with_context(|| format!("Operation {} has an invalid timestamp", op.id))?
+            .date_naive();
+        let amount = format_sat(msat);
+        let (assets_amount, counter_amount) = if outgoing {
+            (format!("-{}", amount), amount.clone())
+        } else {
+            (amount.clone(), format!("-{}", amount))
+        };
+        let domain = quote(&op.domain());
+        match format {
+            ExportFormat::Beancount => {
+                let tags: String = op.tags.iter().map(|t| format!(" #{}", t)).collect();
+                out.push_str(&format!("{} * \"{}\" \"{}\"{}\n", date.format("%Y-%m-%d"), domain, narration, tags));
+                out.push_str(&format!("  operation_id: \"{}\"\n", op.id));
+                if let Some(note) = &op.note {
+                    out.push_str(&format!("  note: \"{}\"\n", quote(note)));
+                }
+                out.push_str(&format!("  {:<40} {:>16} {}\n", accounts.assets, assets_amount, accounts.commodity));
+                out.push_str(&format!("  {:<40} {:>16} {}\n\n", counter, counter_amount, accounts.commodity));
+            }
+            ExportFormat::Ledger => {
+                out.push_str(&format!("{} * {} | {}\n", date.format("%Y/%m/%d"), domain, narration));
+                out.push_str(&format!("    ; operation_id: {}\n", op.id));
+                if !op.tags.is_empty() {
+                    out.push_str(&format!("    ; :{}:\n", op.tags.join(":")));
+                }
+                if let Some(note) = &op.note {
+                    out.push_str(&format!("    ; {}\n", note.replace('\n', " ")));
+                }
+                out.push_str(&format!("    {:<40} {:>16} {}\n", accounts.assets, assets_amount, accounts.commodity));
+                out.push_str(&format!("    {}\n\n", counter));
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_beancount_strings() {
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote("say \"hi\"\\now"), "say \\\"hi\\\"\\\\now");
+        assert_eq!(quote("two\nlines\r\nhere"), "two lines here");
+    }
+}
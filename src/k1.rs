@@ -0,0 +1,138 @@
+//! Sanity checks on the `k1` values services hand out.
+//!
+//! LNURL expects k1 to be 32 random bytes, hex encoded. A service that sends
+//! short, patterned or reused values probably has weaker problems elsewhere
+//! too, so these are reported as warnings. Reuse is detected with a small store
+//! of k1 hashes per domain in the data directory (`k1_seen.json`), each with a
+//! hash of the response it came in. A static, multi-use link answers the same
+//! every time and is only worth a low warning; a k1 that comes back in another
+//! response, or from another service, keeps the medium one.
+
+use crate::warnings::Severity;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+
+/// Hashes remembered per domain; older ones are dropped first.
+const MAX_SEEN_PER_DOMAIN: usize = 200;
+
+/// Random 32-byte values practically always have far more distinct bytes.
+const MIN_DISTINCT_BYTES: usize = 8;
+
+fn seen_path() -> std::path::PathBuf {
+    crate::get_data_dir().join("k1_seen.json")
+}
+
+/// A k1 seen from a domain: hashes of the k1 and of the response it came in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Seen {
+    k1: String,
+    response: String,
+}
+
+fn load_seen() -> BTreeMap<String, Vec<Seen>> {
+    std::fs::read_to_string(seen_path())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn save_seen(seen: &BTreeMap<String, Vec<Seen>>) -> Result<()> {
+    let path = seen_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, serde_json::to_string(seen)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Problems with the format or randomness of `k1`, without side effects.
+pub fn format_issues(k1: &str) -> Vec<(&'static str, String)> {
+    let bytes = match hex::decode(k1) {
+        Ok(bytes) => bytes,
+        Err(_) => return vec![("malformed-k1", "k1 is not hex".to_string())],
+    };
+    let mut issues = Vec::new();
+    if bytes.len() != 32 {
+        issues.push(("malformed-k1", format!("k1 is {} bytes, expected 32", bytes.len())));
+    }
+    let distinct = bytes.iter().collect::<HashSet<_>>().len();
+    if bytes.iter().all(|b| *b == 0) {
+        issues.push(("weak-k1", "k1 is all zeros".to_string()));
+    } else if bytes.len() >= 16 && distinct < MIN_DISTINCT_BYTES {
+        issues.push(("weak-k1", format!("k1 has only {} distinct byte values, it doesn't look random", distinct)));
+    }
+    issues
+}
+
+/// Checks `k1` from `domain`, sent in `response`, and remembers it. Returns a
+/// warning code, severity and description for each problem found, including
+/// reuse of a k1 seen before.
+pub fn check(domain: &str, k1: &str, response: &str) -> Vec<(&'static str, Severity, String)> {
+    let mut issues: Vec<_> = format_issues(k1).into_iter().map(|(code, issue)| (code, Severity::Medium, issue)).collect();
+    let entry = Seen {
+        k1: hex::encode(Sha256::digest(k1.as_bytes())),
+        response: hex::encode(Sha256::digest(response.as_bytes())),
+    };
+    let mut seen = load_seen();
+    let (reuse, changed) = remember(&mut seen, &domain.to_ascii_lowercase(), entry);
+    issues.extend(reuse.map(|(severity, issue)| ("reused-k1", severity, issue)));
+    if changed {
+        if let Err(e) = save_seen(&seen) {
+            log::warn!("failed to remember k1: {:#}", e);
+        }
+    }
+    issues
+}
+
+/// Looks `entry` up in `seen` and adds it under `domain`. Returns the reuse
+/// found, if any, and whether `seen` changed.
+fn remember(seen: &mut BTreeMap<String, Vec<Seen>>, domain: &str, entry: Seen) -> (Option<(Severity, String)>, bool) {
+    let other = seen
+        .iter()
+        .find(|(name, entries)| name.as_str() != domain && entries.iter().any(|e| e.k1 == entry.k1))
+        .map(|(name, _)| name.clone());
+    let entries = seen.entry(domain.to_string()).or_default();
+    let reuse = match other {
+        Some(other) => Some((Severity::Medium, format!("k1 was already sent by another service, {}", other))),
+        None if entries.contains(&entry) => Some((
+            Severity::Low,
+            format!("k1 was already sent by {} in the same response (a static, multi-use link?)", domain),
+        )),
+        None if entries.iter().any(|e| e.k1 == entry.k1) => {
+            Some((Severity::Medium, format!("k1 was already sent by {} in an earlier request", domain)))
+        }
+        None => None,
+    };
+    if entries.contains(&entry) {
+        return (reuse, false);
+    }
+    entries.push(entry);
+    if entries.len() > MAX_SEEN_PER_DOMAIN {
+        entries.remove(0);
+    }
+    (reuse, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(k1: &str, response: &str) -> Seen {
+        Seen { k1: k1.into(), response: response.into() }
+    }
+
+    #[test]
+    fn rates_reuse_by_where_the_k1_came_back() {
+        let mut seen = BTreeMap::new();
+        assert_eq!(remember(&mut seen, "a.example", entry("k1", "r1")), (None, true));
+        let (same, changed) = remember(&mut seen, "a.example", entry("k1", "r1"));
+        assert_eq!((same.map(|(severity, _)| severity), changed), (Some(Severity::Low), false));
+        let (other_response, _) = remember(&mut seen, "a.example", entry("k1", "r2"));
+        assert_eq!(other_response.map(|(severity, _)| severity), Some(Severity::Medium));
+        let (other_service, _) = remember(&mut seen, "b.example", entry("k1", "r1"));
+        assert_eq!(other_service.map(|(severity, _)| severity), Some(Severity::Medium));
+        assert_eq!(remember(&mut seen, "a.example", entry("k2", "r1")), (None, true));
+    }
+}
@@ -0,0 +1,188 @@
+//! Keys for LNURL-auth (LUD-04/05).
+//!
+//! LNURL-auth logs in with a different key per domain, so services can't link
+//! one user across sites. Keys are derived with BIP32 from a 32-byte seed kept
+//! in the data directory (`auth_seed`, created on first use, readable by the
+//! owner only). LUD-05 derivation: the hashing key is `m/138'/0`; the first 16
+//! bytes of HMAC-SHA256(hashing key, domain) give four 32-bit indexes, and the
+//! linking key for the domain is `m/138'/<i1>/<i2>/<i3>/<i4>`.
+//!
+//! Losing the seed means losing every account created with it; back it up.
+//!
+//! Alternatively (`--auth-key node`, LUD-13) the keys come from the node: it
+//! signs a fixed phrase, the SHA-256 of the signature is the hashing key, and
+//! HMAC-SHA256(hashing key, domain) is the linking key. The identity then
+//! follows the node and needs no separate backup. Core Lightning only signs
+//! prefixed text, so the node signs the hex SHA-256 of the phrase; keys differ
+//! from those of wallets that sign the raw hash.
+
+use anyhow::{anyhow, Context, Result};
+use hmac::{Hmac, Mac};
+use secp256k1::{ecdsa::Signature, Message, PublicKey, Scalar, Secp256k1, SecretKey};
+use sha2::{Digest, Sha256, Sha512};
+
+/// Where linking keys come from (`--auth-key`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum KeySource {
+    /// BIP32 derivation from the `auth_seed` file (LUD-05).
+    Seed,
+    /// A node signature (LUD-13).
+    Node,
+}
+
+/// Canonical phrase signed by the node for LUD-13.
+const LUD13_PHRASE: &str = "DO NOT EVER SIGN THIS TEXT WITH YOUR PRIVATE KEYS! IT IS ONLY USED FOR DERIVATION OF LNURL-AUTH HASHING-KEY, DISCLOSING ITS SIGNATURE WILL COMPROMISE YOUR LNURL-AUTH IDENTITY AND MAY LEAD TO LOSS OF FUNDS!";
+
+/// First hardened index (BIP32).
+const HARDENED: u32 = 0x8000_0000;
+
+fn seed_path() -> std::path::PathBuf {
+    crate::get_data_dir().join("auth_seed")
+}
+
+/// Reads the seed, creating a random one if there is none yet.
+pub fn load_or_create_seed() -> Result<Vec<u8>> {
+    let path = seed_path();
+    if let Ok(text) = std::fs::read_to_string(&path) {
+        let seed = hex::decode(text.trim()).with_context(|| format!("{} is not hex", path.display()))?;
+        if !(16..=64).contains(&seed.len()) {
+            return Err(anyhow!("{} must hold a 16 to 64 byte seed", path.display()));
+        }
+        return Ok(seed);
+    }
+    let mut seed = vec![0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| anyhow!("No randomness for a new seed: {}", e))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    write_private(&path, &hex::encode(&seed)).with_context(|| format!("Failed to write {}", path.display()))?;
+    log::info!("Created LNURL-auth seed {} (back it up: it holds your logins)", path.display());
+    Ok(seed)
+}
+
+/// Writes a new file readable by the owner only.
+#[cfg(unix)]
+pub(crate) fn write_private(path: &std::path::Path, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut file = std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)?;
+    file.write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn write_private(path: &std::path::Path, contents: &str) -> std::io::Result<()> {
+    std::fs::write(path, contents)
+}
+
+/// BIP32 extended private key.
+struct ExtendedKey {
+    key: SecretKey,
+    chain_code: [u8; 32],
+}
+
+fn hmac_sha512(key: &[u8], parts: &[&[u8]]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts any key length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+impl ExtendedKey {
+    fn master(seed: &[u8]) -> Result<Self> {
+        let i = hmac_sha512(b"Bitcoin seed", &[seed]);
+        Ok(Self {
+            key: SecretKey::from_slice(&i[..32]).context("Seed gives an invalid master key")?,
+            chain_code: i[32..].try_into().unwrap(),
+        })
+    }
+
+    /// Derives child `index`; indexes from 2^31 up are hardened.
+    fn child(&self, index: u32) -> Result<Self> {
+        let i = if index >= HARDENED {
+            hmac_sha512(&self.chain_code, &[&[0], &self.key.secret_bytes(), &index.to_be_bytes()])
+        } else {
+            let public = PublicKey::from_secret_key(&Secp256k1::signing_only(), &self.key);
+            hmac_sha512(&self.chain_code, &[&public.serialize(), &index.to_be_bytes()])
+        };
+        let tweak = Scalar::from_be_bytes(i[..32].try_into().unwrap())
+            .map_err(|_| anyhow!("Invalid BIP32 child {}", index))?;
+        Ok(Self {
+            key: self.key.add_tweak(&tweak).context("Invalid BIP32 child")?,
+            chain_code: i[32..].try_into().unwrap(),
+        })
+    }
+
+    fn derive(&self, path: &[u32]) -> Result<Self> {
+        path.iter().try_fold(
+            Self {
+                key: self.key,
+                chain_code: self.chain_code,
+            },
+            |key, index| key.child(*index),
+        )
+    }
+}
+
+/// LUD-05 linking key for `domain`.
+pub fn linking_key(seed: &[u8], domain: &str) -> Result<SecretKey> {
+    let master = ExtendedKey::master(seed)?;
+    let hashing_key = master.derive(&[138 | HARDENED, 0])?.key;
+    Ok(master.derive(&linking_path(&hashing_key.secret_bytes(), domain))?.key)
+}
+
+/// Derivation path of the linking key for `domain`: `m/138'` followed by the
+/// first 16 bytes of HMAC-SHA256(hashing key, domain) as four indexes.
+fn linking_path(hashing_key: &[u8], domain: &str) -> Vec<u32> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hashing_key).expect("HMAC accepts any key length");
+    mac.update(domain.as_bytes());
+    let digest = mac.finalize().into_bytes();
+    let mut path = vec![138 | HARDENED];
+    path.extend(digest[..16].chunks(4).map(|c| u32::from_be_bytes(c.try_into().unwrap())));
+    path
+}
+
+/// Message for the node to sign (`signmessage`) for LUD-13 keys.
+pub fn node_message() -> String {
+    hex::encode(Sha256::digest(LUD13_PHRASE.as_bytes()))
+}
+
+/// LUD-13 linking key for `domain`, from the node's signature of `node_message`.
+pub fn node_linking_key(signature: &[u8], domain: &str) -> Result<SecretKey> {
+    let hashing_key = Sha256::digest(signature);
+    let mut mac = Hmac::<Sha256>::new_from_slice(&hashing_key).expect("HMAC accepts any key length");
+    mac.update(domain.as_bytes());
+    SecretKey::from_slice(&mac.finalize().into_bytes()).context("Node signature gives an invalid linking key")
+}
+
+/// Signs the 32-byte challenge `k1` (LUD-04), returning the DER signature.
+pub fn sign_k1(key: &SecretKey, k1: &[u8]) -> Result<Signature> {
+    let message = Message::from_digest_slice(k1).context("k1 must be 32 bytes")?;
+    Ok(Secp256k1::signing_only().sign_ecdsa(&message, key))
+}
+
+/// Compressed public key of `key`, hex encoded (the `key` callback parameter).
+pub fn public_hex(key: &SecretKey) -> String {
+    hex::encode(PublicKey::from_secret_key(&Secp256k1::signing_only(), key).serialize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linking_path_matches_lud05_example() {
+        let hashing_key = hex::decode("7d417a6a5e9a6a4a879aeaba11a11838764c8fa2b959c242d43dea682b3e409b").unwrap();
+        assert_eq!(
+            linking_path(&hashing_key, "site.com"),
+            [138 | HARDENED, 1588488367, 2659270754, 38110259, 4136336762]
+        );
+    }
+
+    #[test]
+    fn derives_bip32_test_vector_1() {
+        let master = ExtendedKey::master(&hex::decode("000102030405060708090a0b0c0d0e0f").unwrap()).unwrap();
+        let key = master.derive(&[HARDENED, 1]).unwrap().key;
+        assert_eq!(hex::encode(key.secret_bytes()), "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368");
+    }
+}
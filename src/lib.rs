@@ -0,0 +1,151 @@
+//! LNURL flows (channel request, withdraw, pay, auth) against a Lightning
+//! node, for the `lnurl-client` binary and for programs that embed them
+//! through [`LnurlClient`].
+//!
+//! Without the default `native` feature only the LNURL core is built: bech32
+//! and LUD-17 targets, pay metadata and success actions, amounts, errors and
+//! warnings. It has no node, HTTP or file access and builds for
+//! `wasm32-unknown-unknown`.
+
+pub mod amount;
+#[cfg(feature = "native")]
+mod audit;
+#[cfg(feature = "native")]
+pub mod backend;
+#[cfg(feature = "native")]
+mod cache;
+#[cfg(feature = "native")]
+mod client;
+#[cfg(feature = "native")]
+pub mod clipboard;
+#[cfg(feature = "native")]
+pub mod color;
+#[cfg(feature = "native")]
+pub mod config;
+#[cfg(feature = "native")]
+pub mod decode;
+#[cfg(feature = "native")]
+mod doh;
+pub mod error;
+#[cfg(feature = "uniffi")]
+pub mod ffi;
+#[cfg(feature = "native")]
+pub mod history;
+#[cfg(feature = "native")]
+mod k1;
+#[cfg(feature = "native")]
+pub mod keys;
+pub mod lnurl;
+#[cfg(feature = "native")]
+pub mod logging;
+#[cfg(feature = "native")]
+mod monitor;
+#[cfg(feature = "native")]
+pub mod net;
+#[cfg(feature = "native")]
+pub mod output;
+#[cfg(feature = "native")]
+pub mod paylinks;
+#[cfg(feature = "native")]
+pub mod plugin;
+#[cfg(feature = "native")]
+pub mod progress;
+#[cfg(feature = "native")]
+pub mod rates;
+#[cfg(feature = "native")]
+pub mod scan;
+#[cfg(feature = "native")]
+pub mod signed;
+#[cfg(feature = "native")]
+pub mod tofu;
+#[cfg(feature = "tor")]
+pub mod tor;
+#[cfg(feature = "native")]
+pub mod tui;
+#[cfg(feature = "native")]
+pub mod update;
+pub mod warnings;
+
+use url::Url;
+use anyhow::{Context, Result, anyhow};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+#[cfg(feature = "native")]
+pub use client::*;
+pub use error::LnurlError;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+
+// -----------------------------------------------------------------------------
+// Targets
+// -----------------------------------------------------------------------------
+
+/// Parses a command's target: a bech32 LNURL (LUD-01), a LUD-17 URI such as
+/// `lnurlw://`, or anything `parse_url_or_ip` accepts.
+pub fn parse_target(input: &str) -> Result<lnurl::Target> {
+    if let Some(parsed) = lnurl::parse_scheme(input.trim()) {
+        let (url, kind) = parsed?;
+        return Ok(lnurl::Target { url, lnurl: true, kind: Some(kind) });
+    }
+    if lnurl::bech32::looks_like_bech32(input) {
+        let decoded = lnurl::bech32::decode(input)?;
+        let url = Url::parse(&decoded).with_context(|| format!("LNURL does not contain a valid URL: {}", decoded))?;
+        // Login and fast withdraw links carry their tag in the URL; the others
+        // answer with it.
+        let kind = url.query_pairs().find(|(k, _)| k == "tag").and_then(|(_, tag)| lnurl::Kind::from_tag(&tag));
+        return Ok(lnurl::Target { url, lnurl: true, kind });
+    }
+    Ok(lnurl::Target {
+        url: parse_url_or_ip(input)?,
+        lnurl: false,
+        kind: None,
+    })
+}
+
+/// Parses a string as a URL or as a host:port (IPv4 or IPv6).
+/// Plain host:port is turned into `http://host:port`.
+fn parse_url_or_ip(input: &str) -> Result<Url> {
+    if let Ok(url) = Url::parse(input) {
+        return Ok(url);
+    }
+
+    // IPv6 with port in brackets, e.g. [::1]:8080
+    if let Some(bracket_end) = input.find("]:") {
+        if input.starts_with('[') {
+            let ip_part = &input[1..bracket_end];
+            let port_part = &input[bracket_end + 2..];
+            if port_part.parse::<u16>().is_ok() {
+                if let Ok(ip) = IpAddr::from_str(ip_part) {
+                    let url_str = format!("http://[{}]:{}", ip, port_part);
+                    return Url::parse(&url_str)
+                        .context("Failed to convert IP address with port to URL");
+                }
+            }
+        }
+    }
+
+    // IPv4 or IPv6 with port, e.g. 192.168.1.1:8080
+    if let Some(colon_pos) = input.rfind(':') {
+        let ip_part = &input[..colon_pos];
+        let port_part = &input[colon_pos + 1..];
+        
+        if port_part.parse::<u16>().is_ok() {
+            if let Ok(ip) = IpAddr::from_str(ip_part) {
+                let url_str = format!("http://{}:{}", ip, port_part);
+                return Url::parse(&url_str)
+                    .context("Failed to convert IP address with port to URL");
+            }
+        }
+    }
+
+    // Plain IP (no port); default to http with no port
+    if let Ok(ip) = IpAddr::from_str(input) {
+        let url_str = format!("http://{}", ip);
+        return Url::parse(&url_str)
+            .context("Failed to convert IP address to URL");
+    }
+    
+    Err(anyhow!("Invalid URL or IP address: {}", input))
+}
@@ -0,0 +1,152 @@
+//! Bech32 encoding and decoding of LNURLs (LUD-01).
+//!
+//! An LNURL is the service URL, UTF-8 encoded and written as bech32 with the
+//! human-readable part `lnurl`. LNURLs are longer than the 90 characters BIP-173
+//! allows, so no length limit is applied.
+
+use anyhow::{anyhow, Result};
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Human-readable part of every LNURL.
+pub const HRP: &str = "lnurl";
+
+fn polymod(values: impl Iterator<Item = u8>) -> u32 {
+    const GEN: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut chk: u32 = 1;
+    for v in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ffffff) << 5) ^ v as u32;
+        for (i, g) in GEN.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let bytes = hrp.as_bytes();
+    let mut out: Vec<u8> = bytes.iter().map(|b| b >> 5).collect();
+    out.push(0);
+    out.extend(bytes.iter().map(|b| b & 31));
+    out
+}
+
+/// Regroups bits, e.g. 8-bit bytes into 5-bit words. When not padding, the
+/// leftover bits must be fewer than `from` and all zero.
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>> {
+    let mut acc: u32 = 0;
+    let mut bits: u32 = 0;
+    let max = (1u32 << to) - 1;
+    let mut out = Vec::new();
+    for &value in data {
+        acc = (acc << from) | value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            out.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            out.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || (acc << (to - bits)) & max != 0 {
+        return Err(anyhow!("Invalid padding in LNURL data"));
+    }
+    Ok(out)
+}
+
+/// Whether `input` looks like a bech32 string (optionally with a `lightning:`
+/// prefix) rather than a URL, so that decoding errors are the ones to report.
+pub fn looks_like_bech32(input: &str) -> bool {
+    let s = strip_scheme(input.trim());
+    s.len() >= 8 && s.contains('1') && s.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn strip_scheme(input: &str) -> &str {
+    match input.get(..10) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("lightning:") => &input[10..],
+        _ => input,
+    }
+}
+
+/// Decodes an `lnurl1...` string (any case, optionally prefixed with
+/// `lightning:`) to the URL it contains.
+pub fn decode(input: &str) -> Result<String> {
+    let s = strip_scheme(input.trim());
+    if s.chars().any(|c| c.is_ascii_lowercase()) && s.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err(anyhow!("Invalid LNURL: mixed upper and lower case"));
+    }
+    let s = s.to_ascii_lowercase();
+    let sep = s.rfind('1').ok_or_else(|| anyhow!("Invalid LNURL: no bech32 separator '1'"))?;
+    let (hrp, data) = (&s[..sep], &s[sep + 1..]);
+    if hrp != HRP {
+        return Err(anyhow!("Invalid LNURL: human-readable part is '{}', expected '{}'", hrp, HRP));
+    }
+    if data.len() < 6 {
+        return Err(anyhow!("Invalid LNURL: too short"));
+    }
+    let values = data
+        .bytes()
+        .map(|c| {
+            CHARSET
+                .iter()
+                .position(|&x| x == c)
+                .map(|p| p as u8)
+                .ok_or_else(|| anyhow!("Invalid LNURL: '{}' is not a bech32 character", c as char))
+        })
+        .collect::<Result<Vec<u8>>>()?;
+    if polymod(hrp_expand(hrp).into_iter().chain(values.iter().copied())) != 1 {
+        return Err(anyhow!("Invalid LNURL: checksum mismatch (mistyped or truncated?)"));
+    }
+    let bytes = convert_bits(&values[..values.len() - 6], 5, 8, false)?;
+    String::from_utf8(bytes).map_err(|_| anyhow!("Invalid LNURL: data is not UTF-8"))
+}
+
+/// Encodes `url` as a lowercase `lnurl1...` string.
+pub fn encode(url: &str) -> String {
+    // Padding is always allowed, so conversion can't fail.
+    let values = convert_bits(url.as_bytes(), 8, 5, true).unwrap_or_default();
+    let checksum = polymod(hrp_expand(HRP).into_iter().chain(values.iter().copied()).chain([0u8; 6])) ^ 1;
+    let mut out = String::with_capacity(HRP.len() + 1 + values.len() + 6);
+    out.push_str(HRP);
+    out.push('1');
+    for v in values {
+        out.push(CHARSET[v as usize] as char);
+    }
+    for i in 0..6 {
+        out.push(CHARSET[((checksum >> (5 * (5 - i))) & 31) as usize] as char);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LUD-01's example.
+    const LNURL: &str = "LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS";
+    const URL: &str = "https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df";
+
+    #[test]
+    fn decodes_lud01_example() {
+        assert_eq!(decode(LNURL).unwrap(), URL);
+        assert_eq!(decode(&format!("lightning:{}", LNURL.to_lowercase())).unwrap(), URL);
+    }
+
+    #[test]
+    fn encodes_lud01_example() {
+        assert_eq!(encode(URL).to_uppercase(), LNURL);
+    }
+
+    #[test]
+    fn rejects_bad_checksum_and_mixed_case() {
+        let mistyped = LNURL.replacen("DP68", "DP69", 1);
+        assert!(decode(&mistyped).unwrap_err().to_string().contains("checksum"));
+        let mixed = format!("lnurl1{}", &LNURL[6..]);
+        assert!(decode(&mixed).unwrap_err().to_string().contains("mixed"));
+    }
+}
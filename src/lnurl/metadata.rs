@@ -0,0 +1,53 @@
+//! The `metadata` of a pay link: a JSON array of `[mime type, content]`
+//! pairs (LUD-06), with the entry types of LUD-06/16/20. The invoice commits
+//! to the raw string, so it is kept as received and only read here.
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+
+#[derive(Debug, Default)]
+pub struct Metadata {
+    /// `text/plain`, required.
+    pub description: Option<String>,
+    /// `text/long-desc` (LUD-20).
+    pub long_description: Option<String>,
+    /// `text/identifier` or `text/email` (LUD-16): the address being paid.
+    pub identifier: Option<String>,
+    /// `image/png;base64` or `image/jpeg;base64`: mime type and decoded size.
+    pub image: Option<(String, usize)>,
+}
+
+impl Metadata {
+    pub fn parse(raw: &str) -> Result<Self> {
+        let entries: Vec<Vec<serde_json::Value>> =
+            serde_json::from_str(raw).context("metadata is not a JSON array of [type, content] pairs")?;
+        let mut metadata = Metadata::default();
+        for entry in entries {
+            let (Some(kind), Some(content)) = (
+                entry.first().and_then(|k| k.as_str()),
+                entry.get(1).and_then(|c| c.as_str()),
+            ) else {
+                return Err(anyhow!("metadata entry {:?} is not a [type, content] pair", entry));
+            };
+            let content = content.to_string();
+            match kind {
+                "text/plain" => metadata.description = Some(content),
+                "text/long-desc" => metadata.long_description = Some(content),
+                "text/identifier" | "text/email" => metadata.identifier = Some(content),
+                "image/png;base64" | "image/jpeg;base64" => {
+                    let bytes = base64::engine::general_purpose::STANDARD
+                        .decode(&content)
+                        .with_context(|| format!("metadata {} is not base64", kind))?;
+                    let mime = kind.trim_end_matches(";base64").to_string();
+                    metadata.image = Some((mime, bytes.len()));
+                }
+                // Unknown types are allowed and skipped.
+                _ => {}
+            }
+        }
+        if metadata.description.is_none() {
+            return Err(anyhow!("metadata has no text/plain entry"));
+        }
+        Ok(metadata)
+    }
+}
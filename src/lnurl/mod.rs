@@ -0,0 +1,116 @@
+//! LNURL strings and the services they point at.
+
+pub mod bech32;
+pub mod metadata;
+pub mod success;
+
+use anyhow::{Context, Result};
+use std::ops::Deref;
+use url::Url;
+
+/// The LNURL sub-protocols, as told by a LUD-17 scheme or a response `tag`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Pay,
+    Withdraw,
+    Channel,
+    Login,
+}
+
+impl Kind {
+    /// Protocol name as in "LNURL-pay".
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Pay => "pay",
+            Kind::Withdraw => "withdraw",
+            Kind::Channel => "channel",
+            Kind::Login => "auth",
+        }
+    }
+
+    /// Maps a response `tag` to its protocol.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        match tag {
+            "payRequest" => Some(Kind::Pay),
+            "withdrawRequest" => Some(Kind::Withdraw),
+            "channelRequest" | "hostedChannelRequest" => Some(Kind::Channel),
+            "login" => Some(Kind::Login),
+            _ => None,
+        }
+    }
+}
+
+/// LUD-17 schemes and the protocol each one announces.
+const SCHEMES: &[(&str, Kind)] = &[
+    ("lnurlp://", Kind::Pay),
+    ("lnurlw://", Kind::Withdraw),
+    ("lnurlc://", Kind::Channel),
+    ("keyauth://", Kind::Login),
+];
+
+/// Rewrites a LUD-17 URI (`lnurlp://`, `lnurlw://`, `lnurlc://`,
+/// `keyauth://`) to https, or http for onion hosts. Returns `None` for other
+/// input.
+pub fn parse_scheme(input: &str) -> Option<Result<(Url, Kind)>> {
+    let (prefix, kind) = SCHEMES
+        .iter()
+        .find(|(prefix, _)| input.get(..prefix.len()).is_some_and(|p| p.eq_ignore_ascii_case(prefix)))?;
+    let rest = &input[prefix.len()..];
+    let host = rest.split(['/', '?', ':']).next().unwrap_or("");
+    let scheme = if host.ends_with(".onion") { "http" } else { "https" };
+    Some(
+        Url::parse(&format!("{}://{}", scheme, rest))
+            .with_context(|| format!("Invalid {} URI: {}", prefix.trim_end_matches("://"), input))
+            .map(|url| (url, *kind)),
+    )
+}
+
+/// What a command was pointed at. A server URL or host:port gets the flow's
+/// endpoint path appended (e.g. `/request-withdraw`); a decoded LNURL already
+/// names the endpoint and is used as is.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub url: Url,
+    /// Decoded from an `lnurl1...` string or a LUD-17 URI.
+    pub lnurl: bool,
+    /// Protocol known before fetching: from a LUD-17 scheme or `tag=login`.
+    pub kind: Option<Kind>,
+}
+
+impl Target {
+    /// URL to GET for the flow whose endpoint on a plain server is `path`.
+    pub fn endpoint(&self, path: &str) -> String {
+        if self.lnurl {
+            self.url.to_string()
+        } else {
+            format!("{}/{}", self.url.as_str().trim_end_matches('/'), path)
+        }
+    }
+}
+
+/// Appends query parameters to a callback that may already have some.
+pub fn callback_url(callback: &str, params: &str) -> String {
+    let separator = if callback.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", callback, separator, params)
+}
+
+/// Serialized as its URL, e.g. in `--json` output.
+impl serde::Serialize for Target {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.url)
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.url.fmt(f)
+    }
+}
+
+impl Deref for Target {
+    type Target = Url;
+
+    fn deref(&self) -> &Url {
+        &self.url
+    }
+}
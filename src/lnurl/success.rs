@@ -0,0 +1,155 @@
+//! What a pay service shows once the payment went through (`successAction`,
+//! LUD-09): a short message or a link. It arrives with the invoice and is used
+//! only after the payment succeeded. An `aes` action (LUD-10) carries a secret,
+//! e.g. a voucher code, encrypted with AES-256-CBC under the payment preimage,
+//! so only the payer can read it and only once the payment settled.
+
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Longest message or description LUD-09 allows.
+const MAX_TEXT_LEN: usize = 144;
+
+/// Longest base64 ciphertext LUD-10 allows.
+const MAX_CIPHERTEXT_LEN: usize = 4096;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "tag", rename_all = "lowercase")]
+pub enum SuccessAction {
+    Message {
+        message: String,
+    },
+    Url {
+        description: String,
+        url: String,
+    },
+    /// Base64 ciphertext and 16-byte IV (LUD-10).
+    Aes {
+        description: String,
+        ciphertext: String,
+        iv: String,
+    },
+    /// A tag this client doesn't know; ignored.
+    #[serde(other)]
+    Unsupported,
+}
+
+impl SuccessAction {
+    /// Raises warnings for an action that breaks LUD-09/10: text over 144
+    /// characters, a link to another domain than the callback's, or
+    /// ciphertext over 4 KB.
+    pub fn check(&self, callback: &str) -> Result<()> {
+        use crate::warnings::{raise, Severity};
+        let text = match self {
+            SuccessAction::Message { message } => message,
+            SuccessAction::Url { description, .. } | SuccessAction::Aes { description, .. } => description,
+            SuccessAction::Unsupported => return Ok(()),
+        };
+        if text.chars().count() > MAX_TEXT_LEN {
+            raise(
+                "long-success-action",
+                Severity::Low,
+                format!("successAction text is {} characters, LUD-09 allows {}", text.chars().count(), MAX_TEXT_LEN),
+            )?;
+        }
+        if let SuccessAction::Url { url, .. } = self {
+            let host = |u: &str| Url::parse(u).ok().and_then(|u| u.host_str().map(|h| h.to_ascii_lowercase()));
+            if host(url).is_none() || host(url) != host(callback) {
+                raise(
+                    "foreign-success-url",
+                    Severity::Medium,
+                    format!("successAction link {} is not on the callback's domain", url),
+                )?;
+            }
+        }
+        if let SuccessAction::Aes { ciphertext, .. } = self {
+            if ciphertext.len() > MAX_CIPHERTEXT_LEN {
+                raise(
+                    "long-success-action",
+                    Severity::Low,
+                    format!("successAction ciphertext is {} characters, LUD-10 allows {}", ciphertext.len(), MAX_CIPHERTEXT_LEN),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decrypts an `aes` action with the payment preimage (hex). `None` for
+    /// other actions.
+    pub fn decrypt(&self, preimage: &str) -> Result<Option<String>> {
+        let SuccessAction::Aes { ciphertext, iv, .. } = self else {
+            return Ok(None);
+        };
+        let key: [u8; 32] = hex::decode(preimage)
+            .ok()
+            .and_then(|k| k.try_into().ok())
+            .ok_or_else(|| anyhow!("Preimage is not 32 bytes of hex"))?;
+        let engine = base64::engine::general_purpose::STANDARD;
+        let iv: [u8; 16] = engine
+            .decode(iv)
+            .ok()
+            .and_then(|iv| iv.try_into().ok())
+            .ok_or_else(|| anyhow!("successAction iv is not 16 bytes of base64"))?;
+        let mut buffer = engine.decode(ciphertext).context("successAction ciphertext is not base64")?;
+        let plaintext = cbc::Decryptor::<aes::Aes256>::new(&key.into(), &iv.into())
+            .decrypt_padded_mut::<Pkcs7>(&mut buffer)
+            .map_err(|_| anyhow!("successAction doesn't decrypt with the payment preimage"))?;
+        let plaintext = String::from_utf8(plaintext.to_vec()).context("Decrypted successAction is not UTF-8")?;
+        Ok(Some(plaintext))
+    }
+
+    /// Name used in the history (`message`, `url`, `aes`).
+    pub fn tag(&self) -> &'static str {
+        match self {
+            SuccessAction::Message { .. } => "message",
+            SuccessAction::Url { .. } => "url",
+            SuccessAction::Aes { .. } => "aes",
+            SuccessAction::Unsupported => "unsupported",
+        }
+    }
+}
+
+impl std::fmt::Display for SuccessAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SuccessAction::Message { message } => f.write_str(message),
+            SuccessAction::Url { description, url } => write!(f, "{} {}", description, url),
+            SuccessAction::Aes { description, .. } => f.write_str(description),
+            SuccessAction::Unsupported => f.write_str("(unsupported successAction)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Encrypted with `openssl enc -aes-256-cbc`, key 0x44 * 32 and iv "0123456789abcdef".
+    fn aes(ciphertext: &str) -> SuccessAction {
+        SuccessAction::Aes {
+            description: "Your code".into(),
+            ciphertext: ciphertext.into(),
+            iv: "MDEyMzQ1Njc4OWFiY2RlZg==".into(),
+        }
+    }
+
+    #[test]
+    fn decrypts_aes_with_the_preimage() {
+        let preimage = "44".repeat(32);
+        let voucher = aes("Y+aBiJZJEsqIbFVRmUsd1gqPDD15ED28aNDxjrgcReQ=");
+        assert_eq!(voucher.decrypt(&preimage).unwrap().as_deref(), Some("voucher code: ABC-123"));
+        // A whole block of plaintext is followed by a whole block of padding.
+        let block = aes("BQvKkDGhkucLonxCmPnvBYcgToshCWXGoOx2+Hcl+y8=");
+        assert_eq!(block.decrypt(&preimage).unwrap().as_deref(), Some("sixteen bytes!!!"));
+    }
+
+    #[test]
+    fn refuses_a_wrong_preimage() {
+        let voucher = aes("Y+aBiJZJEsqIbFVRmUsd1gqPDD15ED28aNDxjrgcReQ=");
+        assert!(voucher.decrypt(&"55".repeat(32)).is_err());
+        assert!(voucher.decrypt("44").is_err());
+    }
+}
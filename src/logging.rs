@@ -0,0 +1,330 @@
+//! Console and file logging.
+//!
+//! Progress messages go through the `log` macros, which are bridged into a
+//! `tracing` subscriber along with the `http` and `node` spans of each
+//! request and node call (and the messages of dependencies). On the console, info and
+//! below are printed as plain lines on stdout, warnings and errors on stderr.
+//! In plugin mode stdout belongs to lightningd, so console messages are sent
+//! as CLN `log` notifications instead; with `--json` it belongs to the
+//! document, so everything goes to stderr. Each HTTP request and node call is
+//! logged at debug level, with its duration; debug and trace messages start
+//! with the spans they belong to. With `--log-file` the same messages are also
+//! written, timestamped and with their spans, to a file that is rotated by
+//! size and optionally once a day.
+
+use crate::color::{self, Color, Stream};
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDate};
+use log::LevelFilter as LogLevelFilter;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::{span, Event, Level, Subscriber};
+use tracing_log::{AsTrace, NormalizeEvent};
+use tracing_subscriber::layer::{self, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+
+static CAPTURE: Mutex<Option<Sender<String>>> = Mutex::new(None);
+
+/// Logging settings, built from the global CLI flags.
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    pub console_level: LogLevelFilter,
+    pub file: Option<PathBuf>,
+    pub file_level: LogLevelFilter,
+    /// Rotate once the file would grow past this many bytes.
+    pub max_size: u64,
+    /// Also rotate when the local date changes.
+    pub rotate_daily: bool,
+    /// Number of rotated files kept (`<file>.1` is the most recent).
+    pub keep: usize,
+    /// Send console messages as CLN plugin `log` notifications.
+    pub plugin: bool,
+    /// Leave stdout to the `--json` document: info and below go to stderr too.
+    pub json: bool,
+    /// Also pass on the messages of dependencies (`-vvv`), e.g. ureq's and
+    /// rustls' own traces.
+    pub dependencies: bool,
+}
+
+/// Log file with size/date based rotation.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    day: NaiveDate,
+    max_size: u64,
+    rotate_daily: bool,
+    keep: usize,
+}
+
+impl RotatingFile {
+    fn open(config: &LogConfig, path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create log directory {}", dir.display()))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open log file {}", path.display()))?;
+        let meta = file.metadata()?;
+        // An existing file belongs to the day it was last written.
+        let day = meta
+            .modified()
+            .map(|t| chrono::DateTime::<Local>::from(t).date_naive())
+            .unwrap_or_else(|_| Local::now().date_naive());
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size: meta.len(),
+            day,
+            max_size: config.max_size,
+            rotate_daily: config.rotate_daily,
+            keep: config.keep,
+        })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    /// Shifts `<file>.N` to `<file>.N+1` (dropping the oldest) and starts a new file.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if self.keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(self.rotated_path(self.keep));
+            for n in (1..self.keep).rev() {
+                let from = self.rotated_path(n);
+                if from.exists() {
+                    std::fs::rename(&from, self.rotated_path(n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let today = Local::now().date_naive();
+        let len = line.len() as u64 + 1;
+        let too_big = self.size > 0 && self.size + len > self.max_size;
+        let new_day = self.rotate_daily && self.size > 0 && today != self.day;
+        if too_big || new_day {
+            self.rotate()?;
+        }
+        self.day = today;
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+struct Logger {
+    console_level: LevelFilter,
+    plugin: bool,
+    json: bool,
+    dependencies: bool,
+    file_level: LevelFilter,
+    file: Option<Arc<Mutex<RotatingFile>>>,
+}
+
+/// A span's name and fields as shown before the messages inside it, e.g.
+/// `http{url=https://service.example/pay}`.
+struct SpanFields(String);
+
+/// Collects an event's message and fields, or a span's fields.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    rest: String,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, format_args!("{}", value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record(field, format_args!("{:?}", value));
+    }
+}
+
+impl Fields {
+    fn record(&mut self, field: &Field, value: fmt::Arguments) {
+        use std::fmt::Write as _;
+        match field.name() {
+            "message" => self.message = value.to_string(),
+            // Added by the bridge for `log` records.
+            name if name.starts_with("log.") => {}
+            name => {
+                let sep = if self.rest.is_empty() { "" } else { " " };
+                let _ = write!(self.rest, "{}{}={}", sep, name, value);
+            }
+        }
+    }
+}
+
+impl<S> Layer<S> for Logger
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn enabled(&self, metadata: &tracing::Metadata<'_>, _ctx: layer::Context<'_, S>) -> bool {
+        *metadata.level() <= self.max_level()
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(self.max_level())
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: layer::Context<'_, S>) {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(format!("{}{{{}}}", attrs.metadata().name(), fields.rest)));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: layer::Context<'_, S>) {
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+        // Only this crate's messages; dependencies log at debug/trace internally.
+        if !self.dependencies && !metadata.target().starts_with(env!("CARGO_CRATE_NAME")) {
+            return;
+        }
+        let level = *metadata.level();
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let mut message = fields.message;
+        if !fields.rest.is_empty() {
+            message = format!("{} {}", message, fields.rest);
+        }
+        let scope = ctx
+            .event_scope(event)
+            .map(|scope| {
+                scope
+                    .from_root()
+                    .filter_map(|span| span.extensions().get::<SpanFields>().map(|f| format!("{}: ", f.0)))
+                    .collect::<String>()
+            })
+            .unwrap_or_default();
+        if level <= self.console_level {
+            // Debug and trace messages show the request or node call they belong to.
+            let text = if level >= Level::DEBUG { format!("{}{}", scope, message) } else { message.clone() };
+            self.log_console(level, &text);
+        }
+        if level <= self.file_level {
+            self.log_file(level, &format!("{}{}", scope, message));
+        }
+    }
+}
+
+impl Logger {
+    fn max_level(&self) -> LevelFilter {
+        if self.file.is_some() {
+            self.console_level.max(self.file_level)
+        } else {
+            self.console_level
+        }
+    }
+
+    fn log_console(&self, level: Level, text: &str) {
+        if self.plugin {
+            let level = match level {
+                Level::ERROR => "error",
+                Level::WARN => "warn",
+                Level::INFO => "info",
+                _ => "debug",
+            };
+            crate::plugin::notify("log", serde_json::json!({ "level": level, "message": text }));
+            return;
+        }
+        match CAPTURE.lock().ok().and_then(|capture| capture.clone()) {
+            Some(sender) => {
+                let _ = sender.send(match level {
+                    Level::ERROR => format!("Error: {}", text),
+                    Level::WARN => format!("Warning: {}", text),
+                    _ => text.to_string(),
+                });
+            }
+            None => crate::progress::suspend(|| match level {
+                Level::ERROR => eprintln!("{} {}", color::paint(Stream::Stderr, Color::Red, "Error:"), text),
+                Level::WARN => eprintln!("{} {}", color::paint(Stream::Stderr, Color::Yellow, "Warning:"), text),
+                _ if self.json => eprintln!("{}", text),
+                _ => println!("{}", text),
+            }),
+        }
+    }
+
+    fn log_file(&self, level: Level, text: &str) {
+        if let Some(file) = &self.file {
+            let line = format!(
+                "{} {:<5} {}",
+                Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+                level,
+                text
+            );
+            if let Ok(mut file) = file.lock() {
+                let _ = file.write_line(&line);
+            }
+        }
+    }
+}
+
+/// Sends console messages to `sender` instead of the terminal, until called
+/// with `None`; the TUI shows them in its status pane. The log file is
+/// written as usual.
+pub fn capture(sender: Option<Sender<String>>) {
+    if let Ok(mut capture) = CAPTURE.lock() {
+        *capture = sender;
+    }
+}
+
+/// Log file of the installed logger, for `flush`.
+static FILE: Mutex<Option<Arc<Mutex<RotatingFile>>>> = Mutex::new(None);
+
+/// Writes out stdout and the log file, before the process exits.
+pub fn flush() {
+    let _ = std::io::stdout().flush();
+    if let Some(file) = FILE.lock().ok().and_then(|file| file.clone()) {
+        if let Ok(mut file) = file.lock() {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// Installs the global subscriber; `log` records from this crate and its
+/// dependencies are passed to it too.
+pub fn init(config: &LogConfig) -> Result<()> {
+    let file = match &config.file {
+        Some(path) => Some(Arc::new(Mutex::new(RotatingFile::open(config, path)?))),
+        None => None,
+    };
+    if let Ok(mut installed) = FILE.lock() {
+        installed.clone_from(&file);
+    }
+    let logger = Logger {
+        console_level: config.console_level.as_trace(),
+        plugin: config.plugin,
+        json: config.json,
+        dependencies: config.dependencies,
+        file_level: config.file_level.as_trace(),
+        file,
+    };
+    tracing_subscriber::registry()
+        .with(logger)
+        .try_init()
+        .context("Logger already initialized")
+}
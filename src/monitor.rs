@@ -0,0 +1,280 @@
+//! Health monitoring of LNURL services (`monitor` command).
+//!
+//! Each round GETs every target endpoint in parallel and validates the answer
+//! the way the flows would before moving funds: HTTP status, JSON, `tag` and the
+//! fields that tag requires, k1 format, callback scheme and response signature
+//! for pinned hosts. No callback is ever called. Results are appended to
+//! `monitor.jsonl` in the data directory, can be written as Prometheus metrics
+//! (textfile collector format), and up/down changes can be POSTed to a webhook.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Per-probe timeout; a slower answer counts as down.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// What to probe and where to report (`monitor` arguments).
+pub struct MonitorConfig {
+    pub targets: Vec<Url>,
+    pub interval: Duration,
+    /// Stop after this many rounds (forever if `None`).
+    pub rounds: Option<u64>,
+    pub metrics_file: Option<PathBuf>,
+    pub notify_url: Option<String>,
+}
+
+/// Result of probing one target once (one line of `monitor.jsonl`).
+#[derive(Debug, Clone, Serialize)]
+pub struct Probe {
+    pub timestamp: String,
+    pub url: String,
+    pub up: bool,
+    pub status: Option<u16>,
+    pub latency_ms: u64,
+    pub tag: Option<String>,
+    /// Why the probe failed; empty when up.
+    pub problems: Vec<String>,
+}
+
+/// Running totals for one target.
+#[derive(Default)]
+struct Stats {
+    probes: u64,
+    up: u64,
+    last: Option<Probe>,
+}
+
+/// Reads targets from a file, one URL per line; blank lines and `#` comments are skipped.
+pub fn read_targets(path: &std::path::Path) -> Result<Vec<Url>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| Url::parse(l).with_context(|| format!("Invalid target '{}' in {}", l, path.display())))
+        .collect()
+}
+
+/// Problems with a 2xx response body, by LNURL tag.
+fn validate(json: &serde_json::Value, problems: &mut Vec<String>) {
+    if json.get("status").and_then(|s| s.as_str()) == Some("ERROR") {
+        let reason = json.get("reason").and_then(|r| r.as_str()).unwrap_or("no reason");
+        problems.push(format!("service error: {}", reason));
+        return;
+    }
+    let Some(tag) = json.get("tag").and_then(|t| t.as_str()) else {
+        problems.push("no tag".to_string());
+        return;
+    };
+    let required: &[&str] = match tag {
+        "channelRequest" => &["uri", "callback", "k1"],
+        "hostedChannelRequest" => &["uri", "k1"],
+        "withdrawRequest" => &["callback", "k1", "minWithdrawable", "maxWithdrawable"],
+        "payRequest" => &["callback", "minSendable", "maxSendable", "metadata"],
+        _ => &[],
+    };
+    for field in required {
+        if json.get(*field).is_none() {
+            problems.push(format!("{} without {}", tag, field));
+        }
+    }
+    if let Some(k1) = json.get("k1").and_then(|k| k.as_str()) {
+        problems.extend(crate::k1::format_issues(k1).into_iter().map(|(_, issue)| issue));
+    }
+    if let Some(callback) = json.get("callback").and_then(|c| c.as_str()) {
+        match Url::parse(callback) {
+            Ok(u) if matches!(u.scheme(), "http" | "https") => {}
+            _ => problems.push(format!("invalid callback '{}'", callback)),
+        }
+    }
+    let min = json.get("minWithdrawable").or_else(|| json.get("minSendable")).and_then(|v| v.as_u64());
+    let max = json.get("maxWithdrawable").or_else(|| json.get("maxSendable")).and_then(|v| v.as_u64());
+    if let (Some(min), Some(max)) = (min, max) {
+        if min > max {
+            problems.push(format!("minimum {} above maximum {}", min, max));
+        }
+    }
+}
+
+fn probe(agent: &ureq::Agent, pins: &[crate::signed::PinnedKey], url: &Url) -> Probe {
+    let started = Instant::now();
+    let mut probe = Probe {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        url: url.to_string(),
+        up: false,
+        status: None,
+        latency_ms: 0,
+        tag: None,
+        problems: Vec::new(),
+    };
+    let response = match agent.get(url.as_str()).timeout(PROBE_TIMEOUT).call() {
+        Ok(r) | Err(ureq::Error::Status(_, r)) => r,
+        Err(e) => {
+            probe.latency_ms = started.elapsed().as_millis() as u64;
+            probe.problems.push(e.to_string());
+            return probe;
+        }
+    };
+    probe.status = Some(response.status());
+    let signature = response.header(crate::signed::SIGNATURE_HEADER).map(|s| s.to_string());
+    let body = response.into_string();
+    probe.latency_ms = started.elapsed().as_millis() as u64;
+    let body = match body {
+        Ok(body) => body,
+        Err(e) => {
+            probe.problems.push(format!("failed to read body: {}", e));
+            return probe;
+        }
+    };
+    if let Err(e) = crate::signed::verify(pins, url.host_str().unwrap_or(""), body.as_bytes(), signature.as_deref()) {
+        probe.problems.push(format!("{:#}", e));
+    }
+    let status = probe.status.unwrap_or(0);
+    if !(200..300).contains(&status) {
+        probe.problems.push(format!("HTTP {}", status));
+    } else {
+        match serde_json::from_str::<serde_json::Value>(&body) {
+            Ok(json) => {
+                probe.tag = json.get("tag").and_then(|t| t.as_str()).map(|t| t.to_string());
+                validate(&json, &mut probe.problems);
+            }
+            Err(e) => probe.problems.push(format!("invalid JSON: {}", e)),
+        }
+    }
+    probe.up = probe.problems.is_empty();
+    probe
+}
+
+fn append_log(probes: &[Probe]) -> Result<()> {
+    let path = crate::get_data_dir().join("monitor.jsonl");
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    for probe in probes {
+        writeln!(file, "{}", serde_json::to_string(probe)?)?;
+    }
+    Ok(())
+}
+
+/// Name, type, help text and value of one exported metric.
+type Metric = (&'static str, &'static str, &'static str, fn(&Stats) -> f64);
+
+/// Escapes a Prometheus label value.
+fn label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Writes all metrics, replacing the file atomically so a scraper never sees half of it.
+fn write_metrics(path: &std::path::Path, config: &MonitorConfig, stats: &HashMap<String, Stats>) -> Result<()> {
+    let mut out = String::new();
+    let metrics: [Metric; 4] = [
+        ("lnurl_monitor_up", "gauge", "Whether the last probe passed.", |s| {
+            s.last.as_ref().map_or(0.0, |p| p.up as u8 as f64)
+        }),
+        ("lnurl_monitor_latency_seconds", "gauge", "Duration of the last probe.", |s| {
+            s.last.as_ref().map_or(0.0, |p| p.latency_ms as f64 / 1000.0)
+        }),
+        ("lnurl_monitor_probes_total", "counter", "Probes made.", |s| s.probes as f64),
+        ("lnurl_monitor_probes_up_total", "counter", "Probes that passed.", |s| s.up as f64),
+    ];
+    for (name, kind, help, value) in metrics {
+        out.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+        for target in &config.targets {
+            if let Some(s) = stats.get(target.as_str()) {
+                out.push_str(&format!("{}{{url=\"{}\"}} {}\n", name, label(target.as_str()), value(s)));
+            }
+        }
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, out).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn notify(agent: &ureq::Agent, notify_url: &str, probe: &Probe) {
+    let result = agent
+        .post(notify_url)
+        .timeout(PROBE_TIMEOUT)
+        .send_json(serde_json::json!({
+            "event": if probe.up { "up" } else { "down" },
+            "probe": probe,
+        }));
+    if let Err(e) = result {
+        log::warn!("failed to notify {}: {}", notify_url, e);
+    }
+}
+
+/// Probes the targets every `interval` until `rounds` is reached.
+pub fn run(agent: &ureq::Agent, pins: &[crate::signed::PinnedKey], config: &MonitorConfig) -> Result<()> {
+    if config.targets.is_empty() {
+        return Err(anyhow!("No targets to monitor"));
+    }
+    log::info!(
+        "Monitoring {} endpoint(s) every {}s",
+        config.targets.len(),
+        config.interval.as_secs()
+    );
+    let mut stats: HashMap<String, Stats> = HashMap::new();
+    let mut round = 0u64;
+    loop {
+        round += 1;
+        let started = Instant::now();
+        let probes: Vec<Probe> = std::thread::scope(|scope| {
+            let handles: Vec<_> = config
+                .targets
+                .iter()
+                .map(|url| scope.spawn(move || probe(agent, pins, url)))
+                .collect();
+            handles.into_iter().map(|h| h.join().expect("probe thread panicked")).collect()
+        });
+
+        for probe in &probes {
+            let entry = stats.entry(probe.url.clone()).or_default();
+            let was_up = entry.last.as_ref().map(|p| p.up);
+            entry.probes += 1;
+            entry.up += probe.up as u64;
+            let uptime = 100.0 * entry.up as f64 / entry.probes as f64;
+            if probe.up {
+                log::info!(
+                    "UP    {} ({}, {} ms, {:.1}% uptime)",
+                    probe.url,
+                    probe.tag.as_deref().unwrap_or("-"),
+                    probe.latency_ms,
+                    uptime
+                );
+            } else {
+                log::warn!("DOWN  {}: {} ({:.1}% uptime)", probe.url, probe.problems.join("; "), uptime);
+            }
+            // Notify on changes only, plus a first probe that is already down.
+            if was_up != Some(probe.up) && (was_up.is_some() || !probe.up) {
+                if let Some(notify_url) = &config.notify_url {
+                    notify(agent, notify_url, probe);
+                }
+            }
+            entry.last = Some(probe.clone());
+        }
+
+        if let Err(e) = append_log(&probes) {
+            log::warn!("failed to record probes: {:#}", e);
+        }
+        if let Some(path) = &config.metrics_file {
+            if let Err(e) = write_metrics(path, config, &stats) {
+                log::warn!("failed to write metrics: {:#}", e);
+            }
+        }
+
+        if config.rounds.is_some_and(|n| round >= n) {
+            return Ok(());
+        }
+        std::thread::sleep(config.interval.saturating_sub(started.elapsed()));
+    }
+}
@@ -0,0 +1,598 @@
+//! Network setup for LNURL HTTP requests: IP family selection, static host
+//! overrides, DNS-over-HTTPS, address family alternation for dual-stack
+//! hosts, and a guard against services steering requests into the local
+//! network.
+
+use anyhow::{anyhow, Context};
+use std::fmt;
+use std::io;
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Which address families may be used to reach LNURL servers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpFamily {
+    Any,
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    fn allows(self, addr: &SocketAddr) -> bool {
+        match self {
+            IpFamily::Any => true,
+            IpFamily::V4 => addr.is_ipv4(),
+            IpFamily::V6 => addr.is_ipv6(),
+        }
+    }
+}
+
+impl fmt::Display for IpFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpFamily::Any => write!(f, "IP"),
+            IpFamily::V4 => write!(f, "IPv4"),
+            IpFamily::V6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+/// Pins a hostname (and optionally a port) to fixed addresses, bypassing DNS.
+/// Parsed from curl's `--resolve` syntax: `host:port:addr[,addr]...`, where
+/// port may be `*` and IPv6 addresses may be bracketed.
+#[derive(Debug, Clone)]
+pub struct ResolveOverride {
+    host: String,
+    port: Option<u16>,
+    addrs: Vec<IpAddr>,
+}
+
+impl ResolveOverride {
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    fn matches(&self, host: &str, port: u16) -> bool {
+        self.host.eq_ignore_ascii_case(host) && self.port.is_none_or(|p| p == port)
+    }
+}
+
+impl FromStr for ResolveOverride {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut parts = s.splitn(3, ':');
+        let (host, port, addrs) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(h), Some(p), Some(a)) if !h.is_empty() && !a.is_empty() => (h, p, a),
+            _ => return Err(anyhow!("Invalid resolve entry '{}', expected host:port:addr", s)),
+        };
+        let port = match port {
+            "*" => None,
+            p => Some(p.parse::<u16>().with_context(|| format!("Invalid port in resolve entry '{}'", s))?),
+        };
+        let addrs = addrs
+            .split(',')
+            .map(|a| {
+                let a = a.trim().trim_start_matches('[').trim_end_matches(']');
+                IpAddr::from_str(a).with_context(|| format!("Invalid address '{}' in resolve entry '{}'", a, s))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            addrs,
+        })
+    }
+}
+
+/// Hosts the user named explicitly (LNURL URLs on the command line, custom
+/// endpoints, `--resolve` entries). Shared between the resolver and the flows.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedHosts(Arc<Mutex<HashSet<String>>>);
+
+impl TrustedHosts {
+    pub fn add(&self, host: &str) {
+        self.0.lock().unwrap().insert(host.to_ascii_lowercase());
+    }
+
+    pub fn contains(&self, host: &str) -> bool {
+        self.0.lock().unwrap().contains(&host.to_ascii_lowercase())
+    }
+}
+
+/// What to do when a callback points at a different host than the LNURL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SameOrigin {
+    Off,
+    Warn,
+    Strict,
+}
+
+/// How requests reach Tor (`--tor`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Tor {
+    /// A Tor daemon's SOCKS port on 127.0.0.1:9050.
+    System,
+    /// The Tor client built in (feature `tor`).
+    #[cfg(feature = "tor")]
+    Builtin,
+}
+
+/// SOCKS port of a Tor daemon with the default settings.
+pub const TOR_SOCKS: &str = "socks5h://127.0.0.1:9050";
+
+/// Network settings, built from the global CLI flags.
+#[derive(Debug, Clone)]
+pub struct NetConfig {
+    pub family: IpFamily,
+    pub overrides: Vec<ResolveOverride>,
+    /// DNS-over-HTTPS endpoint used instead of the system resolver.
+    pub doh_url: Option<String>,
+    /// Only these hosts may resolve to loopback, private or link-local
+    /// addresses, unless `allow_private` is set.
+    pub trusted: TrustedHosts,
+    pub allow_private: bool,
+    /// Ports callbacks on other hosts may use, besides 80 and 443.
+    pub callback_ports: Vec<u16>,
+    pub same_origin: SameOrigin,
+    /// Domains (and their subdomains) callbacks may be delegated to.
+    pub delegate_domains: Vec<String>,
+    /// Check server certificates against the trust-on-first-use store.
+    pub tls_tofu: bool,
+    /// PEM files of CAs trusted besides the web PKI roots.
+    pub ca_certs: Vec<std::path::PathBuf>,
+    /// Public keys the certificates of hosts must have.
+    pub cert_pins: Vec<crate::tofu::CertPin>,
+    /// Hosts whose responses must be signed (see `signed`).
+    pub pinned_keys: Vec<crate::signed::PinnedKey>,
+    /// Proxy for all requests, e.g. `http://proxy:3128` or
+    /// `socks5h://127.0.0.1:9050` for Tor.
+    pub proxy: Option<String>,
+    /// `User-Agent` header instead of ureq's.
+    pub user_agent: Option<String>,
+    /// Time to connect to a server before giving up; the OS's when `None`.
+    pub connect_timeout: Option<Duration>,
+}
+
+impl Default for NetConfig {
+    /// The settings without any network flag: either family, the system
+    /// resolver, TOFU on and callbacks to other hosts allowed.
+    fn default() -> Self {
+        Self {
+            family: IpFamily::Any,
+            overrides: Vec::new(),
+            doh_url: None,
+            trusted: TrustedHosts::default(),
+            allow_private: false,
+            callback_ports: Vec::new(),
+            same_origin: SameOrigin::Off,
+            delegate_domains: Vec::new(),
+            tls_tofu: true,
+            ca_certs: Vec::new(),
+            cert_pins: Vec::new(),
+            pinned_keys: Vec::new(),
+            proxy: None,
+            user_agent: None,
+            connect_timeout: None,
+        }
+    }
+}
+
+impl NetConfig {
+    /// Whether `host` is a configured delegate domain or one of its subdomains.
+    pub fn is_delegate(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.delegate_domains.iter().any(|d| {
+            let d = d.trim_start_matches('.').to_ascii_lowercase();
+            host == d || host.ends_with(&format!(".{}", d))
+        })
+    }
+
+    /// Refuses `url` if it reaches an internal address through the proxy.
+    /// With a proxy the resolver only sees the proxy's own host, so its
+    /// check runs here instead, before the request: IP addresses and local
+    /// names (`localhost`, `.local`, single labels) are refused as such.
+    /// Other names are resolved locally for an HTTP proxy; a SOCKS5 proxy
+    /// resolves them itself, which is the point of using one.
+    pub fn check_proxied(&self, url: &str) -> anyhow::Result<()> {
+        let Some(proxy) = &self.proxy else {
+            return Ok(());
+        };
+        let Ok(url) = url::Url::parse(url) else {
+            return Ok(());
+        };
+        let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+            return Ok(());
+        };
+        let netloc = format!("{}:{}", host, port);
+        let bare = host.trim_start_matches('[').trim_end_matches(']');
+        if self.allow_private || self.trusted.contains(bare) {
+            return Ok(());
+        }
+        let addrs = match url.host() {
+            Some(url::Host::Ipv4(ip)) => vec![IpAddr::V4(ip)],
+            Some(url::Host::Ipv6(ip)) => vec![IpAddr::V6(ip)],
+            Some(url::Host::Domain(name)) if is_local_name(name) => {
+                return Err(crate::LnurlError::invalid(format!(
+                    "refusing to connect to {}: {} is a local name and the host was not given on the command line (use --allow-private-callbacks to permit)",
+                    netloc, name
+                ))
+                .into());
+            }
+            Some(url::Host::Domain(_)) if !proxy.starts_with("socks") => {
+                let resolver = resolver(self)?;
+                let resolved = match resolver.lookup_override(&netloc) {
+                    Some(addrs) => Ok(addrs),
+                    None => resolver.lookup(&netloc),
+                };
+                match resolved {
+                    Ok(addrs) => addrs.into_iter().map(|a| a.ip()).collect(),
+                    Err(e) => {
+                        // Possibly a name only the proxy knows.
+                        log::debug!("{} doesn't resolve locally, leaving it to the proxy: {}", netloc, e);
+                        return Ok(());
+                    }
+                }
+            }
+            _ => return Ok(()),
+        };
+        refuse_internal(&netloc, &addrs).map_err(|e| crate::LnurlError::invalid(e.to_string()).into())
+    }
+}
+
+/// Names that only mean something on the local network.
+fn is_local_name(name: &str) -> bool {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    !name.contains('.') || [".localhost", ".local", ".internal", ".lan", ".home.arpa"].iter().any(|s| name.ends_with(s))
+}
+
+/// Fails if any of the addresses `netloc` resolved to is internal.
+fn refuse_internal(netloc: &str, addrs: &[IpAddr]) -> io::Result<()> {
+    match addrs.iter().find(|ip| is_internal(**ip)) {
+        Some(ip) => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "refusing to connect to {}: {} is an internal address and the host was not given on the command line (use --allow-private-callbacks to permit)",
+                netloc, ip
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Whether `ip` is inside the local network or the host itself: loopback,
+/// RFC 1918 / unique-local, link-local (including the 169.254.169.254 cloud
+/// metadata service), CGNAT, unspecified, broadcast and other special-use
+/// addresses. IPv6 addresses that carry an IPv4 one (mapped, compatible,
+/// NAT64 and 6to4) are judged by the IPv4 address they lead to.
+pub fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_internal_v4(v4),
+        IpAddr::V6(v6) => {
+            if let Some(v4) = embedded_v4(v6) {
+                return is_internal_v4(v4);
+            }
+            let first = v6.segments()[0];
+            v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || (first & 0xfe00) == 0xfc00 // unique local fc00::/7
+                || (first & 0xffc0) == 0xfe80 // link-local fe80::/10
+                || v6.segments()[..3] == [0x64, 0xff9b, 1] // local-use NAT64 64:ff9b:1::/48
+        }
+    }
+}
+
+/// The IPv4 address an IPv6 one stands for: IPv4-mapped `::ffff:a.b.c.d`,
+/// the deprecated IPv4-compatible `::a.b.c.d`, well-known NAT64
+/// `64:ff9b::a.b.c.d` and 6to4 `2002:aabb:ccdd::`.
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return Some(v4);
+    }
+    let v4 = |hi: u16, lo: u16| Ipv4Addr::from((u32::from(hi) << 16) | u32::from(lo));
+    match ip.segments() {
+        // :: and ::1 are taken care of as IPv6.
+        [0, 0, 0, 0, 0, 0, hi, lo] if hi != 0 => Some(v4(hi, lo)),
+        [0x64, 0xff9b, 0, 0, 0, 0, hi, lo] => Some(v4(hi, lo)),
+        [0x2002, hi, lo, ..] => Some(v4(hi, lo)),
+        _ => None,
+    }
+}
+
+fn is_internal_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || a == 0
+        || a >= 240 // reserved 240.0.0.0/4
+        || (a == 100 && (64..128).contains(&b)) // CGNAT 100.64.0.0/10
+        || (a == 192 && b == 0 && c == 0) // IETF protocol assignments 192.0.0.0/24
+        || (a == 198 && (b == 18 || b == 19)) // benchmarking 198.18.0.0/15
+}
+
+/// Timeout for a single DNS-over-HTTPS query.
+const DOH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Builds the HTTP agent used for all requests to LNURL servers. An
+/// `LnurlClient` builds it once and every flow, clone and batch entry shares
+/// it, so connections to a host stay open and are reused (keep-alive) for
+/// the client's lifetime. ureq speaks HTTP/1.1 only. Redirects are left to
+/// the caller, which checks each hop like a callback (see `HttpTransport`).
+pub fn build_agent(config: &NetConfig) -> anyhow::Result<ureq::Agent> {
+    Ok(agent_builder(config)?.redirects(0).resolver(resolver(config)?).build())
+}
+
+/// Builds the agent for requests to hosts the user chose (rate sources,
+/// updates, monitoring), which follows redirects itself: release downloads
+/// are redirected to a storage host.
+pub fn build_redirecting_agent(config: &NetConfig) -> anyhow::Result<ureq::Agent> {
+    Ok(agent_builder(config)?.resolver(resolver(config)?).build())
+}
+
+/// An agent builder with the TLS, proxy and header settings of `config`,
+/// shared by the LNURL agent and the DoH one.
+fn agent_builder(config: &NetConfig) -> anyhow::Result<ureq::AgentBuilder> {
+    let mut builder = ureq::AgentBuilder::new();
+    if config.tls_tofu || !config.ca_certs.is_empty() || !config.cert_pins.is_empty() {
+        builder = builder.tls_config(crate::tofu::client_config(&config.ca_certs, &config.cert_pins, config.tls_tofu)?);
+    }
+    if let Some(proxy) = &config.proxy {
+        // ureq leaves host names to a SOCKS5 proxy to resolve, which is
+        // what socks5h means elsewhere (and what reaches .onion hosts).
+        let spec = match proxy.strip_prefix("socks5h://") {
+            Some(rest) => format!("socks5://{}", rest),
+            None => proxy.clone(),
+        };
+        builder = builder.proxy(ureq::Proxy::new(&spec).with_context(|| format!("Invalid proxy '{}'", proxy))?);
+        // The user named it, so it may be local, like a Tor daemon.
+        if let Some(host) = url::Url::parse(&spec).ok().and_then(|url| url.host_str().map(|h| h.to_string())) {
+            config.trusted.add(host.trim_start_matches('[').trim_end_matches(']'));
+        }
+    }
+    if let Some(user_agent) = &config.user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+    if let Some(timeout) = config.connect_timeout {
+        builder = builder.timeout_connect(timeout);
+    }
+    Ok(builder)
+}
+
+fn resolver(config: &NetConfig) -> anyhow::Result<LnurlResolver> {
+    // The DoH endpoint itself goes through the system resolver (or none at
+    // all when given as an IP, e.g. https://1.1.1.1/dns-query), but through
+    // the proxy and certificate checks of the other requests, so that no
+    // LNURL host name leaves past a proxy.
+    let doh = match &config.doh_url {
+        Some(url) => Some((url.clone(), agent_builder(config)?.timeout(DOH_TIMEOUT).build())),
+        None => None,
+    };
+    Ok(LnurlResolver {
+        family: config.family,
+        overrides: config.overrides.clone(),
+        trusted: config.trusted.clone(),
+        allow_private: config.allow_private,
+        doh,
+    })
+}
+
+/// An HTTP answer, whatever its status.
+pub struct HttpResponse {
+    pub status: u16,
+    /// The response signature header (see `signed`), if sent.
+    pub signature: Option<String>,
+    /// The `Location` header, for redirects.
+    pub location: Option<String>,
+    /// The `ETag` and `Last-Modified` validators, for the cache (`cache`).
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: String,
+}
+
+/// Makes the GET requests of the LNURL flows. The agent from `build_agent` is
+/// the default; programs embedding the flows can put their own client behind
+/// it instead (other TLS settings, a proxy, a test double).
+pub trait HttpTransport: Send {
+    /// GETs `url` within `timeout`, sending `headers` (the cache's
+    /// conditional headers) along. Any HTTP answer is `Ok`; errors are
+    /// transport failures only (DNS, connection, TLS, timeout). Redirects
+    /// are returned, not followed: the client checks each hop like a
+    /// callback before following it.
+    fn get(&self, url: &str, timeout: Duration, headers: &[(&str, &str)]) -> anyhow::Result<HttpResponse>;
+}
+
+impl HttpTransport for ureq::Agent {
+    fn get(&self, url: &str, timeout: Duration, headers: &[(&str, &str)]) -> anyhow::Result<HttpResponse> {
+        let mut request = ureq::Agent::get(self, url).timeout(timeout);
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        match request.call() {
+            Ok(r) | Err(ureq::Error::Status(_, r)) => {
+                let status = r.status();
+                let header = |name| r.header(name).map(|s| s.to_string());
+                let signature = header(crate::signed::SIGNATURE_HEADER);
+                let location = header("Location");
+                let etag = header("ETag");
+                let last_modified = header("Last-Modified");
+                let body = r.into_string()?;
+                Ok(HttpResponse { status, signature, location, etag, last_modified, body })
+            }
+            // A refused certificate is a failed check, not a network failure.
+            Err(e) if is_certificate_error(&e) => Err(crate::LnurlError::invalid(e.to_string()).into()),
+            Err(e) => Err(anyhow!("{}", e)),
+        }
+    }
+}
+
+/// Whether `e` is the TLS handshake refusing the server's certificate: by
+/// the roots, or by a pin or the TOFU store (`tofu`).
+fn is_certificate_error(e: &ureq::Error) -> bool {
+    let mut source = std::error::Error::source(e);
+    while let Some(error) = source {
+        // rustls errors come wrapped in an io::Error, whose source skips them.
+        let tls = error
+            .downcast_ref::<rustls::Error>()
+            .or_else(|| error.downcast_ref::<io::Error>()?.get_ref()?.downcast_ref::<rustls::Error>());
+        if let Some(tls) = tls {
+            return matches!(tls, rustls::Error::InvalidCertificate(_) | rustls::Error::General(_));
+        }
+        source = error.source();
+    }
+    false
+}
+
+/// Resolver that applies host overrides, filters addresses by family and, for
+/// dual-stack hosts, alternates the families (see `interleave`).
+struct LnurlResolver {
+    family: IpFamily,
+    overrides: Vec<ResolveOverride>,
+    trusted: TrustedHosts,
+    allow_private: bool,
+    doh: Option<(String, ureq::Agent)>,
+}
+
+impl LnurlResolver {
+    /// Addresses pinned for `netloc` (`host:port`), if any override matches.
+    fn lookup_override(&self, netloc: &str) -> Option<Vec<SocketAddr>> {
+        let (host, port) = netloc.rsplit_once(':')?;
+        let port = port.parse::<u16>().ok()?;
+        self.overrides.iter().find(|o| o.matches(host, port)).map(|o| {
+            log::debug!("resolve override: {} -> {:?}", netloc, o.addrs);
+            o.addrs.iter().map(|ip| SocketAddr::new(*ip, port)).collect()
+        })
+    }
+
+    /// Resolves `netloc` through DoH when configured and the host is a name,
+    /// otherwise through the system resolver.
+    fn lookup(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let Some((doh_url, doh_agent)) = &self.doh else {
+            return Ok(netloc.to_socket_addrs()?.collect());
+        };
+        let (host, port) = netloc
+            .rsplit_once(':')
+            .and_then(|(h, p)| Some((h, p.parse::<u16>().ok()?)))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid address {}", netloc)))?;
+        let literal = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = IpAddr::from_str(literal) {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+        let ips = crate::doh::resolve(
+            doh_agent,
+            doh_url,
+            host,
+            self.family != IpFamily::V6,
+            self.family != IpFamily::V4,
+        )?;
+        Ok(ips.into_iter().map(|ip| SocketAddr::new(ip, port)).collect())
+    }
+}
+
+impl ureq::Resolver for LnurlResolver {
+    fn resolve(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let resolved = match self.lookup_override(netloc) {
+            Some(addrs) => addrs,
+            None => self.lookup(netloc)?,
+        };
+        let addrs: Vec<SocketAddr> = resolved
+            .into_iter()
+            .filter(|a| self.family.allows(a))
+            .collect();
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no {} address for {}", self.family, netloc),
+            ));
+        }
+        // Checked here rather than before the request, so a host can't pass
+        // a check and then resolve to an internal address (DNS rebinding).
+        let host = netloc.rsplit_once(':').map(|(h, _)| h).unwrap_or(netloc);
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if !self.allow_private && !self.trusted.contains(host) {
+            refuse_internal(netloc, &addrs.iter().map(|a| a.ip()).collect::<Vec<_>>())?;
+        }
+        Ok(interleave(addrs))
+    }
+}
+
+/// Alternates address families, keeping the resolver's order within each
+/// family and starting with the family of the first address (RFC 8305).
+/// This is the ordering half of happy eyeballs only: ureq has no connector
+/// hook, so connection attempts aren't raced. It tries the addresses one
+/// after the other, giving each half of the connect timeout left when there
+/// are several, so a host with a broken AAAA record still costs half of
+/// `--connect-timeout` before an IPv4 address is tried.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_is_v6 = addrs.first().map(|a| a.is_ipv6()).unwrap_or(true);
+    let (mut first, mut second): (Vec<_>, Vec<_>) =
+        addrs.into_iter().partition(|a| a.is_ipv6() == first_is_v6);
+    let mut out = Vec::with_capacity(first.len() + second.len());
+    first.reverse();
+    second.reverse();
+    loop {
+        match (first.pop(), second.pop()) {
+            (None, None) => break,
+            (a, b) => out.extend(a.into_iter().chain(b)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn internal(ip: &str) -> bool {
+        is_internal(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn refuses_internal_ipv4() {
+        for ip in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "0.0.0.0", "100.64.0.1",
+            "192.0.0.8", "198.18.0.1", "198.19.255.255", "224.0.0.1", "240.0.0.1", "255.255.255.255",
+        ] {
+            assert!(internal(ip), "{} should be internal", ip);
+        }
+        for ip in ["1.1.1.1", "100.128.0.1", "172.32.0.1", "192.0.1.1", "198.20.0.1", "8.8.8.8"] {
+            assert!(!internal(ip), "{} should be public", ip);
+        }
+    }
+
+    #[test]
+    fn refuses_internal_ipv6_and_embedded_ipv4() {
+        for ip in [
+            "::1", "::", "fc00::1", "fd12::1", "fe80::1", "ff02::1", "::ffff:127.0.0.1", "::ffff:169.254.169.254",
+            "::10.0.0.1", "64:ff9b::a9fe:a9fe", "64:ff9b::10.0.0.1", "64:ff9b:1::1", "2002:c0a8:0101::1",
+        ] {
+            assert!(internal(ip), "{} should be internal", ip);
+        }
+        for ip in ["2606:4700::1111", "::ffff:1.1.1.1", "64:ff9b::808:808", "2002:0808:0808::1"] {
+            assert!(!internal(ip), "{} should be public", ip);
+        }
+    }
+
+    #[test]
+    fn checks_proxied_hosts() {
+        let direct = NetConfig::default();
+        assert!(direct.check_proxied("http://10.0.0.1/cb").is_ok());
+        let proxied = NetConfig { proxy: Some("socks5h://127.0.0.1:9050".into()), ..NetConfig::default() };
+        assert!(proxied.check_proxied("http://10.0.0.1/cb").is_err());
+        assert!(proxied.check_proxied("http://[::ffff:127.0.0.1]/cb").is_err());
+        assert!(proxied.check_proxied("http://printer/cb").is_err());
+        assert!(proxied.check_proxied("http://nas.local/cb").is_err());
+        assert!(proxied.check_proxied("https://service.example/cb").is_ok());
+        proxied.trusted.add("10.0.0.1");
+        assert!(proxied.check_proxied("http://10.0.0.1/cb").is_ok());
+        let allowed = NetConfig { allow_private: true, ..proxied };
+        assert!(allowed.check_proxied("http://printer/cb").is_ok());
+    }
+}
@@ -0,0 +1,372 @@
+//! How the CLI shows what a command did.
+//!
+//! The flows log their progress and return their results; printing the
+//! outcome of a command is left to this module, on stdout: as text, or with
+//! `--json` as one document per run.
+
+use crate::color::{self, Color, Stream};
+use crate::{amount, decode, history, paylinks, rates, tofu, warnings};
+use crate::{Balance, ChannelOutcome, LnurlClient, LnurlError, Opened, Payment, Withdrawal};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+/// What a command produced: the `result` of the `--json` document.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Report {
+    /// Nothing beyond the log (`null`).
+    None,
+    Channel(ChannelOutcome),
+    Withdrawal(Withdrawal),
+    Payment(Payment),
+    Opened(Opened),
+    Balances(Vec<Balance>),
+    PayLinks(BTreeMap<String, paylinks::PayLink>),
+    Rate(rates::Rate),
+    History(Vec<history::Operation>),
+    KnownHosts(BTreeMap<String, tofu::KnownHost>),
+    /// Printed as is (a plaintext-accounting export).
+    Export(String),
+    /// Printed on a line (an encoded LNURL).
+    Line(String),
+    /// Printed as pretty JSON (a proof bundle, version details).
+    Json(serde_json::Value),
+    Decoded(decode::Decoded),
+    /// The outcome for each target read from stdin.
+    Batch(Vec<BatchItem>),
+    /// Where `--dry-run` stopped: the request to `url` it didn't send.
+    DryRun { step: String, url: String },
+}
+
+/// One target of a batch run (`-`): its `result` or `error`.
+#[derive(Debug, Serialize)]
+pub struct BatchItem {
+    pub target: String,
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Report>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<LnurlError>,
+}
+
+impl BatchItem {
+    pub fn new(target: String, result: Result<Report, LnurlError>) -> Self {
+        match result {
+            Ok(report) => BatchItem { target, status: "OK", result: Some(report), error: None },
+            Err(e) => BatchItem { target, status: "ERROR", result: None, error: Some(e) },
+        }
+    }
+}
+
+/// Prints `report` as text; with `qr`, followed by a QR code of what is
+/// there to scan (`--qr`).
+pub fn show(client: &LnurlClient, report: &Report, qr: bool) {
+    show_text(client, report);
+    if !qr {
+        return;
+    }
+    match shareable(report) {
+        // Bech32 (invoices, LNURLs) uppercased makes for a smaller code.
+        Some(data) if data.get(..2).is_some_and(|prefix| prefix.eq_ignore_ascii_case("ln")) => {
+            print_qr(&data.to_ascii_uppercase())
+        }
+        Some(data) => print_qr(data),
+        None => log::info!("Nothing to show as a QR code."),
+    }
+}
+
+/// Prints only the value a script wants from `report` (`--quiet`): the
+/// funding txid of a channel (its node URI until there is one), the invoice
+/// of a withdrawal, the preimage of a payment (its hash if the node didn't
+/// report it). Lists and decoded details have no single value and are shown
+/// as usual.
+pub fn show_quiet(client: &LnurlClient, report: &Report) {
+    match report {
+        Report::None | Report::Opened(Opened::Login) => {}
+        Report::Channel(outcome) | Report::Opened(Opened::Channel(outcome)) => match outcome {
+            ChannelOutcome::Opened(opened) => println!("{}", opened.txid.as_deref().unwrap_or(&opened.uri)),
+            ChannelOutcome::Hosted(hosted) => println!("{}", hosted.uri),
+        },
+        Report::Withdrawal(withdrawal) | Report::Opened(Opened::Withdraw(withdrawal)) => println!("{}", withdrawal.bolt11),
+        Report::Payment(payment) | Report::Opened(Opened::Pay(payment)) => {
+            println!("{}", payment.preimage.as_deref().unwrap_or(&payment.payment_hash))
+        }
+        Report::Rate(rate) => println!("{:.2}", rate.price_per_btc),
+        Report::Line(line) => println!("{}", line),
+        Report::DryRun { url, .. } => println!("{}", url),
+        // The failures were logged as errors, which --quiet still shows.
+        Report::Batch(items) => {
+            for result in items.iter().filter_map(|item| item.result.as_ref()) {
+                show_quiet(client, result);
+            }
+        }
+        _ => show_text(client, report),
+    }
+}
+
+/// What a command produced for another wallet or node: the invoice of a
+/// withdrawal, the node URI of a channel, an encoded LNURL.
+pub fn shareable(report: &Report) -> Option<&str> {
+    match report {
+        Report::Withdrawal(withdrawal) | Report::Opened(Opened::Withdraw(withdrawal)) => Some(&withdrawal.bolt11),
+        Report::Channel(outcome) | Report::Opened(Opened::Channel(outcome)) => Some(match outcome {
+            ChannelOutcome::Opened(opened) => &opened.uri,
+            ChannelOutcome::Hosted(hosted) => &hosted.uri,
+        }),
+        Report::Line(lnurl) => Some(lnurl),
+        _ => None,
+    }
+}
+
+fn show_text(client: &LnurlClient, report: &Report) {
+    match report {
+        Report::None => {}
+        Report::Channel(outcome) => channel(outcome),
+        Report::Withdrawal(result) => withdrawal(client, result),
+        Report::Payment(payment) => pay(payment),
+        Report::Opened(Opened::Pay(payment)) => pay(payment),
+        Report::Opened(Opened::Withdraw(result)) => withdrawal(client, result),
+        Report::Opened(Opened::Channel(outcome)) => channel(outcome),
+        Report::Opened(Opened::Login) => {}
+        Report::Balances(balances) => {
+            for balance in balances {
+                println!(
+                    "{}  {} withdrawable (min {})",
+                    balance.service,
+                    client.fmt_msat(balance.max_withdrawable),
+                    client.fmt_msat(balance.min_withdrawable)
+                );
+            }
+        }
+        Report::PayLinks(links) => {
+            if links.is_empty() {
+                log::info!("No reusable pay links saved in {}", paylinks::links_path().display());
+            }
+            for (url, link) in links {
+                println!(
+                    "{}  {}  (paid {} time(s), last {})",
+                    url,
+                    link.description.as_deref().unwrap_or("-"),
+                    link.uses,
+                    link.last_used
+                );
+            }
+        }
+        Report::Rate(rate) => println!(
+            "1 BTC = {:.2} {} (source: {}, fetched {}s ago)",
+            rate.price_per_btc,
+            rate.currency.to_uppercase(),
+            rate.source,
+            rate.age().as_secs()
+        ),
+        Report::History(ops) => {
+            for op in ops {
+                let tags: String = op.tags.iter().map(|t| format!(" #{}", t)).collect();
+                println!(
+                    "{}  {}  {:<12} {:>14}  {} {}{}",
+                    op.id,
+                    op.timestamp,
+                    op.kind,
+                    op.amount_msat.map(amount::show).unwrap_or_else(|| "-".to_string()),
+                    color::status(&format!("{:<8}", op.status)),
+                    op.domain(),
+                    tags
+                );
+                if let Some(note) = &op.note {
+                    println!("    {}", note);
+                }
+            }
+        }
+        Report::KnownHosts(hosts) => {
+            for (host, known) in hosts {
+                println!("{}  {}  (first seen {})", host, known.key, known.first_seen);
+                if let Some(pending) = &known.pending {
+                    println!("{}  {}  (presented since, not accepted)", host, pending);
+                }
+            }
+        }
+        Report::Export(text) => print!("{}", text),
+        Report::Line(line) => println!("{}", line),
+        Report::Json(value) => println!("{}", serde_json::to_string_pretty(value).unwrap_or_default()),
+        Report::Decoded(decoded) => show_decoded(decoded),
+        // Each outcome was shown as it came; this is the summary.
+        Report::Batch(items) => {
+            let failed: Vec<&BatchItem> = items.iter().filter(|item| item.error.is_some()).collect();
+            println!(
+                "{} target(s): {}, {}",
+                items.len(),
+                color::paint(Stream::Stdout, Color::Green, &format!("{} OK", items.len() - failed.len())),
+                match failed.len() {
+                    0 => "0 failed".to_string(),
+                    n => color::paint(Stream::Stdout, Color::Red, &format!("{} failed", n)),
+                }
+            );
+            for item in failed {
+                println!("  {}  {}", item.target, item.error.as_ref().map(|e| e.to_string()).unwrap_or_default());
+            }
+        }
+        Report::DryRun { step, url } => {
+            println!("Dry run: not sending the {}:", step);
+            println!("  {}", url);
+        }
+    }
+}
+
+fn show_decoded(decoded: &decode::Decoded) {
+    match decoded {
+        decode::Decoded::Lnurl(link) => {
+            println!("URL: {}", link.url);
+            match &link.kind {
+                Some(kind) => println!("Kind: LNURL-{}", kind),
+                None => println!("Kind: not known until the URL is fetched"),
+            }
+            if !link.secure {
+                println!("Scheme: not https; LUD-01 wallets refuse this link");
+            }
+            if !link.params.is_empty() {
+                println!("Parameters:");
+                for (key, value) in &link.params {
+                    println!("  {}: {}", key, value);
+                }
+            }
+        }
+        decode::Decoded::Invoice(invoice) => {
+            println!("Network: {}", invoice.network);
+            println!("Amount: {}", invoice.amount_msat.map(amount::show).unwrap_or_else(|| "any (chosen by the payer)".to_string()));
+            println!("Payee: {}", invoice.payee);
+            match (&invoice.description, &invoice.description_hash) {
+                (Some(description), _) => println!("Description: {}", description),
+                (None, Some(hash)) => println!("Description hash: {}", hash),
+                (None, None) => {}
+            }
+            println!("Payment hash: {}", invoice.payment_hash);
+            println!("Created: {}", invoice.created_at);
+            println!(
+                "Expiry: {}s, until {}{}",
+                invoice.expiry_secs,
+                invoice.expires_at,
+                if invoice.expired { " (expired)" } else { "" }
+            );
+            println!("Min final CLTV delta: {}", invoice.min_final_cltv_expiry_delta);
+            if invoice.route_hints > 0 {
+                println!("Route hints: {}", invoice.route_hints);
+            }
+        }
+    }
+}
+
+/// The `--json` document of a run: `request` (the command and its params),
+/// `status` (`OK` or `ERROR`), the `result` or `error`, the operations
+/// recorded since `started` with their HTTP exchanges, and the warnings
+/// raised.
+pub fn document(request: serde_json::Value, started: &str, result: &Result<Report, LnurlError>) -> serde_json::Value {
+    let mut document = request;
+    match result {
+        Ok(report) => {
+            document["status"] = json!("OK");
+            document["result"] = json!(report);
+        }
+        Err(e) => {
+            document["status"] = json!("ERROR");
+            document["error"] = json!(e);
+        }
+    }
+    document["operations"] = json!(history::since(started));
+    document["warnings"] = json!(warnings::raised());
+    document
+}
+
+/// A table of the operations recorded during the run, printed after its
+/// result so that a flow ends with what it did at each service.
+pub fn summary(operations: &[history::Operation]) {
+    if operations.is_empty() {
+        return;
+    }
+    println!();
+    println!("{:<14} {:<24} {:>14}  STATUS", "OPERATION", "SERVICE", "AMOUNT");
+    for op in operations {
+        let reason = op.reason.as_deref().map(|reason| format!("  {}", reason)).unwrap_or_default();
+        println!(
+            "{:<14} {:<24} {:>14}  {}{}",
+            op.kind,
+            op.domain(),
+            op.amount_msat.map(amount::show).unwrap_or_else(|| "-".to_string()),
+            color::status(&op.status),
+            reason
+        );
+    }
+}
+
+/// Renders `data` with two modules per character, light on dark so that
+/// it scans on the usual dark terminal background.
+fn print_qr(data: &str) {
+    use qrcode::render::unicode::Dense1x2;
+    match qrcode::QrCode::new(data) {
+        Ok(code) => println!(
+            "{}",
+            code.render::<Dense1x2>().dark_color(Dense1x2::Light).light_color(Dense1x2::Dark).build()
+        ),
+        Err(e) => log::warn!("cannot show {} as a QR code: {}", data, e),
+    }
+}
+
+fn channel(outcome: &ChannelOutcome) {
+    match outcome {
+        ChannelOutcome::Opened(opened) => {
+            println!("Channel opened successfully!");
+            if let Some(txid) = &opened.txid {
+                println!("  Transaction ID: {}", txid);
+            }
+            if let Some(channel_id) = &opened.channel_id {
+                println!("  Channel ID: {}", channel_id);
+            }
+            for (key, value) in &opened.confirmed {
+                println!("  {}: {}", key, value);
+            }
+        }
+        ChannelOutcome::Hosted(hosted) => {
+            println!("Connected to the host {}.", hosted.uri);
+            println!(
+                "Core Lightning has no hosted channel support of its own; \
+                 ask for the channel with a hosted-channels plugin, giving it the host and the secret {}.",
+                hosted.secret
+            );
+        }
+    }
+}
+
+fn withdrawal(client: &LnurlClient, withdrawal: &Withdrawal) {
+    match &withdrawal.label {
+        Some(label) => println!(
+            "Withdrawal successful! The service accepted invoice {} for {}.",
+            label,
+            client.fmt_msat(withdrawal.amount_msat)
+        ),
+        None => println!(
+            "Withdrawal successful! The service accepted the given invoice for {}.",
+            client.fmt_msat(withdrawal.amount_msat)
+        ),
+    }
+}
+
+fn pay(payment: &Payment) {
+    use crate::lnurl::success::SuccessAction;
+    println!("Payment sent!");
+    if let Some(preimage) = &payment.preimage {
+        println!("  Preimage: {}", preimage);
+    }
+    if !payment.disclosed.is_empty() {
+        println!("  Disclosed: {}", payment.disclosed.join(", "));
+    }
+    match &payment.success_action {
+        Some(SuccessAction::Message { message }) => println!("{}", message),
+        Some(SuccessAction::Url { description, url }) => println!("{}\n{}", description, url),
+        Some(SuccessAction::Aes { description, .. }) => match &payment.success_secret {
+            Some(plaintext) => println!("{}\n{}", description, plaintext),
+            None => println!("{}", description),
+        },
+        Some(SuccessAction::Unsupported) => log::debug!("ignoring unsupported successAction"),
+        None => {}
+    }
+}
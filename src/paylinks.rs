@@ -0,0 +1,70 @@
+//! Pay links seen by `pay`, kept by their `disposable` flag (LUD-11).
+//!
+//! A service marks a pay link `disposable: false` when it can be paid again,
+//! e.g. a static donation QR code. Those are saved with their metadata in the
+//! data directory (`pay_links.json`) and listed by `pay-links`. Other links are
+//! one-time by default; they are remembered too, so paying one a second time
+//! can be warned about.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayLink {
+    pub disposable: bool,
+    /// `text/plain` of the metadata.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The raw metadata string, only kept for reusable links.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<String>,
+    pub first_used: String,
+    pub last_used: String,
+    pub uses: u64,
+}
+
+pub fn links_path() -> std::path::PathBuf {
+    crate::get_data_dir().join("pay_links.json")
+}
+
+pub fn load() -> BTreeMap<String, PayLink> {
+    std::fs::read_to_string(links_path())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn save(links: &BTreeMap<String, PayLink>) -> Result<()> {
+    let path = links_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(links)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// The link as last recorded, if it was used before.
+pub fn get(url: &str) -> Option<PayLink> {
+    load().remove(url)
+}
+
+/// Records a use of the pay link `url` with what the service said about it.
+pub fn record(url: &str, disposable: bool, description: Option<String>, metadata: &str) -> Result<()> {
+    let mut links = load();
+    let now = chrono::Utc::now().to_rfc3339();
+    let link = links.entry(url.to_string()).or_insert_with(|| PayLink {
+        disposable,
+        description: None,
+        metadata: None,
+        first_used: now.clone(),
+        last_used: now.clone(),
+        uses: 0,
+    });
+    link.disposable = disposable;
+    link.description = description;
+    link.metadata = (!disposable).then(|| metadata.to_string());
+    link.last_used = now;
+    link.uses += 1;
+    save(&links)
+}
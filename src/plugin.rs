@@ -0,0 +1,167 @@
+//! Core Lightning plugin mode.
+//!
+//! Started by lightningd (which sets `LIGHTNINGD_PLUGIN`) or with the `plugin`
+//! subcommand, the client speaks the plugin protocol: JSON-RPC messages on
+//! stdin/stdout. It registers `lnurlwithdraw`, `lnurlpay`, `lnurlchannel` and
+//! `lnurlauth`, which run the same flows as the CLI against the node's own RPC
+//! socket, taken from the `init` configuration. Requests are handled one at a time; log
+//! messages are forwarded to the node log as `log` notifications.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::io::Write;
+
+/// JSON-RPC error codes returned to lightningd.
+const INVALID_PARAMS: i64 = -32602;
+const FLOW_FAILED: i64 = -32000;
+
+/// RPC methods: name, usage, description.
+const METHODS: &[(&str, &str, &str)] = &[
+    ("lnurlwithdraw", "url amount_msat [description]", "Withdraw from an LNURL-withdraw service (amount may be a percentage like 50%)"),
+    ("lnurlpay", "url amount_msat [comment] [payer_name] [payer_email] [payer_identifier] [payer_pubkey] [payer_auth] [verify_timeout]", "Pay an LNURL-pay service"),
+    ("lnurlchannel", "url", "Request an inbound channel from an LNURL-channel service"),
+    ("lnurlauth", "url", "Authenticate to an LNURL-auth service with the node key"),
+];
+
+/// Writes one message to lightningd.
+fn write(message: &Value) {
+    let mut out = std::io::stdout().lock();
+    let _ = write!(out, "{}\n\n", message);
+    let _ = out.flush();
+}
+
+/// Sends a notification (no reply expected), e.g. `log`.
+pub fn notify(method: &str, params: Value) {
+    write(&json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+}
+
+fn manifest() -> Value {
+    let methods: Vec<Value> = METHODS
+        .iter()
+        .map(|(name, usage, description)| json!({ "name": name, "usage": usage, "description": description }))
+        .collect();
+    json!({ "options": [], "rpcmethods": methods, "dynamic": true })
+}
+
+/// Points the flows at the node's RPC socket, unless another backend was chosen.
+fn init(ctx: &mut crate::LnurlClient, params: &Value) {
+    let config = &params["configuration"];
+    if let (Some(dir), Some(file)) = (config["lightning-dir"].as_str(), config["rpc-file"].as_str()) {
+        if let crate::backend::Config::Cln { socket } = &mut ctx.node {
+            *socket = std::path::Path::new(dir).join(file).to_string_lossy().into_owned();
+            log::debug!("using node RPC socket {}", socket);
+        }
+    }
+}
+
+/// A parameter given by name (object) or position (array), as a string.
+fn param(params: &Value, name: &str, index: usize) -> Option<String> {
+    let value = match params {
+        Value::Object(map) => map.get(name),
+        Value::Array(list) => list.get(index),
+        _ => None,
+    }?;
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+fn required(params: &Value, name: &str, index: usize) -> Result<String, (i64, String)> {
+    param(params, name, index).ok_or((INVALID_PARAMS, format!("Missing parameter '{}'", name)))
+}
+
+fn url_param(params: &Value) -> Result<crate::lnurl::Target, (i64, String)> {
+    let url = required(params, "url", 0)?;
+    crate::parse_target(&url).map_err(|e| (INVALID_PARAMS, format!("{:#}", e)))
+}
+
+fn amount_param(params: &Value) -> Result<crate::amount::AmountArg, (i64, String)> {
+    required(params, "amount_msat", 1)?
+        .parse()
+        .map_err(|e: anyhow::Error| (INVALID_PARAMS, format!("{:#}", e)))
+}
+
+/// A parameter that is on when given as `true`.
+fn flag(params: &Value, name: &str, index: usize) -> bool {
+    param(params, name, index).as_deref() == Some("true")
+}
+
+fn pay_args(params: &Value) -> crate::PayArgs {
+    crate::PayArgs {
+        comment: param(params, "comment", 2),
+        payer_name: param(params, "payer_name", 3),
+        payer_email: param(params, "payer_email", 4),
+        payer_identifier: param(params, "payer_identifier", 5),
+        payer_pubkey: flag(params, "payer_pubkey", 6),
+        payer_auth: flag(params, "payer_auth", 7),
+        verify_timeout: param(params, "verify_timeout", 8)
+            .and_then(|t| t.parse().ok())
+            .unwrap_or(60),
+    }
+}
+
+/// Runs a plugin RPC method and returns its result: what the flow returned,
+/// if anything, and the operations it recorded in the history.
+fn call(ctx: &crate::LnurlClient, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+    let started = chrono::Utc::now().to_rfc3339();
+    let result = match method {
+        "lnurlwithdraw" => {
+            let url = url_param(params)?;
+            crate::withdraw_request(ctx, &url, amount_param(params)?, param(params, "description", 2)).map(|w| json!(w))
+        }
+        "lnurlpay" => {
+            let url = url_param(params)?;
+            crate::pay_request(ctx, &url, amount_param(params)?, &pay_args(params)).map(|p| json!(p))
+        }
+        "lnurlchannel" => crate::channel_request(ctx, &url_param(params)?).map(|c| json!(c)),
+        "lnurlauth" => crate::auth_request(ctx, &url_param(params)?).map(|()| Value::Null),
+        _ => return Err((INVALID_PARAMS, format!("Unknown method '{}'", method))),
+    };
+    let result = result.map_err(|e| (FLOW_FAILED, format!("{:#}", e)))?;
+    let operations: Vec<_> = crate::history::since(&started)
+        .into_iter()
+        .map(|mut op| {
+            op.exchanges.clear();
+            op
+        })
+        .collect();
+    let mut reply = json!({ "status": "OK", "operations": operations });
+    if !result.is_null() {
+        reply["result"] = result;
+    }
+    Ok(reply)
+}
+
+/// Serves lightningd until it closes stdin.
+pub fn run(ctx: &mut crate::LnurlClient) -> Result<()> {
+    let stdin = std::io::stdin();
+    for message in serde_json::Deserializer::from_reader(stdin.lock()).into_iter::<Value>() {
+        let message = message.context("Invalid JSON from lightningd")?;
+        let Some(method) = message["method"].as_str() else {
+            continue;
+        };
+        let result = match method {
+            "getmanifest" => Ok(manifest()),
+            "init" => {
+                init(ctx, &message["params"]);
+                Ok(json!({}))
+            }
+            _ => call(ctx, method, &message["params"]),
+        };
+        // Messages without an id are notifications and get no reply.
+        let Some(id) = message.get("id") else {
+            continue;
+        };
+        match result {
+            Ok(result) => write(&json!({ "jsonrpc": "2.0", "id": id, "result": result })),
+            Err((code, error)) => write(&json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": error },
+            })),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+//! Spinners on stderr for the steps that can take a while: HTTP requests,
+//! connecting to a peer, paying, waiting for a confirmation.
+//!
+//! Off unless the CLI turns them on (stdout is a terminal and `--json` is
+//! not set). One spinner shows at a time; console log lines are printed
+//! around it, so they don't get mixed up.
+
+use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static ACTIVE: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+pub fn enable(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Shown until dropped.
+pub struct Spinner(Option<ProgressBar>);
+
+/// Starts a spinner with `message`, replacing the one showing.
+pub fn spinner(message: impl Into<String>) -> Spinner {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Spinner(None);
+    }
+    let bar = ProgressBar::new_spinner();
+    bar.set_style(ProgressStyle::with_template("{spinner} {msg} ({elapsed})").unwrap_or_else(|_| ProgressStyle::default_spinner()));
+    bar.set_message(message.into());
+    bar.enable_steady_tick(Duration::from_millis(100));
+    if let Ok(mut active) = ACTIVE.lock() {
+        if let Some(previous) = active.replace(bar.clone()) {
+            previous.finish_and_clear();
+        }
+    }
+    Spinner(Some(bar))
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        let Some(bar) = self.0.take() else { return };
+        bar.finish_and_clear();
+        if let Ok(mut active) = ACTIVE.lock() {
+            if active.as_ref().is_some_and(|a| a.is_finished()) {
+                *active = None;
+            }
+        }
+    }
+}
+
+/// Runs `print` with the spinner out of the way.
+pub fn suspend<R>(print: impl FnOnce() -> R) -> R {
+    let active = ACTIVE.lock().ok().and_then(|active| active.clone());
+    match active {
+        Some(bar) => bar.suspend(print),
+        None => print(),
+    }
+}
@@ -0,0 +1,272 @@
+//! Fiat exchange rates for displaying and entering amounts.
+//!
+//! A `RateSource` fetches the price of one BTC in a fiat currency. Sources
+//! are selected with a spec string (`coingecko`, `kraken`, `static:<price>`,
+//! or `url:<url>|<json path>`), and results are cached in the data directory
+//! so repeated commands don't hit the provider every time.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Provider of BTC prices.
+pub trait RateSource: Send {
+    /// Stable identifier, used as cache key.
+    fn name(&self) -> String;
+    /// Price of 1 BTC in `currency` (ISO code, lowercase).
+    fn fetch(&self, agent: &ureq::Agent, currency: &str) -> Result<f64>;
+}
+
+/// CoinGecko simple price API.
+pub struct Coingecko;
+
+impl RateSource for Coingecko {
+    fn name(&self) -> String {
+        "coingecko".to_string()
+    }
+
+    fn fetch(&self, agent: &ureq::Agent, currency: &str) -> Result<f64> {
+        let url = format!(
+            "https://api.coingecko.com/api/v3/simple/price?ids=bitcoin&vs_currencies={}",
+            currency
+        );
+        let body: Value = agent.get(&url).call()?.into_json()?;
+        number_at(&body, &format!("$.bitcoin.{}", currency))
+    }
+}
+
+/// Kraken public ticker (last trade price).
+pub struct Kraken;
+
+impl RateSource for Kraken {
+    fn name(&self) -> String {
+        "kraken".to_string()
+    }
+
+    fn fetch(&self, agent: &ureq::Agent, currency: &str) -> Result<f64> {
+        let url = format!(
+            "https://api.kraken.com/0/public/Ticker?pair=XBT{}",
+            currency.to_uppercase()
+        );
+        let body: Value = agent.get(&url).call()?.into_json()?;
+        if let Some(err) = body["error"].as_array().and_then(|e| e.first()) {
+            return Err(anyhow!("Kraken: {}", err));
+        }
+        // The result key is Kraken's pair name (e.g. XXBTZUSD), take the only entry.
+        let ticker = body["result"]
+            .as_object()
+            .and_then(|r| r.values().next())
+            .ok_or_else(|| anyhow!("Kraken: empty ticker result"))?;
+        number_at(ticker, "$.c[0]")
+    }
+}
+
+/// Fixed price, for offline use and tests.
+pub struct StaticRate(pub f64);
+
+impl RateSource for StaticRate {
+    fn name(&self) -> String {
+        format!("static:{}", self.0)
+    }
+
+    fn fetch(&self, _agent: &ureq::Agent, _currency: &str) -> Result<f64> {
+        Ok(self.0)
+    }
+}
+
+/// Any JSON endpoint: `{currency}` / `{CURRENCY}` in the URL are replaced,
+/// and the price is read at a JSON path such as `$.data.rates.USD`.
+pub struct CustomUrl {
+    pub url: String,
+    pub path: String,
+}
+
+impl RateSource for CustomUrl {
+    fn name(&self) -> String {
+        format!("url:{}|{}", self.url, self.path)
+    }
+
+    fn fetch(&self, agent: &ureq::Agent, currency: &str) -> Result<f64> {
+        let url = self
+            .url
+            .replace("{currency}", currency)
+            .replace("{CURRENCY}", &currency.to_uppercase());
+        let path = self
+            .path
+            .replace("{currency}", currency)
+            .replace("{CURRENCY}", &currency.to_uppercase());
+        let body: Value = agent.get(&url).call()?.into_json()?;
+        number_at(&body, &path)
+    }
+}
+
+/// Parses a source spec: `coingecko`, `kraken`, `static:<price>`, `url:<url>|<path>`.
+pub fn source_from_spec(spec: &str) -> Result<Box<dyn RateSource>> {
+    match spec {
+        "coingecko" => return Ok(Box::new(Coingecko)),
+        "kraken" => return Ok(Box::new(Kraken)),
+        _ => {}
+    }
+    if let Some(price) = spec.strip_prefix("static:") {
+        let price = price
+            .parse::<f64>()
+            .with_context(|| format!("Invalid static rate '{}'", price))?;
+        return Ok(Box::new(StaticRate(price)));
+    }
+    if let Some(rest) = spec.strip_prefix("url:") {
+        let (url, path) = rest
+            .rsplit_once('|')
+            .ok_or_else(|| anyhow!("Custom rate source must be url:<url>|<json path>"))?;
+        return Ok(Box::new(CustomUrl {
+            url: url.to_string(),
+            path: path.to_string(),
+        }));
+    }
+    Err(anyhow!(
+        "Unknown rate source '{}' (expected coingecko, kraken, static:<price> or url:<url>|<path>)",
+        spec
+    ))
+}
+
+/// Reads a number (JSON number or numeric string) at a simple JSON path:
+/// `$`, `.key`, `['key']` and `[index]` segments.
+fn number_at(root: &Value, path: &str) -> Result<f64> {
+    let mut node = root;
+    let mut rest = path.strip_prefix('$').unwrap_or(path);
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix("['") {
+            let end = r.find("']").ok_or_else(|| anyhow!("Unterminated ['key'] in {}", path))?;
+            node = &node[&r[..end]];
+            rest = &r[end + 2..];
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let end = r.find(']').ok_or_else(|| anyhow!("Unterminated [index] in {}", path))?;
+            let index = r[..end]
+                .parse::<usize>()
+                .with_context(|| format!("Invalid index in {}", path))?;
+            node = &node[index];
+            rest = &r[end + 1..];
+        } else if let Some(r) = rest.strip_prefix('.') {
+            let end = r.find(['.', '[']).unwrap_or(r.len());
+            node = &node[&r[..end]];
+            rest = &r[end..];
+        } else {
+            return Err(anyhow!("Invalid JSON path {}", path));
+        }
+    }
+    let value = match node {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse::<f64>().ok(),
+        _ => None,
+    };
+    value.ok_or_else(|| anyhow!("No numeric value at {}", path))
+}
+
+// -----------------------------------------------------------------------------
+// Cached rates
+// -----------------------------------------------------------------------------
+
+/// A BTC price and when it was fetched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rate {
+    pub currency: String,
+    pub price_per_btc: f64,
+    /// Unix time (seconds) the price was fetched.
+    pub fetched_at: i64,
+    pub source: String,
+}
+
+impl Rate {
+    pub fn age(&self) -> Duration {
+        let secs = chrono::Utc::now().timestamp() - self.fetched_at;
+        Duration::from_secs(secs.max(0) as u64)
+    }
+
+    /// Converts msat to the fiat currency.
+    pub fn msat_to_fiat(&self, msat: u64) -> f64 {
+        msat as f64 / 100_000_000_000.0 * self.price_per_btc
+    }
+}
+
+/// Rate source plus cache policy.
+pub struct RateProvider {
+    pub source: Box<dyn RateSource>,
+    /// Cached rates younger than this are used without fetching.
+    pub cache_ttl: Duration,
+    /// Rates older than this are refused, even as a fallback when fetching fails.
+    pub max_age: Duration,
+    /// Fiat amounts (`5usd`) are only converted with rates younger than this.
+    pub amount_max_age: Duration,
+}
+
+fn cache_path() -> std::path::PathBuf {
+    crate::get_data_dir().join("rates.json")
+}
+
+fn load_cache() -> BTreeMap<String, Rate> {
+    std::fs::read_to_string(cache_path())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn save_cache(cache: &BTreeMap<String, Rate>) -> Result<()> {
+    let path = cache_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(cache)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+impl RateProvider {
+    /// Returns the price of 1 BTC in `currency`: from cache if fresh,
+    /// otherwise fetched. If fetching fails, a cached rate within `max_age`
+    /// is used with a warning.
+    pub fn get(&self, agent: &ureq::Agent, currency: &str) -> Result<Rate> {
+        let currency = currency.to_lowercase();
+        let key = format!("{}/{}", self.source.name(), currency);
+        let mut cache = load_cache();
+        let cached = cache.get(&key).cloned();
+        if let Some(rate) = &cached {
+            if rate.age() <= self.cache_ttl {
+                return Ok(rate.clone());
+            }
+        }
+
+        match self.source.fetch(agent, &currency) {
+            Ok(price) if price > 0.0 => {
+                let rate = Rate {
+                    currency: currency.clone(),
+                    price_per_btc: price,
+                    fetched_at: chrono::Utc::now().timestamp(),
+                    source: self.source.name(),
+                };
+                cache.insert(key, rate.clone());
+                if let Err(e) = save_cache(&cache) {
+                    log::warn!("failed to cache exchange rate: {}", e);
+                }
+                Ok(rate)
+            }
+            Ok(price) => Err(anyhow!("Rate source {} returned invalid price {}", self.source.name(), price)),
+            Err(e) => match cached {
+                Some(rate) if rate.age() <= self.max_age => {
+                    log::warn!(
+                        "failed to fetch {} rate ({}), using cached rate from {}s ago",
+                        currency.to_uppercase(),
+                        e,
+                        rate.age().as_secs()
+                    );
+                    Ok(rate)
+                }
+                _ => Err(e.context(format!(
+                    "Failed to fetch {} rate from {} and no cached rate younger than {}s",
+                    currency.to_uppercase(),
+                    self.source.name(),
+                    self.max_age.as_secs()
+                ))),
+            },
+        }
+    }
+}
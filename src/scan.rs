@@ -0,0 +1,51 @@
+//! QR codes read from image files (`scan`, `--from-image`).
+//!
+//! Screenshots of a service's QR code are PNG or JPEG files holding a bech32
+//! LNURL, a LUD-17 URI or a lightning address. Unified BIP-21 codes
+//! (`bitcoin:<address>?lightning=<lnurl>`) are common too; their `lightning`
+//! parameter is what the flows want.
+
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+use url::Url;
+
+/// The text of the first QR code found in the image at `path`.
+pub fn decode_image(path: &Path) -> Result<String> {
+    let image = image::open(path)
+        .with_context(|| format!("Failed to read the image {}", path.display()))?
+        .to_luma8();
+    let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(image.width() as usize, image.height() as usize, |x, y| {
+        image.get_pixel(x as u32, y as u32).0[0]
+    });
+    let grids = prepared.detect_grids();
+    let mut last_err = None;
+    for grid in grids {
+        match grid.decode() {
+            Ok((_, content)) => return Ok(content),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(match last_err {
+        Some(e) => anyhow!("The QR code in {} can't be read: {}", path.display(), e),
+        None => anyhow!("No QR code found in {}", path.display()),
+    })
+}
+
+/// The target in a QR code's text: the `lightning` parameter of a BIP-21
+/// URI, otherwise the text itself.
+pub fn target_text(content: &str) -> String {
+    let content = content.trim();
+    if content.len() > 8 && content[..8].eq_ignore_ascii_case("bitcoin:") {
+        if let Ok(uri) = Url::parse(content) {
+            if let Some((_, lightning)) = uri.query_pairs().find(|(k, _)| k.eq_ignore_ascii_case("lightning")) {
+                return lightning.into_owned();
+            }
+        }
+    }
+    content.to_string()
+}
+
+/// The target in the QR code of the image at `path`, for `parse_target`.
+pub fn target_from_image(path: &Path) -> Result<String> {
+    decode_image(path).map(|content| target_text(&content))
+}
@@ -0,0 +1,69 @@
+//! Optional signatures on LNURL responses.
+//!
+//! A service can sign each response body and send the signature in the
+//! `X-LNURL-Signature` header: an ECDSA/secp256k1 signature (64-byte compact
+//! or DER, hex encoded) over the SHA-256 of the exact body bytes. The key can
+//! be the service's node key or any key agreed out of band. When a key is
+//! pinned for a host, every response from that host must carry a valid
+//! signature; responses from other hosts are not affected.
+
+use anyhow::{anyhow, Context, Result};
+use secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1};
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+
+/// Response header carrying the signature.
+pub const SIGNATURE_HEADER: &str = "X-LNURL-Signature";
+
+/// A host and the public key its responses must be signed with.
+#[derive(Debug, Clone)]
+pub struct PinnedKey {
+    host: String,
+    pubkey: PublicKey,
+}
+
+impl FromStr for PinnedKey {
+    type Err = anyhow::Error;
+
+    /// Parses `host=pubkey` (pubkey as 33-byte compressed hex).
+    fn from_str(s: &str) -> Result<Self> {
+        let (host, key) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid pinned key '{}', expected host=pubkey", s))?;
+        let pubkey = PublicKey::from_str(key.trim())
+            .with_context(|| format!("Invalid public key in pinned key '{}'", s))?;
+        Ok(Self {
+            host: host.trim().to_ascii_lowercase(),
+            pubkey,
+        })
+    }
+}
+
+/// Whether `host` has a pinned key.
+pub fn is_pinned(pins: &[PinnedKey], host: &str) -> bool {
+    pins.iter().any(|p| p.host.eq_ignore_ascii_case(host))
+}
+
+/// Checks `signature` (the header value) over `body` against the key pinned
+/// for `host`. Hosts without a pinned key pass without a signature.
+pub fn verify(pins: &[PinnedKey], host: &str, body: &[u8], signature: Option<&str>) -> Result<()> {
+    let Some(pin) = pins.iter().find(|p| p.host.eq_ignore_ascii_case(host)) else {
+        return Ok(());
+    };
+    let signature = signature
+        .ok_or_else(|| anyhow!("{} has a pinned key but sent no {} header", host, SIGNATURE_HEADER))?;
+    let bytes = hex::decode(signature.trim())
+        .with_context(|| format!("{} header from {} is not hex", SIGNATURE_HEADER, host))?;
+    let mut sig = if bytes.len() == 64 {
+        Signature::from_compact(&bytes)
+    } else {
+        Signature::from_der(&bytes)
+    }
+    .with_context(|| format!("Malformed {} header from {}", SIGNATURE_HEADER, host))?;
+    // libsecp256k1 only accepts low-S signatures; many signers don't normalize.
+    sig.normalize_s();
+    let digest: [u8; 32] = Sha256::digest(body).into();
+    Secp256k1::verification_only()
+        .verify_ecdsa(&Message::from_digest(digest), &sig, &pin.pubkey)
+        .map_err(|_| anyhow!("Response from {} is not signed by its pinned key {}", host, pin.pubkey))
+}
@@ -0,0 +1,268 @@
+//! Trust-on-first-use for TLS certificates of LNURL services.
+//!
+//! Certificates are still validated against the web PKI. On top of that, the
+//! SHA-256 of each host's public key (SubjectPublicKeyInfo, as `--pin-sha256`
+//! takes it) is stored the first time the host is contacted
+//! (`known_hosts.json` in the data directory), and a prominent warning is
+//! logged whenever a later connection presents another key. Renewals that
+//! keep the key pass unnoticed. A new key is remembered as pending and only
+//! replaces the stored one when the user accepts it, so a MITM can't make the
+//! warning go away by being seen once. Every agent of a process shares one
+//! copy of the store.
+//!
+//! The same verifier applies the other certificate settings: CAs trusted
+//! besides the web PKI (`--cacert`), e.g. the private CA of a self-hosted
+//! LSP, and public keys a host's certificate must have (`--pin-sha256`).
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use rustls::pki_types::pem::PemObject;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// Key recorded for a host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnownHost {
+    /// `sha256//` followed by the base64 SHA-256 of the public key.
+    pub key: String,
+    pub first_seen: String,
+    /// Another key the host presented since, until accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending: Option<String>,
+}
+
+pub fn known_hosts_path() -> std::path::PathBuf {
+    crate::get_data_dir().join("known_hosts.json")
+}
+
+/// Loads the store (empty if it doesn't exist yet).
+pub fn load() -> BTreeMap<String, KnownHost> {
+    std::fs::read_to_string(known_hosts_path())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(hosts: &BTreeMap<String, KnownHost>) -> Result<()> {
+    let path = known_hosts_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(hosts)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// The store the verifiers of this process share, loaded on first use.
+static HOSTS: Mutex<Option<BTreeMap<String, KnownHost>>> = Mutex::new(None);
+
+/// Records `known` for `host`, in the shared store and on disk. The file is
+/// read again first, so entries other processes wrote meanwhile are kept.
+fn remember(hosts: &mut BTreeMap<String, KnownHost>, host: &str, known: KnownHost) {
+    hosts.insert(host.to_string(), known.clone());
+    let mut on_disk = load();
+    on_disk.insert(host.to_string(), known);
+    if let Err(e) = save(&on_disk) {
+        log::warn!("failed to save known hosts: {:#}", e);
+    }
+}
+
+/// A public key hash as `sha256//<base64>`.
+fn show_key(sha256: &[u8; 32]) -> String {
+    format!("sha256//{}", base64::engine::general_purpose::STANDARD.encode(sha256))
+}
+
+/// A host and the SHA-256 of a public key its certificate must have.
+#[derive(Debug, Clone)]
+pub struct CertPin {
+    host: String,
+    sha256: [u8; 32],
+}
+
+impl FromStr for CertPin {
+    type Err = anyhow::Error;
+
+    /// Parses `host=hash`, the hash of the certificate's SubjectPublicKeyInfo
+    /// in base64 (as curl's `sha256//...`, which is also accepted) or hex.
+    fn from_str(s: &str) -> Result<Self> {
+        let (host, hash) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid certificate pin '{}', expected host=sha256", s))?;
+        let hash = hash.trim();
+        let hash = hash.strip_prefix("sha256//").unwrap_or(hash);
+        let bytes = match hex::decode(hash) {
+            Ok(bytes) => bytes,
+            Err(_) => base64::engine::general_purpose::STANDARD
+                .decode(hash)
+                .with_context(|| format!("Invalid hash in certificate pin '{}': neither base64 nor hex", s))?,
+        };
+        let sha256 = bytes
+            .try_into()
+            .map_err(|_| anyhow!("Invalid hash in certificate pin '{}': not 32 bytes", s))?;
+        Ok(Self { host: host.trim().to_ascii_lowercase(), sha256 })
+    }
+}
+
+/// The SHA-256 of the public key (SubjectPublicKeyInfo) in `cert`, which is
+/// what `--pin-sha256` pins.
+fn spki_sha256(cert: &CertificateDer<'_>) -> Result<[u8; 32]> {
+    let cert = webpki::EndEntityCert::try_from(cert).map_err(|e| anyhow!("Unreadable certificate: {:?}", e))?;
+    Ok(Sha256::digest(cert.subject_public_key_info().as_ref()).into())
+}
+
+/// Certificate verifier that validates against the roots, then checks the
+/// pins and the TOFU store.
+#[derive(Debug)]
+struct TofuVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pins: Vec<CertPin>,
+    /// Check the shared store (off with `--no-tls-tofu`).
+    tofu: bool,
+}
+
+impl TofuVerifier {
+    /// Refuses a certificate without the key pinned for `host`.
+    fn check_pins(&self, host: &str, cert: &CertificateDer<'_>) -> Result<()> {
+        let pins: Vec<&CertPin> = self.pins.iter().filter(|pin| pin.host.eq_ignore_ascii_case(host)).collect();
+        if pins.is_empty() {
+            return Ok(());
+        }
+        let seen = spki_sha256(cert)?;
+        if !pins.iter().any(|pin| pin.sha256 == seen) {
+            return Err(anyhow!(
+                "The certificate of {} doesn't have a pinned public key (--pin-sha256); it has {}",
+                host,
+                show_key(&seen)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fails only when the change warning is configured to abort.
+    fn check(&self, host: &str, cert: &CertificateDer<'_>) -> Result<()> {
+        if !self.tofu {
+            return Ok(());
+        }
+        let seen = show_key(&spki_sha256(cert)?);
+        let mut store = HOSTS.lock().unwrap();
+        let hosts = store.get_or_insert_with(load);
+        match hosts.get(host).cloned() {
+            Some(known) if known.key == seen => {}
+            Some(known) => {
+                log::warn!("==============================================================");
+                crate::warnings::raise(
+                    "certificate-changed",
+                    crate::warnings::Severity::High,
+                    format!(
+                        "TLS KEY FOR {} HAS CHANGED: known since {}: {}, presented now: {}",
+                        host, known.first_seen, known.key, seen
+                    ),
+                )?;
+                log::warn!("This may be a renewal with a new key or a man-in-the-middle.");
+                log::warn!("Verify out of band, then run `lnurl-client known-hosts accept {}`.", host);
+                log::warn!("==============================================================");
+                if known.pending.as_deref() != Some(seen.as_str()) {
+                    remember(hosts, host, KnownHost { pending: Some(seen), ..known });
+                }
+            }
+            None => {
+                log::info!("New TLS key for {}: {} (trusted on first use)", host, seen);
+                let known = KnownHost {
+                    key: seen,
+                    first_seen: chrono::Utc::now().to_rfc3339(),
+                    pending: None,
+                };
+                remember(hosts, host, known);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ServerCertVerifier for TofuVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self
+            .inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        let host = server_name.to_str();
+        self.check_pins(&host, end_entity)
+            .and_then(|()| self.check(&host, end_entity))
+            .map_err(|e| rustls::Error::General(e.to_string()))?;
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Reads the certificates of a PEM file.
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow!("Failed to read certificates from {}: {}", path.display(), e))?;
+    if certs.is_empty() {
+        return Err(anyhow!("No PEM certificate in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+/// TLS configuration for the LNURL agent: web PKI roots and the CAs in
+/// `ca_files`, then the `pins`, then the TOFU check if `tofu` is set.
+pub fn client_config(ca_files: &[PathBuf], pins: &[CertPin], tofu: bool) -> Result<Arc<rustls::ClientConfig>> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    for path in ca_files {
+        for cert in load_certs(path)? {
+            roots.add(cert).with_context(|| format!("Invalid CA certificate in {}", path.display()))?;
+        }
+    }
+    let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+        .build()
+        .context("Failed to build certificate verifier")?;
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .context("Failed to configure TLS")?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(TofuVerifier {
+            inner,
+            pins: pins.to_vec(),
+            tofu,
+        }))
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
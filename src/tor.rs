@@ -0,0 +1,116 @@
+//! The embedded Tor client (`--tor builtin`, feature `tor`), so that
+//! `.onion` services can be reached without a Tor daemon.
+//!
+//! arti runs on its own thread behind a SOCKS5 port on 127.0.0.1, which the
+//! HTTP agent then uses as its proxy, as it would a daemon's. Its state and
+//! directory cache are kept in `tor/` in the data directory, which makes
+//! later starts much faster than the first.
+
+use anyhow::{anyhow, bail, Result};
+use arti_client::config::TorClientConfigBuilder;
+use arti_client::{DataStream, TorClient};
+use std::future::Future;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// How long connecting to the Tor network may take; arti would otherwise
+/// keep trying without a network.
+const BOOTSTRAP_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// SOCKS5 reply: succeeded, bound to 0.0.0.0:0.
+const SUCCEEDED: [u8; 10] = [5, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+/// SOCKS5 reply: host unreachable.
+const UNREACHABLE: [u8; 10] = [5, 4, 0, 1, 0, 0, 0, 0, 0, 0];
+/// SOCKS5 reply: command not supported.
+const UNSUPPORTED: [u8; 10] = [5, 7, 0, 1, 0, 0, 0, 0, 0, 0];
+
+/// Connects to the Tor network with its files under `dir` and returns the
+/// `socks5h://` URL of the local port that forwards through it.
+pub fn start(dir: &Path) -> Result<String> {
+    let config = TorClientConfigBuilder::from_directories(dir.join("state"), dir.join("cache"))
+        .build()
+        .map_err(|e| anyhow!("Invalid Tor configuration: {}", e))?;
+    log::info!("Connecting to the Tor network...");
+    let (started, ready) = std::sync::mpsc::channel::<Result<SocketAddr>>();
+    std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Runtime::new() {
+            Ok(runtime) => runtime,
+            Err(e) => return drop(started.send(Err(e.into()))),
+        };
+        runtime.block_on(async move {
+            let bootstrapped = async {
+                let client = tokio::time::timeout(BOOTSTRAP_TIMEOUT, TorClient::create_bootstrapped(config))
+                    .await
+                    .map_err(|_| anyhow!("Could not connect to the Tor network within {}s", BOOTSTRAP_TIMEOUT.as_secs()))??;
+                let listener = TcpListener::bind("127.0.0.1:0").await?;
+                anyhow::Ok((client, listener))
+            };
+            let (client, listener) = match bootstrapped.await {
+                Ok(ready) => ready,
+                Err(e) => return drop(started.send(Err(e))),
+            };
+            let _ = started.send(listener.local_addr().map_err(Into::into));
+            // Until the process exits.
+            loop {
+                let Ok((stream, _)) = listener.accept().await else { continue };
+                let client = client.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = forward(stream, |host, port| async move { client.connect((host, port)).await }).await {
+                        log::debug!("Tor connection failed: {:#}", e);
+                    }
+                });
+            }
+        });
+    });
+    let addr = ready.recv().map_err(|_| anyhow!("The Tor client stopped while starting"))??;
+    log::debug!("Tor SOCKS port on {}", addr);
+    Ok(format!("socks5h://{}", addr))
+}
+
+/// Serves one SOCKS5 CONNECT (without authentication) on `stream`, with
+/// `connect` opening the Tor stream to the host and port asked for.
+async fn forward<F>(mut stream: TcpStream, connect: impl FnOnce(String, u16) -> F) -> Result<()>
+where
+    F: Future<Output = Result<DataStream, arti_client::Error>>,
+{
+    let mut greeting = [0u8; 2];
+    stream.read_exact(&mut greeting).await?;
+    let mut methods = vec![0u8; greeting[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    stream.write_all(&[5, 0]).await?;
+
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request).await?;
+    if request[1] != 1 {
+        stream.write_all(&UNSUPPORTED).await?;
+        bail!("SOCKS command {} is not CONNECT", request[1]);
+    }
+    let host = match request[3] {
+        1 => Ipv4Addr::from(stream.read_u32().await?).to_string(),
+        3 => {
+            let mut name = vec![0u8; stream.read_u8().await? as usize];
+            stream.read_exact(&mut name).await?;
+            String::from_utf8(name)?
+        }
+        4 => Ipv6Addr::from(stream.read_u128().await?).to_string(),
+        kind => {
+            stream.write_all(&UNSUPPORTED).await?;
+            bail!("SOCKS address type {} is not supported", kind);
+        }
+    };
+    let port = stream.read_u16().await?;
+
+    let mut tor = match connect(host.clone(), port).await {
+        Ok(tor) => tor,
+        Err(e) => {
+            stream.write_all(&UNREACHABLE).await?;
+            return Err(anyhow!("{}:{}: {}", host, port, e));
+        }
+    };
+    stream.write_all(&SUCCEEDED).await?;
+    tokio::io::copy_bidirectional(&mut stream, &mut tor).await?;
+    Ok(())
+}
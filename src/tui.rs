@@ -0,0 +1,396 @@
+//! Terminal interface (`tui`) for daily use.
+//!
+//! Two tabs: the saved endpoints (the config file's `[servers]` and the
+//! reusable pay links) and the operation history, newest first. `w`, `p` and
+//! `c` open a withdraw, pay or channel form on the selected endpoint; the
+//! flow runs in the background and its log lines show in the status pane as
+//! they come. Submitting the form is the confirmation, so the client doesn't
+//! prompt here.
+
+use crate::amount::{self, AmountArg};
+use crate::{config, history, logging, parse_target, pay_links_list, progress};
+use crate::{ChannelOutcome, LnurlClient, LnurlError, PayArgs};
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Flex, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Clear, List, ListState, Paragraph, Row, Table, TableState, Tabs, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread::Scope;
+use std::time::Duration;
+
+/// Status lines kept for the status pane.
+const STATUS_LINES: usize = 500;
+
+struct Endpoint {
+    name: String,
+    url: String,
+}
+
+#[derive(Clone, Copy)]
+enum Flow {
+    Withdraw,
+    Pay,
+    Channel,
+}
+
+impl Flow {
+    fn title(self) -> &'static str {
+        match self {
+            Flow::Withdraw => "Withdraw",
+            Flow::Pay => "Pay",
+            Flow::Channel => "Request a channel",
+        }
+    }
+
+    /// Form fields, the target first.
+    fn fields(self) -> &'static [&'static str] {
+        match self {
+            Flow::Withdraw => &["Target", "Amount (e.g. 25000sat, or %)", "Description"],
+            Flow::Pay => &["Target", "Amount (e.g. 25000sat, or %)", "Comment"],
+            Flow::Channel => &["Target"],
+        }
+    }
+}
+
+struct Form {
+    flow: Flow,
+    values: Vec<String>,
+    focus: usize,
+}
+
+enum Mode {
+    Browse,
+    Form(Form),
+    /// A flow is running; its summary line comes on the receiver.
+    Running(Receiver<Result<String, LnurlError>>),
+}
+
+struct App<'s, 'e> {
+    client: &'s Mutex<&'e mut LnurlClient>,
+    /// Where the flows run, so that they can borrow the client.
+    scope: &'s Scope<'s, 'e>,
+    tab: usize,
+    endpoints: Vec<Endpoint>,
+    endpoint: ListState,
+    operations: Vec<history::Operation>,
+    operation: TableState,
+    mode: Mode,
+    status: Vec<String>,
+    log: Receiver<String>,
+}
+
+/// Runs the interface until the user quits. Log lines go to the status pane
+/// meanwhile, not to the console.
+pub fn run(client: &mut LnurlClient) -> Result<()> {
+    let prompt = std::mem::replace(&mut client.prompt, false);
+    progress::enable(false);
+    let (sender, log) = mpsc::channel();
+    logging::capture(Some(sender));
+    let shared = Mutex::new(&mut *client);
+    let result = std::thread::scope(|scope| {
+        let mut app = App::new(&shared, scope, log);
+        let mut terminal = ratatui::try_init()?;
+        let result = app.run(&mut terminal);
+        ratatui::restore();
+        result
+    });
+    logging::capture(None);
+    client.prompt = prompt;
+    result
+}
+
+fn load_endpoints() -> Vec<Endpoint> {
+    let servers = config::servers().into_iter().map(|(name, url)| Endpoint { name, url });
+    let links = pay_links_list().into_iter().map(|(url, link)| Endpoint {
+        name: link.description.unwrap_or_else(|| "pay link".to_string()),
+        url,
+    });
+    servers.chain(links).collect()
+}
+
+fn load_operations() -> Vec<history::Operation> {
+    let mut operations = history::load().unwrap_or_else(|e| {
+        log::warn!("cannot read the history: {:#}", e);
+        Vec::new()
+    });
+    operations.reverse();
+    operations
+}
+
+impl<'s, 'e> App<'s, 'e> {
+    fn new(client: &'s Mutex<&'e mut LnurlClient>, scope: &'s Scope<'s, 'e>, log: Receiver<String>) -> Self {
+        let endpoints = load_endpoints();
+        let operations = load_operations();
+        let mut endpoint = ListState::default();
+        endpoint.select((!endpoints.is_empty()).then_some(0));
+        let mut operation = TableState::default();
+        operation.select((!operations.is_empty()).then_some(0));
+        App {
+            client,
+            scope,
+            tab: 0,
+            endpoints,
+            endpoint,
+            operations,
+            operation,
+            mode: Mode::Browse,
+            status: Vec::new(),
+            log,
+        }
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            self.collect();
+            terminal.draw(|frame| self.draw(frame))?;
+            if !event::poll(Duration::from_millis(100))? {
+                continue;
+            }
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn push_status(&mut self, line: String) {
+        self.status.push(line);
+        if self.status.len() > STATUS_LINES {
+            self.status.drain(..self.status.len() - STATUS_LINES);
+        }
+    }
+
+    /// Takes in the log lines and the outcome of the running flow.
+    fn collect(&mut self) {
+        while let Ok(line) = self.log.try_recv() {
+            self.push_status(line);
+        }
+        let Mode::Running(done) = &self.mode else { return };
+        let outcome = match done.try_recv() {
+            Ok(outcome) => outcome,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => Err(LnurlError::invalid("The flow stopped without an outcome")),
+        };
+        self.mode = Mode::Browse;
+        match outcome {
+            Ok(summary) => self.push_status(summary),
+            Err(e) => self.push_status(format!("Error: {}", e)),
+        }
+        self.operations = load_operations();
+        self.operation.select((!self.operations.is_empty()).then_some(0));
+    }
+
+    /// Handles a key press; `false` to quit.
+    fn key(&mut self, code: KeyCode) -> bool {
+        match &mut self.mode {
+            Mode::Running(_) => {}
+            Mode::Form(form) => match code {
+                KeyCode::Esc => self.mode = Mode::Browse,
+                KeyCode::Tab | KeyCode::Down => form.focus = (form.focus + 1) % form.values.len(),
+                KeyCode::BackTab | KeyCode::Up => form.focus = (form.focus + form.values.len() - 1) % form.values.len(),
+                KeyCode::Enter if form.focus + 1 < form.values.len() => form.focus += 1,
+                KeyCode::Enter => self.submit(),
+                KeyCode::Backspace => {
+                    form.values[form.focus].pop();
+                }
+                KeyCode::Char(c) => form.values[form.focus].push(c),
+                _ => {}
+            },
+            Mode::Browse => match code {
+                KeyCode::Char('q') | KeyCode::Esc => return false,
+                KeyCode::Tab | KeyCode::BackTab => self.tab = 1 - self.tab,
+                KeyCode::Up => self.select(-1),
+                KeyCode::Down => self.select(1),
+                KeyCode::Char('w') => self.open_form(Flow::Withdraw),
+                KeyCode::Char('p') => self.open_form(Flow::Pay),
+                KeyCode::Char('c') => self.open_form(Flow::Channel),
+                KeyCode::Char('r') => {
+                    self.endpoints = load_endpoints();
+                    self.operations = load_operations();
+                }
+                _ => {}
+            },
+        }
+        true
+    }
+
+    fn select(&mut self, step: isize) {
+        let (len, selected) = match self.tab {
+            0 => (self.endpoints.len(), self.endpoint.selected()),
+            _ => (self.operations.len(), self.operation.selected()),
+        };
+        if len == 0 {
+            return;
+        }
+        let next = Some(selected.unwrap_or(0).saturating_add_signed(step).min(len - 1));
+        match self.tab {
+            0 => self.endpoint.select(next),
+            _ => self.operation.select(next),
+        }
+    }
+
+    /// The form for `flow`, with the target of the selected endpoint or
+    /// operation filled in.
+    fn open_form(&mut self, flow: Flow) {
+        let target = match self.tab {
+            0 => self.endpoint.selected().and_then(|i| self.endpoints.get(i)).map(|e| e.url.clone()),
+            _ => self.operation.selected().and_then(|i| self.operations.get(i)).map(|op| op.url.clone()),
+        };
+        let mut values = vec![String::new(); flow.fields().len()];
+        values[0] = target.unwrap_or_default();
+        self.mode = Mode::Form(Form { flow, values, focus: 0 });
+    }
+
+    /// Checks the form and starts its flow; a field that doesn't parse
+    /// leaves the form open.
+    fn submit(&mut self) {
+        let Mode::Form(form) = &self.mode else { return };
+        let flow = form.flow;
+        let values = form.values.clone();
+        let input = values[0].trim();
+        let url = match parse_target(config::server(input).unwrap_or(input)) {
+            Ok(url) => url,
+            Err(e) => return self.push_status(format!("Error: {:#}", e)),
+        };
+        let amount = match values.get(1).map(|s| s.trim()) {
+            Some("") if matches!(flow, Flow::Withdraw) => Some(AmountArg::Percent(100.0)),
+            Some(amount) => match amount.parse::<AmountArg>() {
+                Ok(amount) => Some(amount),
+                Err(e) => return self.push_status(format!("Error: {:#}", e)),
+            },
+            None => None,
+        };
+        let text = values.get(2).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+
+        self.push_status(format!("{}: {}", flow.title(), url.url));
+        let client = self.client;
+        let (sender, done) = mpsc::channel();
+        self.scope.spawn(move || {
+            let client = client.lock().unwrap_or_else(|e| e.into_inner());
+            let outcome = match (flow, amount) {
+                (Flow::Withdraw, Some(amount)) => client.withdraw_request(&url, amount, text).map(|withdrawal| {
+                    format!(
+                        "Withdrawal accepted for {}{}",
+                        client.fmt_msat(withdrawal.amount_msat),
+                        withdrawal.label.map(|label| format!(" (invoice {})", label)).unwrap_or_default()
+                    )
+                }),
+                (Flow::Pay, Some(amount)) => {
+                    let extra = PayArgs { comment: text, ..PayArgs::default() };
+                    client.pay_request(&url, amount, &extra).map(|payment| {
+                        format!(
+                            "Paid {}, preimage {}",
+                            client.fmt_msat(payment.amount_msat),
+                            payment.preimage.as_deref().unwrap_or("unknown")
+                        )
+                    })
+                }
+                (_, _) => client.channel_request(&url).map(|outcome| match outcome {
+                    ChannelOutcome::Opened(opened) => format!(
+                        "Channel opened with {}{}",
+                        opened.uri,
+                        opened.txid.map(|txid| format!(", transaction {}", txid)).unwrap_or_default()
+                    ),
+                    ChannelOutcome::Hosted(hosted) => {
+                        format!("Connected to the host {}; its secret is {}", hosted.uri, hosted.secret)
+                    }
+                }),
+            };
+            let _ = sender.send(outcome);
+        });
+        self.mode = Mode::Running(done);
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [tabs, main, status, help] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(5),
+            Constraint::Length(10),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let selected = Style::default().add_modifier(Modifier::REVERSED);
+
+        frame.render_widget(Tabs::new(["Endpoints", "History"]).select(self.tab).highlight_style(selected), tabs);
+
+        if self.tab == 0 {
+            let items = self.endpoints.iter().map(|e| format!("{:<20} {}", e.name, e.url));
+            let list = List::new(items)
+                .block(Block::default().borders(Borders::ALL).title("Endpoints"))
+                .highlight_style(selected);
+            frame.render_stateful_widget(list, main, &mut self.endpoint);
+        } else {
+            let rows = self.operations.iter().map(|op| {
+                Row::new([
+                    op.id.clone(),
+                    op.timestamp.chars().take(19).collect(),
+                    op.kind.clone(),
+                    op.amount_msat.map(amount::show).unwrap_or_else(|| "-".to_string()),
+                    op.status.clone(),
+                    op.domain(),
+                ])
+            });
+            let widths = [
+                Constraint::Length(8),
+                Constraint::Length(19),
+                Constraint::Length(14),
+                Constraint::Length(16),
+                Constraint::Length(8),
+                Constraint::Min(10),
+            ];
+            let table = Table::new(rows, widths)
+                .header(Row::new(["id", "time", "kind", "amount", "status", "domain"]).style(Style::default().add_modifier(Modifier::BOLD)))
+                .block(Block::default().borders(Borders::ALL).title("History"))
+                .row_highlight_style(selected);
+            frame.render_stateful_widget(table, main, &mut self.operation);
+        }
+
+        let title = match self.mode {
+            Mode::Running(_) => "Status (running)",
+            _ => "Status",
+        };
+        let shown = status.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = self.status.iter().skip(self.status.len().saturating_sub(shown)).map(|l| Line::raw(l.as_str())).collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+            status,
+        );
+
+        let keys = match self.mode {
+            Mode::Browse => "↑/↓ select  Tab switch tab  w withdraw  p pay  c channel  r reload  q quit",
+            Mode::Form(_) => "Tab/↑/↓ field  Enter next/submit  Esc cancel",
+            Mode::Running(_) => "Running, see the status pane",
+        };
+        frame.render_widget(Paragraph::new(keys), help);
+
+        if let Mode::Form(form) = &self.mode {
+            let area = popup(main, 70, form.values.len() as u16 * 2 + 2);
+            let mut lines = Vec::new();
+            for (i, (label, value)) in form.flow.fields().iter().zip(&form.values).enumerate() {
+                let marker = if i == form.focus { "> " } else { "  " };
+                lines.push(Line::raw(format!("{}{}", marker, label)));
+                let line = Line::raw(format!("  {}{}", value, if i == form.focus { "_" } else { "" }));
+                lines.push(if i == form.focus { line.style(selected) } else { line });
+            }
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(lines)
+                    .wrap(Wrap { trim: false })
+                    .block(Block::default().borders(Borders::ALL).title(form.flow.title())),
+                area,
+            );
+        }
+    }
+}
+
+/// A `width` by `height` area centred in `area`.
+fn popup(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width)]).flex(Flex::Center).areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center).areas(area);
+    area
+}
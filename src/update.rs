@@ -0,0 +1,133 @@
+//! Self-update from the project's GitHub releases.
+//!
+//! A release provides one binary per platform named
+//! `lnurl-client-<arch>-<os>` (e.g. `lnurl-client-x86_64-linux`) and a
+//! `SHA256SUMS` file. The binary is only installed if its checksum matches.
+//! Both come from the same release, so this catches corrupted or truncated
+//! downloads but doesn't prove who built them: anyone who can publish a
+//! release can publish a matching checksum.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+
+/// Latest-release endpoint, overridable with `LNURL_UPDATE_URL` (mirrors, tests).
+const RELEASES_URL: &str = "https://api.github.com/repos/Ugo02/LN_Client/releases/latest";
+
+/// Largest binary we are willing to download.
+const MAX_BINARY_SIZE: u64 = 200 * 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Name of the release asset for the running platform.
+fn asset_name() -> String {
+    format!("lnurl-client-{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+/// Parses `v1.2.3` / `1.2.3` into comparable numeric parts.
+fn parse_version(v: &str) -> Vec<u64> {
+    v.trim_start_matches('v')
+        .split(['.', '-'])
+        .map_while(|p| p.parse::<u64>().ok())
+        .collect()
+}
+
+fn download(agent: &ureq::Agent, url: &str) -> Result<Vec<u8>> {
+    let resp = agent.get(url).call().with_context(|| format!("Failed to download {}", url))?;
+    let mut bytes = Vec::new();
+    resp.into_reader()
+        .take(MAX_BINARY_SIZE + 1)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Failed to download {}", url))?;
+    if bytes.len() as u64 > MAX_BINARY_SIZE {
+        return Err(anyhow!("{} is larger than {} MB; refusing to download it", url, MAX_BINARY_SIZE / (1024 * 1024)));
+    }
+    Ok(bytes)
+}
+
+/// Finds the expected checksum for `name` in a `sha256sum`-style file.
+fn expected_checksum(sums: &str, name: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let hash = parts.next()?;
+        let file = parts.next()?.trim_start_matches('*');
+        (file == name).then(|| hash.to_lowercase())
+    })
+}
+
+/// Checks for a newer release and, unless `check_only`, installs it over the
+/// running executable.
+pub fn self_update(agent: &ureq::Agent, check_only: bool) -> Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    let feed = std::env::var("LNURL_UPDATE_URL").unwrap_or_else(|_| RELEASES_URL.to_string());
+    log::info!("Checking {} for updates...", feed);
+
+    let release: Release = agent
+        .get(&feed)
+        .set("Accept", "application/vnd.github+json")
+        .call()
+        .context("Failed to fetch release information")?
+        .into_json()?;
+
+    if parse_version(&release.tag_name) <= parse_version(current) {
+        log::info!("lnurl-client {} is up to date (latest release: {}).", current, release.tag_name);
+        return Ok(());
+    }
+    log::info!("New version available: {} (current {}).", release.tag_name, current);
+
+    let name = asset_name();
+    let binary = release
+        .assets
+        .iter()
+        .find(|a| a.name == name)
+        .ok_or_else(|| anyhow!("Release {} has no binary for this platform ({})", release.tag_name, name))?;
+    let sums = release
+        .assets
+        .iter()
+        .find(|a| a.name == "SHA256SUMS")
+        .ok_or_else(|| anyhow!("Release {} has no SHA256SUMS file; refusing to update", release.tag_name))?;
+
+    if check_only {
+        log::info!("Run `lnurl-client self-update` to install {}.", binary.browser_download_url);
+        return Ok(());
+    }
+
+    let sums = String::from_utf8(download(agent, &sums.browser_download_url)?)
+        .context("SHA256SUMS is not valid UTF-8")?;
+    let expected = expected_checksum(&sums, &name)
+        .ok_or_else(|| anyhow!("SHA256SUMS has no entry for {}", name))?;
+
+    log::info!("Downloading {}...", binary.browser_download_url);
+    let bytes = download(agent, &binary.browser_download_url)?;
+    let actual = hex::encode(Sha256::digest(&bytes));
+    if actual != expected {
+        return Err(anyhow!("Checksum mismatch for {}: expected {}, got {}", name, expected, actual));
+    }
+    log::info!("Checksum verified ({}).", actual);
+
+    // Write next to the executable and rename over it, so a failed update
+    // never leaves a half-written binary in place.
+    let exe = std::env::current_exe().context("Cannot locate the running executable")?;
+    let tmp = exe.with_extension("new");
+    std::fs::write(&tmp, &bytes).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o755))?;
+    }
+    std::fs::rename(&tmp, &exe).with_context(|| format!("Failed to replace {}", exe.display()))?;
+
+    log::info!("Updated {} to {}.", exe.display(), release.tag_name);
+    Ok(())
+}
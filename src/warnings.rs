@@ -0,0 +1,89 @@
+//! Warnings about suspicious server behavior, kept apart from errors.
+//!
+//! Each warning has a stable code and a severity, so automation can decide to
+//! continue or abort: `--abort-on <severity>` turns warnings at or above that
+//! severity into errors at the point they are raised, and `--warnings json`
+//! prints them as one JSON object per line on stderr instead of text.
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        })
+    }
+}
+
+/// How warnings are printed on the console.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Warning {
+    /// Stable identifier, e.g. `http-callback`, `weak-k1`, `certificate-changed`.
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+}
+
+struct State {
+    format: Format,
+    abort_on: Option<Severity>,
+    raised: Vec<Warning>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    format: Format::Text,
+    abort_on: None,
+    raised: Vec::new(),
+});
+
+pub fn configure(format: Format, abort_on: Option<Severity>) {
+    let mut state = STATE.lock().unwrap();
+    state.format = format;
+    state.abort_on = abort_on;
+}
+
+/// Reports a warning. Fails when its severity reaches `--abort-on`.
+pub fn raise(code: &'static str, severity: Severity, message: impl Into<String>) -> Result<()> {
+    let warning = Warning {
+        code,
+        severity,
+        message: message.into(),
+    };
+    let mut state = STATE.lock().unwrap();
+    match state.format {
+        Format::Text => log::warn!("[{}] {}", code, warning.message),
+        Format::Json => {
+            eprintln!("{}", serde_json::to_string(&warning).unwrap_or_default());
+            log::debug!("warning [{}] {}", code, warning.message);
+        }
+    }
+    let abort = state.abort_on.is_some_and(|level| severity >= level);
+    state.raised.push(warning);
+    if abort {
+        return Err(anyhow!("Aborting on {} severity warning [{}]", severity, code));
+    }
+    Ok(())
+}
+
+/// Warnings raised so far.
+pub fn raised() -> Vec<Warning> {
+    STATE.lock().unwrap().raised.clone()
+}